@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use projectm::core::{ProjectM, MONO, STEREO};
+
+/// Most samples handed to projectM in one call; it rejects more than its buffer holds.
+const MAX_FEED_SAMPLES: usize = 512;
+/// Blocks the audio threads may queue ahead of the render thread. Beyond this
+/// the render thread has stalled and new audio is dropped rather than blocking.
+const QUEUE_DEPTH: usize = 256;
+/// Sample level counted as a calibration click.
+const CLICK_LEVEL: f32 = 0.5;
+/// How long the screen stays lit after a click.
+const FLASH_TIME: Duration = Duration::from_millis(80);
+
+/// Interleaved samples and the moment their first frame becomes audible.
+struct PcmBlock {
+    samples: Vec<f32>,
+    channels: usize,
+    at: Instant,
+}
+
+/// The audio-thread end of the bridge. Sending never blocks or locks.
+#[derive(Clone)]
+pub struct AudioSender {
+    sender: SyncSender<PcmBlock>,
+    /// Manual visual delay in microseconds, shared by every clone.
+    offset_us: Arc<AtomicI64>,
+    /// Output latency reported with the most recent block.
+    latency_us: Arc<AtomicU64>,
+}
+
+impl AudioSender {
+    /// Queues interleaved `samples` with any channel count that will be heard
+    /// `latency` from now; the visualizer gets them then, plus the manual offset.
+    pub fn send(&self, samples: Vec<f32>, channels: usize, latency: Duration) {
+        self.latency_us
+            .store(latency.as_micros() as u64, Ordering::Relaxed);
+        let now = Instant::now();
+        let offset = self.offset_us.load(Ordering::Relaxed);
+        let delay = latency.as_micros() as i64 + offset;
+        let at = if delay >= 0 {
+            now + Duration::from_micros(delay as u64)
+        } else {
+            now
+        };
+        let _ = self.sender.try_send(PcmBlock {
+            samples,
+            channels: channels.max(1),
+            at,
+        });
+    }
+
+    pub fn set_offset_ms(&self, offset_ms: f32) {
+        self.offset_us
+            .store((offset_ms * 1000.0) as i64, Ordering::Relaxed);
+    }
+
+    /// The output latency the audio device reported last.
+    pub fn latency(&self) -> Duration {
+        Duration::from_micros(self.latency_us.load(Ordering::Relaxed))
+    }
+}
+
+/// The render-thread end: the only place projectM gets its audio from, so it
+/// is only ever touched from the thread that renders it.
+pub struct AudioBridge {
+    receiver: Receiver<PcmBlock>,
+    /// Blocks received but not yet due.
+    pending: VecDeque<PcmBlock>,
+    /// When the last loud click was fed, for the latency calibration.
+    last_click: Option<Instant>,
+}
+
+impl AudioBridge {
+    pub fn new() -> (AudioSender, AudioBridge) {
+        let (sender, receiver) = sync_channel(QUEUE_DEPTH);
+        (
+            AudioSender {
+                sender,
+                offset_us: Arc::new(AtomicI64::new(0)),
+                latency_us: Arc::new(AtomicU64::new(0)),
+            },
+            AudioBridge {
+                receiver,
+                pending: VecDeque::new(),
+                last_click: None,
+            },
+        )
+    }
+
+    /// Hands projectM every block that is audible by now. Call right before rendering a frame.
+    pub fn feed(&mut self, projectm: &ProjectM) {
+        self.pending.extend(self.receiver.try_iter());
+        let now = Instant::now();
+        while self.pending.front().is_some_and(|block| block.at <= now) {
+            let block = self.pending.pop_front().unwrap();
+            if block.samples.iter().any(|sample| sample.abs() >= CLICK_LEVEL) {
+                self.last_click = Some(now);
+            }
+            feed_projectm(projectm, &block.samples, block.channels);
+        }
+    }
+
+    /// True shortly after a click reached projectM.
+    pub fn flashing(&self) -> bool {
+        self.last_click
+            .is_some_and(|click| click.elapsed() < FLASH_TIME)
+    }
+}
+
+/// Passes interleaved samples with any channel count to projectM, which takes
+/// mono or stereo: surround audio gives it the front left and right channels.
+pub fn feed_projectm(projectm: &ProjectM, samples: &[f32], channels: usize) {
+    let channels = channels.max(1);
+    let front: Vec<f32>;
+    let (samples, channels) = if channels <= 2 {
+        (samples, channels)
+    } else {
+        front = samples
+            .chunks_exact(channels)
+            .flat_map(|frame| [frame[0], frame[1]])
+            .collect();
+        (&front[..], 2)
+    };
+    let layout = if channels == 1 { MONO } else { STEREO };
+    for chunk in samples.chunks(MAX_FEED_SAMPLES / channels * channels) {
+        projectm.pcm_add_float(chunk, layout);
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use egui::{pos2, vec2, Color32, Rect, Sense, Stroke, Ui};
+use realfft::num_complex::Complex;
+use realfft::{RealFftPlanner, RealToComplex};
+
+/// Mono samples kept for the spectrum and the meter.
+const SCOPE_LEN: usize = 2048;
+/// Samples the VU meter averages over.
+const VU_LEN: usize = 1024;
+const BANDS: usize = 32;
+const MIN_FREQ: f32 = 40.0;
+const MAX_FREQ: f32 = 16000.0;
+/// Bottom of the spectrum and meter scales.
+const FLOOR_DB: f32 = -60.0;
+/// How fast bars fall back after a peak, in dB per second.
+const FALL_RATE: f32 = 40.0;
+
+/// The most recent audio handed to projectM, written by the output callback.
+pub struct AudioScope {
+    samples: Mutex<VecDeque<f32>>,
+    sample_rate: u32,
+}
+
+impl AudioScope {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            samples: Mutex::new(VecDeque::from(vec![0.0; SCOPE_LEN])),
+            sample_rate: sample_rate.max(1),
+        }
+    }
+
+    /// Appends interleaved `samples`, mixed down to mono.
+    pub fn push(&self, samples: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        let mut scope = self.samples.lock().unwrap();
+        scope.extend(
+            samples
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+        let excess = scope.len().saturating_sub(SCOPE_LEN);
+        scope.drain(..excess);
+    }
+
+    fn snapshot(&self) -> Vec<f32> {
+        self.samples.lock().unwrap().iter().copied().collect()
+    }
+}
+
+/// A mini spectrum and VU meter of what projectM is hearing.
+pub struct AudioMeter {
+    scope: Arc<AudioScope>,
+    fft: Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    /// Displayed level of each band in dB, falling at `FALL_RATE`.
+    bands: [f32; BANDS],
+    rms_db: f32,
+    peak_db: f32,
+}
+
+impl AudioMeter {
+    pub fn new(scope: Arc<AudioScope>) -> Self {
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(SCOPE_LEN);
+        let spectrum = fft.make_output_vec();
+        let window = (0..SCOPE_LEN)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / SCOPE_LEN as f32).cos())
+            .collect();
+
+        Self {
+            scope,
+            fft,
+            window,
+            spectrum,
+            bands: [FLOOR_DB; BANDS],
+            rms_db: FLOOR_DB,
+            peak_db: FLOOR_DB,
+        }
+    }
+
+    fn update(&mut self, dt: f32) {
+        let samples = self.scope.snapshot();
+        let fall = FALL_RATE * dt;
+        let to_db = |amplitude: f32| (20.0 * (amplitude + 1e-10).log10()).max(FLOOR_DB);
+
+        let recent = &samples[samples.len() - VU_LEN..];
+        let rms = (recent.iter().map(|s| s * s).sum::<f32>() / VU_LEN as f32).sqrt();
+        let peak = recent.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        self.rms_db = to_db(rms).max(self.rms_db - fall);
+        self.peak_db = to_db(peak).max(self.peak_db - fall);
+
+        let mut frame: Vec<f32> = samples
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, weight)| sample * weight)
+            .collect();
+        self.fft.process(&mut frame, &mut self.spectrum).unwrap();
+
+        // A full-scale sine peaks at N/4 after the Hann window.
+        let scale = 4.0 / SCOPE_LEN as f32;
+        let bin_hz = self.scope.sample_rate as f32 / SCOPE_LEN as f32;
+        let ratio = MAX_FREQ / MIN_FREQ;
+        for (band, level) in self.bands.iter_mut().enumerate() {
+            let low = MIN_FREQ * ratio.powf(band as f32 / BANDS as f32);
+            let high = MIN_FREQ * ratio.powf((band + 1) as f32 / BANDS as f32);
+            let first = ((low / bin_hz) as usize).min(self.spectrum.len() - 1);
+            let last = ((high / bin_hz) as usize).clamp(first + 1, self.spectrum.len());
+            let magnitude = self.spectrum[first..last]
+                .iter()
+                .fold(0.0f32, |max, bin| max.max(bin.norm()));
+            *level = to_db(magnitude * scale).max(*level - fall);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        self.update(ui.input(|input| input.unstable_dt).min(0.1));
+        let level = |db: f32| ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+
+        let (rect, _) = ui.allocate_exact_size(vec2(ui.available_width(), 48.0), Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+        let bar_width = rect.width() / BANDS as f32;
+        for (band, db) in self.bands.iter().enumerate() {
+            let left = rect.left() + band as f32 * bar_width;
+            let top = rect.bottom() - rect.height() * level(*db);
+            painter.rect_filled(
+                Rect::from_min_max(pos2(left + 1.0, top), pos2(left + bar_width - 1.0, rect.bottom())),
+                0.0,
+                ui.visuals().selection.bg_fill,
+            );
+        }
+
+        let (rect, response) = ui.allocate_exact_size(vec2(ui.available_width(), 8.0), Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+        let color = match self.rms_db {
+            db if db > -6.0 => Color32::from_rgb(220, 60, 60),
+            db if db > -18.0 => Color32::from_rgb(220, 200, 60),
+            _ => Color32::from_rgb(80, 200, 90),
+        };
+        let mut fill = rect;
+        fill.set_right(rect.left() + rect.width() * level(self.rms_db));
+        painter.rect_filled(fill, 2.0, color);
+        let peak_x = rect.left() + rect.width() * level(self.peak_db);
+        painter.line_segment(
+            [pos2(peak_x, rect.top()), pos2(peak_x, rect.bottom())],
+            Stroke::new(1.5, ui.visuals().strong_text_color()),
+        );
+        response.on_hover_text(if self.peak_db <= FLOOR_DB {
+            String::from("No signal reaching projectM")
+        } else {
+            format!("RMS {:.1} dB, peak {:.1} dB", self.rms_db, self.peak_db)
+        });
+    }
+}
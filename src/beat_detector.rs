@@ -0,0 +1,623 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use realfft::num_complex::Complex;
+use realfft::{RealFftPlanner, RealToComplex};
+
+/// Analysis frame and hop length in samples.
+const FRAME: usize = 1024;
+const HOP: usize = 512;
+/// Seconds of onset strength the adaptive threshold is taken over.
+const THRESHOLD_WINDOW: f64 = 1.5;
+/// Seconds of onset strength the tempo is estimated from.
+const TEMPO_WINDOW: f64 = 8.0;
+/// Seconds of history needed before a tempo is trusted.
+const MIN_TEMPO_HISTORY: f64 = 3.0;
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+/// Onsets closer together than this are treated as one.
+const MIN_ONSET_GAP: f64 = 0.05;
+/// How far from the predicted beat, as a fraction of the beat period, an
+/// onset may land and still count as that beat.
+const BEAT_TOLERANCE: f64 = 0.2;
+/// Beats keep coming on the tempo grid for this long after the last onset.
+const FLYWHEEL: f64 = 2.0;
+/// Beats per bar assumed when guessing downbeats.
+const BEATS_PER_BAR: usize = 4;
+/// Time constants of the short- and long-term loudness averages, in seconds.
+const SHORT_ENERGY: f64 = 0.4;
+const LONG_ENERGY: f64 = 6.0;
+/// Seconds between energy reports.
+const ENERGY_INTERVAL: f64 = 0.25;
+/// Blocks the audio threads may queue ahead of the detector thread before
+/// new audio is dropped rather than blocking.
+const WORKER_QUEUE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioEvent {
+    /// A sudden rise in spectral energy: a drum hit, a note, a chord change.
+    Onset { time: f64, strength: f32 },
+    /// A beat on the tracked tempo grid. `downbeat` marks the likely first beat of a bar.
+    Beat { time: f64, bpm: f32, downbeat: bool },
+    /// Short- and long-term loudness in dBFS; a big gap between them is a drop or a breakdown.
+    Energy { time: f64, short_db: f32, long_db: f32 },
+}
+
+/// Fans audio events out to every subscriber. Subscribers that drop their
+/// receiver are forgotten on the next publish.
+#[derive(Default)]
+pub struct AudioEvents {
+    subscribers: Mutex<Vec<Sender<AudioEvent>>>,
+    /// Set while a tapped tempo stands in for the detector's beats.
+    manual_beats: AtomicBool,
+}
+
+impl AudioEvents {
+    pub fn subscribe(&self) -> Receiver<AudioEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, event: AudioEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event).is_ok());
+    }
+
+    /// Publishes an event from a beat detector, unless it is a beat and
+    /// beats are being tapped by hand.
+    pub fn publish_detected(&self, event: AudioEvent) {
+        if matches!(event, AudioEvent::Beat { .. }) && self.manual_beats.load(Ordering::Relaxed) {
+            return;
+        }
+        self.publish(event);
+    }
+
+    pub fn set_manual_beats(&self, manual: bool) {
+        self.manual_beats.store(manual, Ordering::Relaxed);
+    }
+}
+
+/// Spectral-flux onset detection with autocorrelation tempo estimation and a
+/// beat tracker that locks onto onsets near the predicted beat.
+///
+/// Every event is stamped with media time, so feeding the same audio always
+/// gives the same events no matter how it is chunked.
+pub struct BeatDetector {
+    sample_rate: u32,
+    sensitivity: f32,
+    fft: Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,
+    frame: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    previous: Vec<f32>,
+    /// Mono samples not analyzed yet, starting at `input_time`.
+    input: Vec<f32>,
+    input_time: f64,
+    /// Onset strength per hop, oldest first.
+    flux: VecDeque<f32>,
+    hops_until_tempo: usize,
+    last_onset: Option<f64>,
+    bpm: Option<f32>,
+    last_beat: Option<f64>,
+    beat_count: usize,
+    /// Decaying onset strength per position in the bar; the strongest is taken as the downbeat.
+    bar_strength: [f32; BEATS_PER_BAR],
+    short_power: f32,
+    long_power: f32,
+    hops_until_energy: usize,
+}
+
+impl BeatDetector {
+    pub fn new(sample_rate: u32, sensitivity: f32) -> Self {
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(FRAME);
+        let spectrum = fft.make_output_vec();
+        let window = (0..FRAME)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FRAME as f32).cos())
+            .collect();
+
+        Self {
+            sample_rate: sample_rate.max(1),
+            sensitivity,
+            fft,
+            window,
+            frame: vec![0.0; FRAME],
+            previous: vec![0.0; spectrum.len()],
+            spectrum,
+            input: Vec::new(),
+            input_time: 0.0,
+            flux: VecDeque::new(),
+            hops_until_tempo: 0,
+            last_onset: None,
+            bpm: None,
+            last_beat: None,
+            beat_count: 0,
+            bar_strength: [0.0; BEATS_PER_BAR],
+            short_power: 0.0,
+            long_power: 0.0,
+            hops_until_energy: 0,
+        }
+    }
+
+    /// Higher values report quieter onsets; 1.0 is the default.
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity;
+    }
+
+    /// The current tempo estimate.
+    pub fn bpm(&self) -> Option<f32> {
+        self.bpm
+    }
+
+    /// Forgets everything heard so far, e.g. after a seek or a track change.
+    pub fn reset(&mut self) {
+        self.previous.iter_mut().for_each(|value| *value = 0.0);
+        self.input.clear();
+        self.flux.clear();
+        self.hops_until_tempo = 0;
+        self.last_onset = None;
+        self.bpm = None;
+        self.last_beat = None;
+        self.beat_count = 0;
+        self.bar_strength = [0.0; BEATS_PER_BAR];
+        self.short_power = 0.0;
+        self.long_power = 0.0;
+        self.hops_until_energy = 0;
+    }
+
+    /// Analyzes interleaved `samples` whose first frame plays at media `time`
+    /// and returns the events they complete.
+    pub fn process(&mut self, samples: &[f32], channels: usize, time: f64) -> Vec<AudioEvent> {
+        let channels = channels.max(1);
+        let expected = self.input_time + self.input.len() as f64 / self.sample_rate as f64;
+        if !self.input.is_empty() && (time - expected).abs() > 0.1 {
+            self.reset();
+        }
+        if self.input.is_empty() {
+            self.input_time = time;
+        }
+        self.input.extend(
+            samples
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+
+        let mut events = Vec::new();
+        while self.input.len() >= FRAME {
+            self.analyze_frame(&mut events);
+            self.input.drain(..HOP);
+            self.input_time += HOP as f64 / self.sample_rate as f64;
+        }
+        events
+    }
+
+    fn hop_secs(&self) -> f64 {
+        HOP as f64 / self.sample_rate as f64
+    }
+
+    fn analyze_frame(&mut self, events: &mut Vec<AudioEvent>) {
+        for (i, sample) in self.frame.iter_mut().enumerate() {
+            *sample = self.input[i] * self.window[i];
+        }
+        let power = self.input[FRAME - HOP..FRAME]
+            .iter()
+            .map(|sample| sample * sample)
+            .sum::<f32>()
+            / HOP as f32;
+        self.fft.process(&mut self.frame, &mut self.spectrum).unwrap();
+
+        // Log-compressed magnitudes make quiet and loud passages comparable.
+        let mut flux = 0.0;
+        for (bin, previous) in self.spectrum.iter().zip(self.previous.iter_mut()) {
+            let magnitude = (1.0 + 100.0 * bin.norm() / FRAME as f32).ln();
+            flux += (magnitude - *previous).max(0.0);
+            *previous = magnitude;
+        }
+        flux /= self.spectrum.len() as f32;
+
+        let hop = self.hop_secs();
+        let history = (TEMPO_WINDOW / hop) as usize;
+        self.flux.push_back(flux);
+        while self.flux.len() > history {
+            self.flux.pop_front();
+        }
+
+        // The newest audio in this frame is its last hop; date the frame by
+        // that hop's middle.
+        let now = self.input_time + (FRAME - HOP / 2) as f64 / self.sample_rate as f64;
+
+        if let Some(strength) = self.peak() {
+            let onset_time = now - hop;
+            if self
+                .last_onset
+                .is_none_or(|last| onset_time - last >= MIN_ONSET_GAP)
+            {
+                self.last_onset = Some(onset_time);
+                events.push(AudioEvent::Onset {
+                    time: onset_time,
+                    strength,
+                });
+                self.track_onset(onset_time, strength, events);
+            }
+        }
+        self.flywheel(now, events);
+        self.track_energy(now, power, events);
+
+        if self.hops_until_tempo == 0 {
+            self.estimate_tempo();
+            self.hops_until_tempo = (1.0 / hop) as usize;
+        } else {
+            self.hops_until_tempo -= 1;
+        }
+    }
+
+    /// The strength of the previous hop if it was a peak above the adaptive threshold.
+    fn peak(&self) -> Option<f32> {
+        let len = self.flux.len();
+        if len < 3 {
+            return None;
+        }
+        let (before, candidate, after) = (self.flux[len - 3], self.flux[len - 2], self.flux[len - 1]);
+        if candidate <= before || candidate < after {
+            return None;
+        }
+
+        let window = ((THRESHOLD_WINDOW / self.hop_secs()) as usize).min(len);
+        let recent = self.flux.iter().skip(len - window);
+        let mean = recent.clone().sum::<f32>() / window as f32;
+        let variance = recent.map(|value| (value - mean).powi(2)).sum::<f32>() / window as f32;
+        let threshold = mean + variance.sqrt() * 1.5 / self.sensitivity.max(0.05) + 0.002;
+
+        (candidate > threshold).then_some(candidate)
+    }
+
+    /// Autocorrelates the onset strength and picks the strongest period in
+    /// the allowed tempo range, leaning towards 120 BPM when in doubt.
+    fn estimate_tempo(&mut self) {
+        let hop = self.hop_secs();
+        if (self.flux.len() as f64) * hop < MIN_TEMPO_HISTORY {
+            return;
+        }
+        let mean = self.flux.iter().sum::<f32>() / self.flux.len() as f32;
+        let signal: Vec<f32> = self.flux.iter().map(|value| value - mean).collect();
+
+        let min_lag = (60.0 / MAX_BPM as f64 / hop).floor().max(1.0) as usize;
+        let max_lag = ((60.0 / MIN_BPM as f64 / hop).ceil() as usize).min(signal.len() / 2);
+        let mut best: Option<(f32, usize)> = None;
+        for lag in min_lag..=max_lag {
+            let correlation: f32 = signal[lag..]
+                .iter()
+                .zip(&signal)
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / (signal.len() - lag) as f32;
+            let bpm = 60.0 / (lag as f64 * hop) as f32;
+            let weight = (-0.5 * (bpm / 120.0).log2().powi(2)).exp();
+            let score = correlation * weight;
+            if score > 0.0 && best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, lag));
+            }
+        }
+
+        if let Some((_, lag)) = best {
+            // Refine the integer lag with a parabola through its neighbours.
+            let correlation = |lag: usize| -> f32 {
+                signal[lag..].iter().zip(&signal).map(|(a, b)| a * b).sum::<f32>()
+                    / (signal.len() - lag) as f32
+            };
+            let mut period = lag as f32;
+            if lag > min_lag && lag < max_lag {
+                let (left, center, right) = (correlation(lag - 1), correlation(lag), correlation(lag + 1));
+                let curvature = left - 2.0 * center + right;
+                if curvature < 0.0 {
+                    period += 0.5 * (left - right) / curvature;
+                }
+            }
+            self.bpm = Some(60.0 / (period * hop as f32));
+        }
+    }
+
+    fn beat_period(&self) -> Option<f64> {
+        self.bpm.map(|bpm| 60.0 / bpm as f64)
+    }
+
+    /// Locks the beat grid onto an onset close to where the next beat was due.
+    fn track_onset(&mut self, time: f64, strength: f32, events: &mut Vec<AudioEvent>) {
+        let Some(period) = self.beat_period() else {
+            return;
+        };
+        let on_beat = match self.last_beat {
+            None => true,
+            Some(last) => ((time - last) - period).abs() <= period * BEAT_TOLERANCE,
+        };
+        if on_beat {
+            self.emit_beat(time, strength, events);
+        }
+    }
+
+    fn emit_beat(&mut self, time: f64, strength: f32, events: &mut Vec<AudioEvent>) {
+        let Some(bpm) = self.bpm else {
+            return;
+        };
+        let position = self.beat_count % BEATS_PER_BAR;
+        for value in &mut self.bar_strength {
+            *value *= 0.9;
+        }
+        self.bar_strength[position] += strength;
+        let strongest = (0..BEATS_PER_BAR)
+            .max_by(|a, b| self.bar_strength[*a].total_cmp(&self.bar_strength[*b]))
+            .unwrap();
+
+        self.last_beat = Some(time);
+        self.beat_count += 1;
+        events.push(AudioEvent::Beat {
+            time,
+            bpm,
+            downbeat: position == strongest,
+        });
+    }
+
+    fn track_energy(&mut self, now: f64, power: f32, events: &mut Vec<AudioEvent>) {
+        let hop = self.hop_secs();
+        let short = 1.0 - (-hop / SHORT_ENERGY).exp() as f32;
+        let long = 1.0 - (-hop / LONG_ENERGY).exp() as f32;
+        self.short_power += (power - self.short_power) * short;
+        self.long_power += (power - self.long_power) * long;
+
+        if self.hops_until_energy == 0 {
+            let to_db = |power: f32| 10.0 * (power + 1e-10).log10();
+            events.push(AudioEvent::Energy {
+                time: now,
+                short_db: to_db(self.short_power),
+                long_db: to_db(self.long_power),
+            });
+            self.hops_until_energy = (ENERGY_INTERVAL / hop) as usize;
+        } else {
+            self.hops_until_energy -= 1;
+        }
+    }
+
+    /// Keeps beats coming on the grid through short gaps between onsets.
+    fn flywheel(&mut self, now: f64, events: &mut Vec<AudioEvent>) {
+        let (Some(period), Some(last)) = (self.beat_period(), self.last_beat) else {
+            return;
+        };
+        let due = last + period;
+        if now <= due + period * BEAT_TOLERANCE {
+            return;
+        }
+        if self.last_onset.is_some_and(|onset| now - onset <= FLYWHEEL) {
+            self.emit_beat(due, 0.0, events);
+        } else {
+            // Silence: re-lock on whatever comes next.
+            self.last_beat = None;
+        }
+    }
+}
+
+enum WorkerInput {
+    /// Interleaved samples whose first frame is frame `first_frame` of the stream.
+    Samples {
+        samples: Vec<f32>,
+        channels: usize,
+        first_frame: u64,
+    },
+    /// Starts over on a stream with this sample rate; `None` stops analyzing.
+    Restart(Option<u32>),
+    Sensitivity(f32),
+}
+
+/// The audio-thread end of a `BeatWorker`. Sending never blocks or locks.
+#[derive(Clone)]
+pub struct DetectorSender {
+    sender: SyncSender<WorkerInput>,
+}
+
+impl DetectorSender {
+    pub fn send(&self, samples: Vec<f32>, channels: usize, first_frame: u64) {
+        let _ = self.sender.try_send(WorkerInput::Samples {
+            samples,
+            channels,
+            first_frame,
+        });
+    }
+}
+
+/// Runs a `BeatDetector` on its own thread and publishes what it finds, so
+/// the realtime audio callbacks only hand samples over. The thread ends once
+/// the worker and all of its senders are dropped.
+pub struct BeatWorker {
+    sender: SyncSender<WorkerInput>,
+    /// The tempo estimate as `f32` bits; zero while there is none.
+    bpm: Arc<AtomicU32>,
+}
+
+impl BeatWorker {
+    pub fn spawn(events: Arc<AudioEvents>, sensitivity: f32) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(WORKER_QUEUE);
+        let bpm = Arc::new(AtomicU32::new(0));
+        let shared_bpm = bpm.clone();
+        thread::spawn(move || {
+            let mut sensitivity = sensitivity;
+            let mut detector: Option<BeatDetector> = None;
+            for input in receiver {
+                match input {
+                    WorkerInput::Samples {
+                        samples,
+                        channels,
+                        first_frame,
+                    } => {
+                        let Some(detector) = detector.as_mut() else {
+                            continue;
+                        };
+                        let time = first_frame as f64 / detector.sample_rate as f64;
+                        for event in detector.process(&samples, channels, time) {
+                            events.publish_detected(event);
+                        }
+                        let bits = detector.bpm().map_or(0, f32::to_bits);
+                        shared_bpm.store(bits, Ordering::Relaxed);
+                    }
+                    WorkerInput::Restart(sample_rate) => {
+                        detector = sample_rate.map(|rate| BeatDetector::new(rate, sensitivity));
+                        shared_bpm.store(0, Ordering::Relaxed);
+                    }
+                    WorkerInput::Sensitivity(value) => {
+                        sensitivity = value;
+                        if let Some(detector) = detector.as_mut() {
+                            detector.set_sensitivity(value);
+                        }
+                    }
+                }
+            }
+        });
+        Self { sender, bpm }
+    }
+
+    pub fn sender(&self) -> DetectorSender {
+        DetectorSender {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Forgets the current stream and analyzes the next one at `sample_rate`,
+    /// or nothing until the next restart when it is `None`. Samples sent
+    /// before this belong to the old stream.
+    pub fn restart(&self, sample_rate: Option<u32>) {
+        let _ = self.sender.send(WorkerInput::Restart(sample_rate));
+    }
+
+    pub fn set_sensitivity(&self, sensitivity: f32) {
+        let _ = self.sender.send(WorkerInput::Sensitivity(sensitivity));
+    }
+
+    /// The detector's latest tempo estimate.
+    pub fn bpm(&self) -> Option<f32> {
+        let bits = self.bpm.load(Ordering::Relaxed);
+        (bits != 0).then(|| f32::from_bits(bits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const RATE: u32 = 44100;
+
+    /// Mono clicks every `60 / bpm` seconds from time zero: short decaying
+    /// bursts of a 2 kHz tone.
+    fn click_track(bpm: f64, seconds: f64) -> Vec<f32> {
+        let period = 60.0 / bpm;
+        (0..(seconds * RATE as f64) as usize)
+            .map(|i| {
+                let time = i as f64 / RATE as f64;
+                let since = time % period;
+                if since < 0.03 {
+                    let tone = (2.0 * std::f64::consts::PI * 2000.0 * time).sin();
+                    (tone * (-since / 0.005).exp() * 0.8) as f32
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    fn detect(samples: &[f32], chunk: usize) -> (BeatDetector, Vec<AudioEvent>) {
+        let mut detector = BeatDetector::new(RATE, 1.0);
+        let mut events = Vec::new();
+        for (i, block) in samples.chunks(chunk).enumerate() {
+            let time = (i * chunk) as f64 / RATE as f64;
+            events.extend(detector.process(block, 1, time));
+        }
+        (detector, events)
+    }
+
+    fn beat_times(events: &[AudioEvent]) -> Vec<f64> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                AudioEvent::Beat { time, .. } => Some(*time),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_tempo_of_click_track() {
+        let (detector, events) = detect(&click_track(120.0, 16.0), 480);
+
+        let bpm = detector.bpm().unwrap();
+        assert!((bpm - 120.0).abs() < 2.0, "bpm {}", bpm);
+        assert!(events.iter().any(
+            |event| matches!(event, AudioEvent::Beat { bpm, .. } if (bpm - 120.0).abs() < 2.0)
+        ));
+    }
+
+    #[test]
+    fn beats_land_on_clicks() {
+        let (_, events) = detect(&click_track(120.0, 16.0), 480);
+        let beats = beat_times(&events);
+
+        // Tempo is only trusted after a few seconds of history.
+        assert!(beats.len() >= 20, "{} beats", beats.len());
+        for time in &beats {
+            let off = (time - (time / 0.5).round() * 0.5).abs();
+            assert!(off < 0.03, "beat at {:.3}s is {:.3}s off the clicks", time, off);
+        }
+        for pair in beats.windows(2) {
+            assert!((pair[1] - pair[0] - 0.5).abs() < 0.03, "beats {:?}", pair);
+        }
+    }
+
+    #[test]
+    fn same_events_however_chunked() {
+        let track = click_track(120.0, 8.0);
+        let (_, small) = detect(&track, 256);
+        let (_, large) = detect(&track, 4410);
+        assert_eq!(beat_times(&small), beat_times(&large));
+    }
+
+    #[test]
+    fn silence_has_no_beats() {
+        let (detector, events) = detect(&vec![0.0; 10 * RATE as usize], 512);
+
+        assert_eq!(detector.bpm(), None);
+        assert!(!events.iter().any(|event| matches!(
+            event,
+            AudioEvent::Onset { .. } | AudioEvent::Beat { .. }
+        )));
+    }
+
+    #[test]
+    fn worker_publishes_beats() {
+        let events = Arc::new(AudioEvents::default());
+        let received = events.subscribe();
+        let worker = BeatWorker::spawn(events.clone(), 1.0);
+        worker.restart(Some(RATE));
+
+        let sender = worker.sender();
+        let track = click_track(120.0, 8.0);
+        for (i, block) in track.chunks(4410).enumerate() {
+            sender.send(block.to_vec(), 1, (i * 4410) as u64);
+            // Leave room in the queue; a real callback comes every few milliseconds.
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let beat = std::iter::from_fn(|| received.recv_timeout(Duration::from_secs(5)).ok())
+            .find(|event| matches!(event, AudioEvent::Beat { .. }));
+        assert!(beat.is_some());
+        for _ in 0..100 {
+            if worker.bpm().is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!((worker.bpm().unwrap() - 120.0).abs() < 2.0);
+    }
+}
@@ -0,0 +1,167 @@
+//! Command-line client for the visualizer's remote-control socket.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use music_visualizer::paths;
+use serde_json::{json, Value};
+
+#[derive(Parser)]
+#[command(name = "aurora-ctl", about = "Control a running music visualizer")]
+struct Args {
+    /// Control socket; defaults to the one in the visualizer's config.
+    #[arg(long)]
+    socket: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the current state.
+    Status,
+    /// Print every state change until interrupted.
+    Watch,
+    NextPreset,
+    PrevPreset,
+    LoadPreset { preset: PathBuf },
+    /// Mark the current preset as a favorite.
+    Favorite {
+        /// Unmark it instead.
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Block the current preset and switch to the next.
+    Block,
+    /// Tap the beat.
+    Tap,
+    /// Add audio files to the queue.
+    QueueAdd { files: Vec<PathBuf> },
+    /// Play the queue entry at INDEX, or resume.
+    Play { index: Option<usize> },
+    Pause,
+    NextTrack,
+    PrevTrack,
+    /// Record the current track.
+    Record {
+        /// Render offline instead of recording in real time.
+        #[arg(long)]
+        offline: bool,
+    },
+    StopRecording,
+    /// Show custom text in an overlay element.
+    OverlayText {
+        text: String,
+        /// title, artist or url.
+        #[arg(long, default_value = "title")]
+        element: String,
+    },
+    /// Send a raw JSON command object.
+    Send { json: String },
+}
+
+/// The socket the visualizer listens on, going by `remote.socket_path` in its config.
+fn default_socket() -> PathBuf {
+    let configured = paths::config_dir()
+        .and_then(|dir| std::fs::read_to_string(dir.join(paths::CONFIG_FILE)).ok())
+        .and_then(|contents| contents.parse::<toml::Table>().ok())
+        .and_then(|config| {
+            let path = config.get("remote")?.get("socket_path")?.as_str()?;
+            Some(PathBuf::from(path))
+        })
+        .unwrap_or_default();
+    paths::control_socket(&configured)
+}
+
+/// The visualizer has its own working directory, so send absolute paths.
+fn absolute(path: PathBuf) -> PathBuf {
+    std::fs::canonicalize(&path).unwrap_or(path)
+}
+
+fn request(command: Command) -> Result<Option<Value>, String> {
+    Ok(Some(match command {
+        Command::Status => json!({ "command": "status" }),
+        Command::Watch => return Ok(None),
+        Command::NextPreset => json!({ "command": "next_preset" }),
+        Command::PrevPreset => json!({ "command": "previous_preset" }),
+        Command::LoadPreset { preset } => {
+            json!({ "command": "load_preset", "preset": absolute(preset) })
+        }
+        Command::Favorite { remove } => json!({ "command": "favorite", "favorite": !remove }),
+        Command::Block => json!({ "command": "block" }),
+        Command::Tap => json!({ "command": "tap" }),
+        Command::QueueAdd { files } => {
+            let files: Vec<PathBuf> = files.into_iter().map(absolute).collect();
+            json!({ "command": "queue_add", "files": files })
+        }
+        Command::Play { index } => json!({ "command": "play", "index": index }),
+        Command::Pause => json!({ "command": "pause" }),
+        Command::NextTrack => json!({ "command": "next_track" }),
+        Command::PrevTrack => json!({ "command": "previous_track" }),
+        Command::Record { offline } => json!({ "command": "start_recording", "offline": offline }),
+        Command::StopRecording => json!({ "command": "stop_recording" }),
+        Command::OverlayText { text, element } => {
+            json!({ "command": "set_overlay_text", "text": text, "element": element })
+        }
+        Command::Send { json } => {
+            let value: Value = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+            if !value.is_object() {
+                return Err(String::from("The command must be a JSON object"));
+            }
+            value
+        }
+    }))
+}
+
+#[cfg(unix)]
+fn run(args: Args) -> Result<(), String> {
+    use std::os::unix::net::UnixStream;
+
+    let socket = args.socket.unwrap_or_else(default_socket);
+    let mut stream = UnixStream::connect(&socket)
+        .map_err(|err| format!("Could not connect to {}: {}", socket.display(), err))?;
+    let request = request(args.command)?;
+    let reader = BufReader::new(stream.try_clone().map_err(|err| err.to_string())?);
+
+    let Some(mut request) = request else {
+        for line in reader.lines() {
+            println!("{}", line.map_err(|err| err.to_string())?);
+        }
+        return Ok(());
+    };
+    // Events may arrive before the reply; the id picks the reply out.
+    request["id"] = json!(std::process::id());
+    writeln!(stream, "{}", request).map_err(|err| err.to_string())?;
+    for line in reader.lines() {
+        let line = line.map_err(|err| err.to_string())?;
+        let Ok(response) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        if response.get("id") != Some(&request["id"]) {
+            continue;
+        }
+        println!("{}", line);
+        return match response["ok"].as_bool() {
+            Some(true) => Ok(()),
+            _ => Err(response["error"].as_str().unwrap_or("failed").to_string()),
+        };
+    }
+    Err(String::from("The visualizer closed the connection"))
+}
+
+#[cfg(not(unix))]
+fn run(_args: Args) -> Result<(), String> {
+    Err(String::from("aurora-ctl needs Unix domain sockets; use the HTTP API instead"))
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("aurora-ctl: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,7 +1,28 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use music_visualizer::paths::{config_dir, CONFIG_FILE};
+
+use crate::hotkeys::HotkeyConfig;
+use crate::live_input::InputConfig;
+use crate::midi::MidiConfig;
+use crate::normalization::NormalizationConfig;
+use crate::osc::OscConfig;
+use crate::output_profile::OutputProfile;
+use crate::output_stage::OutputConfig;
+use crate::overlay::OverlayConfig;
+use crate::preset_director::DirectorConfig;
+use crate::preset_rotation::RotationConfig;
+use crate::remote_control::RemoteConfig;
+use crate::subtitles::SubtitleConfig;
+#[cfg(feature = "transcription")]
+use crate::transcription::TranscriptionConfig;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct Config {
     pub width: u32,
     pub height: u32,
@@ -10,6 +31,24 @@ pub struct Config {
     pub frame_rate: u32,
     pub preset_duration: f64,
     pub beat_sensitivity: f32,
+    pub normalization: NormalizationConfig,
+    pub output: OutputConfig,
+    pub input: InputConfig,
+    /// Extra delay of the visuals in milliseconds per audio device, found with
+    /// the latency calibration. Negative values make them earlier.
+    pub av_offsets: BTreeMap<String, f32>,
+    /// Delay of the lyrics in milliseconds per audio file, for lyrics that
+    /// were timed against a different release.
+    pub lyric_offsets: BTreeMap<String, f32>,
+    pub director: DirectorConfig,
+    pub recording: RecordingConfig,
+    pub overlay: OverlayConfig,
+    pub hotkeys: HotkeyConfig,
+    pub remote: RemoteConfig,
+    pub osc: OscConfig,
+    pub midi: MidiConfig,
+    #[cfg(feature = "transcription")]
+    pub transcription: TranscriptionConfig,
 }
 
 impl Default for Config {
@@ -22,10 +61,136 @@ impl Default for Config {
             frame_rate: 60,
             preset_duration: 10.0,
             beat_sensitivity: 1.0,
+            normalization: NormalizationConfig::default(),
+            output: OutputConfig::default(),
+            input: InputConfig::default(),
+            av_offsets: BTreeMap::new(),
+            lyric_offsets: BTreeMap::new(),
+            director: DirectorConfig::default(),
+            recording: RecordingConfig::default(),
+            overlay: OverlayConfig::default(),
+            hotkeys: HotkeyConfig::default(),
+            remote: RemoteConfig::default(),
+            osc: OscConfig::default(),
+            midi: MidiConfig::default(),
+            #[cfg(feature = "transcription")]
+            transcription: TranscriptionConfig::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RecordingConfig {
+    /// Where finished videos are moved to once ffmpeg has exited cleanly.
+    pub output_dir: PathBuf,
+    /// Scratch directory the encoders write into while a render is running.
+    pub temp_dir: PathBuf,
+    /// Path to the ffmpeg executable.
+    pub ffmpeg_path: PathBuf,
+    /// Named output profiles, including the built-ins.
+    pub profiles: Vec<OutputProfile>,
+    /// Names of the profiles produced by the next render.
+    pub active_profiles: Vec<String>,
+    /// Which presets a render uses and when it switches between them.
+    pub rotation: RotationConfig,
+    /// Subtitles of the overlay text, for platforms that index them.
+    pub subtitles: SubtitleConfig,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        let output_dir = directories::UserDirs::new()
+            .and_then(|dirs| dirs.video_dir().map(|dir| dir.to_path_buf()))
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        Self {
+            output_dir,
+            temp_dir: std::env::temp_dir().join("aurora-visualizer-rs"),
+            ffmpeg_path: PathBuf::from("ffmpeg"),
+            profiles: OutputProfile::builtins(),
+            active_profiles: vec![OutputProfile::builtins()[0].name.clone()],
+            rotation: RotationConfig::default(),
+            subtitles: SubtitleConfig::default(),
+        }
+    }
+}
+
+impl RecordingConfig {
+    /// Re-adds any built-in profile missing from a saved config.
+    fn ensure_builtins(&mut self) {
+        for builtin in OutputProfile::builtins() {
+            if self.profile(&builtin.name).is_none() {
+                self.profiles.push(builtin);
+            }
         }
     }
+
+    pub fn profile(&self, name: &str) -> Option<&OutputProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// The profiles selected for the next render, in the order they were selected.
+    pub fn active(&self) -> Vec<OutputProfile> {
+        self.active_profiles
+            .iter()
+            .filter_map(|name| self.profile(name).cloned())
+            .collect()
+    }
 }
 
+/// Set when an invalid config file couldn't be moved aside, which saving would overwrite.
+static SAVING_DISABLED: AtomicBool = AtomicBool::new(false);
+
 pub fn load_config() -> Config {
-    Config::default()
+    let Some(path) = config_dir().map(|dir| dir.join(CONFIG_FILE)) else {
+        return Config::default();
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => match toml::from_str::<Config>(&contents) {
+            Ok(mut config) => {
+                config.recording.ensure_builtins();
+                config
+            }
+            Err(err) => {
+                // Moved aside so the next save doesn't overwrite the user's edits.
+                let invalid = path.with_extension("toml.invalid");
+                log::warn!(
+                    "Ignoring invalid config {}, moved to {}: {}",
+                    path.display(),
+                    invalid.display(),
+                    err
+                );
+                if let Err(err) = fs::rename(&path, &invalid) {
+                    log::error!("Could not move {} aside, not saving: {}", path.display(), err);
+                    SAVING_DISABLED.store(true, Ordering::Relaxed);
+                }
+                Config::default()
+            }
+        },
+        Err(_) => Config::default(),
+    }
+}
+
+pub fn save_config(config: &Config) {
+    let Some(dir) = config_dir() else {
+        return;
+    };
+    if SAVING_DISABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // Written next to the config and renamed over it, so a crash mid-write
+    // leaves the old file intact.
+    let temp = dir.join(format!("{}.tmp", CONFIG_FILE));
+    let result = fs::create_dir_all(&dir).and_then(|_| {
+        let contents = toml::to_string_pretty(config).map_err(std::io::Error::other)?;
+        fs::write(&temp, contents)?;
+        fs::rename(&temp, dir.join(CONFIG_FILE))
+    });
+
+    if let Err(err) = result {
+        log::error!("Failed to save config to {}: {}", dir.display(), err);
+    }
 }
@@ -0,0 +1,173 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HotkeyAction {
+    NextPreset,
+    PreviousPreset,
+    ToggleOverlay,
+    ToggleRecording,
+    OpenSettings,
+    VolumeUp,
+    VolumeDown,
+    ToggleMute,
+    ToggleFavorite,
+    /// Blacks out the screen while projectM keeps running underneath.
+    ToggleBlackout,
+    /// Taps the beat; see `TapTempo`.
+    TapTempo,
+    NudgeBeatEarlier,
+    NudgeBeatLater,
+    HalveTempo,
+    DoubleTempo,
+    /// Drops the tapped tempo and goes back to detected beats.
+    AutoTempo,
+}
+
+impl HotkeyAction {
+    pub const ALL: [HotkeyAction; 16] = [
+        HotkeyAction::NextPreset,
+        HotkeyAction::PreviousPreset,
+        HotkeyAction::ToggleOverlay,
+        HotkeyAction::ToggleRecording,
+        HotkeyAction::OpenSettings,
+        HotkeyAction::VolumeUp,
+        HotkeyAction::VolumeDown,
+        HotkeyAction::ToggleMute,
+        HotkeyAction::ToggleFavorite,
+        HotkeyAction::ToggleBlackout,
+        HotkeyAction::TapTempo,
+        HotkeyAction::NudgeBeatEarlier,
+        HotkeyAction::NudgeBeatLater,
+        HotkeyAction::HalveTempo,
+        HotkeyAction::DoubleTempo,
+        HotkeyAction::AutoTempo,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            HotkeyAction::NextPreset => "Next preset",
+            HotkeyAction::PreviousPreset => "Previous preset",
+            HotkeyAction::ToggleOverlay => "Show/hide overlay",
+            HotkeyAction::ToggleRecording => "Start/stop recording",
+            HotkeyAction::OpenSettings => "Advanced settings",
+            HotkeyAction::VolumeUp => "Volume up",
+            HotkeyAction::VolumeDown => "Volume down",
+            HotkeyAction::ToggleMute => "Mute/unmute",
+            HotkeyAction::ToggleFavorite => "Favorite/unfavorite preset",
+            HotkeyAction::ToggleBlackout => "Blackout",
+            HotkeyAction::TapTempo => "Tap tempo",
+            HotkeyAction::NudgeBeatEarlier => "Nudge beat earlier",
+            HotkeyAction::NudgeBeatLater => "Nudge beat later",
+            HotkeyAction::HalveTempo => "Half tempo",
+            HotkeyAction::DoubleTempo => "Double tempo",
+            HotkeyAction::AutoTempo => "Detect tempo again",
+        }
+    }
+}
+
+/// Key bound to each action, by egui key name (`"N"`, `"F2"`, `"Space"`...).
+/// An empty string leaves the action unbound.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct HotkeyConfig {
+    pub next_preset: String,
+    pub previous_preset: String,
+    pub toggle_overlay: String,
+    pub toggle_recording: String,
+    pub open_settings: String,
+    pub volume_up: String,
+    pub volume_down: String,
+    pub toggle_mute: String,
+    pub toggle_favorite: String,
+    pub toggle_blackout: String,
+    pub tap_tempo: String,
+    pub nudge_beat_earlier: String,
+    pub nudge_beat_later: String,
+    pub halve_tempo: String,
+    pub double_tempo: String,
+    pub auto_tempo: String,
+}
+
+impl Default for HotkeyConfig {
+    fn default() -> Self {
+        Self {
+            next_preset: String::from("N"),
+            previous_preset: String::from("P"),
+            toggle_overlay: String::from("O"),
+            toggle_recording: String::from("R"),
+            open_settings: String::from("F2"),
+            volume_up: String::from("Plus"),
+            volume_down: String::from("Minus"),
+            toggle_mute: String::from("M"),
+            toggle_favorite: String::from("F"),
+            toggle_blackout: String::from("B"),
+            tap_tempo: String::from("T"),
+            nudge_beat_earlier: String::from("Comma"),
+            nudge_beat_later: String::from("Period"),
+            halve_tempo: String::from("OpenBracket"),
+            double_tempo: String::from("CloseBracket"),
+            auto_tempo: String::from("Backspace"),
+        }
+    }
+}
+
+impl HotkeyConfig {
+    pub fn binding_mut(&mut self, action: HotkeyAction) -> &mut String {
+        match action {
+            HotkeyAction::NextPreset => &mut self.next_preset,
+            HotkeyAction::PreviousPreset => &mut self.previous_preset,
+            HotkeyAction::ToggleOverlay => &mut self.toggle_overlay,
+            HotkeyAction::ToggleRecording => &mut self.toggle_recording,
+            HotkeyAction::OpenSettings => &mut self.open_settings,
+            HotkeyAction::VolumeUp => &mut self.volume_up,
+            HotkeyAction::VolumeDown => &mut self.volume_down,
+            HotkeyAction::ToggleMute => &mut self.toggle_mute,
+            HotkeyAction::ToggleFavorite => &mut self.toggle_favorite,
+            HotkeyAction::ToggleBlackout => &mut self.toggle_blackout,
+            HotkeyAction::TapTempo => &mut self.tap_tempo,
+            HotkeyAction::NudgeBeatEarlier => &mut self.nudge_beat_earlier,
+            HotkeyAction::NudgeBeatLater => &mut self.nudge_beat_later,
+            HotkeyAction::HalveTempo => &mut self.halve_tempo,
+            HotkeyAction::DoubleTempo => &mut self.double_tempo,
+            HotkeyAction::AutoTempo => &mut self.auto_tempo,
+        }
+    }
+
+    pub fn binding(&self, action: HotkeyAction) -> &str {
+        match action {
+            HotkeyAction::NextPreset => &self.next_preset,
+            HotkeyAction::PreviousPreset => &self.previous_preset,
+            HotkeyAction::ToggleOverlay => &self.toggle_overlay,
+            HotkeyAction::ToggleRecording => &self.toggle_recording,
+            HotkeyAction::OpenSettings => &self.open_settings,
+            HotkeyAction::VolumeUp => &self.volume_up,
+            HotkeyAction::VolumeDown => &self.volume_down,
+            HotkeyAction::ToggleMute => &self.toggle_mute,
+            HotkeyAction::ToggleFavorite => &self.toggle_favorite,
+            HotkeyAction::ToggleBlackout => &self.toggle_blackout,
+            HotkeyAction::TapTempo => &self.tap_tempo,
+            HotkeyAction::NudgeBeatEarlier => &self.nudge_beat_earlier,
+            HotkeyAction::NudgeBeatLater => &self.nudge_beat_later,
+            HotkeyAction::HalveTempo => &self.halve_tempo,
+            HotkeyAction::DoubleTempo => &self.double_tempo,
+            HotkeyAction::AutoTempo => &self.auto_tempo,
+        }
+    }
+
+    /// Actions whose key went down this frame. Nothing fires while a text
+    /// field has focus, so typing an overlay text doesn't skip presets.
+    pub fn pressed(&self, ctx: &egui::Context) -> Vec<HotkeyAction> {
+        if ctx.wants_keyboard_input() {
+            return Vec::new();
+        }
+        HotkeyAction::ALL
+            .into_iter()
+            .filter(|action| {
+                egui::Key::from_name(self.binding(*action))
+                    .is_some_and(|key| ctx.input(|input| input.key_pressed(key)))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,116 @@
+use eframe::egui;
+
+use crate::config;
+use crate::main_app::MusicVisualizerApp;
+
+/// Range of the manual offset slider in milliseconds.
+const OFFSET_RANGE: std::ops::RangeInclusive<f32> = -200.0..=300.0;
+/// Captured audio reaches the visualizer as it is heard, so it can only be delayed.
+const INPUT_OFFSET_RANGE: std::ops::RangeInclusive<f32> = 0.0..=300.0;
+
+/// Options → Latency Calibration. Plays a click every second and flashes the
+/// visualizer when the click reaches projectM, so the offset can be adjusted
+/// until both line up. The offset is saved for the current audio device.
+pub struct LatencyCalibration {
+    is_open: bool,
+    offset_ms: f32,
+    clicking: bool,
+}
+
+impl LatencyCalibration {
+    pub fn new() -> Self {
+        Self {
+            is_open: false,
+            offset_ms: 0.0,
+            clicking: false,
+        }
+    }
+
+    pub fn open(&mut self, offset_ms: f32) {
+        if !self.is_open {
+            self.is_open = true;
+            self.offset_ms = offset_ms;
+            self.clicking = false;
+        }
+    }
+}
+
+pub fn show(ctx: &egui::Context, app: &mut MusicVisualizerApp) {
+    if !app.calibration.is_open {
+        app.visualizer.calibrating = false;
+        return;
+    }
+    app.visualizer.calibrating = true;
+
+    let key = app.playback.latency_key();
+    let live_input = key.starts_with("input:");
+    let calibration = &mut app.calibration;
+    let range = if live_input {
+        INPUT_OFFSET_RANGE
+    } else {
+        OFFSET_RANGE
+    };
+
+    let mut open = true;
+    let mut save = false;
+    let mut close = false;
+    egui::Window::new("Latency Calibration")
+        .open(&mut open)
+        .default_width(360.0)
+        .show(ctx, |ui| {
+            if live_input {
+                ui.label(
+                    "Play a metronome into the input. The visualizer flashes white on every \
+                     click it receives; move the slider until the flashes match the clicks.",
+                );
+            } else {
+                ui.label(
+                    "Start the clicks. The visualizer flashes white on every click it \
+                     receives; move the slider until the flashes match what you hear.",
+                );
+                if ui.button("Start Clicks").clicked() {
+                    app.playback.play_click_track();
+                    calibration.clicking = true;
+                }
+            }
+            ui.separator();
+            ui.label(format!("Device: {}", if key.is_empty() { "default" } else { &key }));
+            ui.label(format!(
+                "Measured output latency: {:.0} ms",
+                app.playback.output_latency().as_secs_f32() * 1000.0
+            ));
+            let slider = egui::Slider::new(&mut calibration.offset_ms, range)
+                .text("Visual offset")
+                .suffix(" ms");
+            if ui.add(slider).changed() {
+                app.playback.set_visual_offset(calibration.offset_ms);
+            }
+            if live_input {
+                ui.weak("Captured audio is visualized as it arrives, so the visuals can only be delayed.");
+            } else {
+                ui.weak(
+                    "Positive values delay the visuals. Negative values make them earlier, \
+                     by at most the measured output latency.",
+                );
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                save = ui.button("Save").clicked();
+                close = ui.button("Close").clicked();
+            });
+        });
+
+    if save {
+        app.config.av_offsets.insert(key, calibration.offset_ms);
+        config::save_config(&app.config);
+    }
+    if save || close || !open {
+        if calibration.clicking {
+            app.playback.stop_click_track();
+        }
+        calibration.is_open = false;
+        app.visualizer.calibrating = false;
+        // Puts back the saved offset if the slider was moved without saving.
+        app.apply_config();
+    }
+}
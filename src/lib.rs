@@ -0,0 +1,3 @@
+//! What the visualizer shares with `aurora-ctl`.
+
+pub mod paths;
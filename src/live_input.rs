@@ -0,0 +1,249 @@
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample, Stream, StreamConfig};
+use serde::{Deserialize, Serialize};
+
+use crate::audio_bridge::AudioSender;
+use crate::audio_meter::AudioScope;
+use crate::beat_detector::{AudioEvents, BeatWorker};
+use crate::normalization::Gains;
+
+/// How often a lost or missing input device is looked for again.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+/// Prefix of device names that are PulseAudio/PipeWire sources rather than cpal devices.
+const MONITOR_PREFIX: &str = "monitor:";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioSource {
+    /// Decoded files from the queue, played through the speakers.
+    Files,
+    /// Whatever arrives on an input device, visualized without playing it back.
+    Input,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct InputConfig {
+    pub source: AudioSource,
+    /// cpal input device name, `monitor:<source>` for a PulseAudio/PipeWire
+    /// monitor, or empty for the default input.
+    pub device: String,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            source: AudioSource::Files,
+            device: String::new(),
+        }
+    }
+}
+
+/// An input the user can pick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputDevice {
+    /// Stored in `InputConfig::device`.
+    pub name: String,
+    pub label: String,
+}
+
+/// cpal's input devices, followed by the monitor sources of the sound server,
+/// which capture what is playing on the system.
+pub fn input_devices() -> Vec<InputDevice> {
+    let mut devices = vec![InputDevice {
+        name: String::new(),
+        label: String::from("Default input"),
+    }];
+    if let Ok(inputs) = cpal::default_host().input_devices() {
+        devices.extend(inputs.filter_map(|device| device.name().ok()).map(|name| InputDevice {
+            label: name.clone(),
+            name,
+        }));
+    }
+    devices.extend(monitor_sources().into_iter().map(|source| InputDevice {
+        name: format!("{}{}", MONITOR_PREFIX, source),
+        label: format!("System audio: {}", source),
+    }));
+    devices
+}
+
+/// Monitor sources as listed by `pactl`, which PipeWire provides as well.
+fn monitor_sources() -> Vec<String> {
+    let Ok(output) = Command::new("pactl").args(["list", "short", "sources"]).output() else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .filter(|name| name.ends_with(".monitor"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Points the sound server's ALSA plugin at the monitor `config` names, if any.
+/// Runs at startup before any threads exist, since the environment can't be
+/// changed safely after that; picking another monitor takes a restart.
+pub fn select_monitor(config: &InputConfig) {
+    if let Some(source) = config.device.strip_prefix(MONITOR_PREFIX) {
+        std::env::set_var("PULSE_SOURCE", source);
+    }
+}
+
+/// What captured audio is handed to.
+#[derive(Clone)]
+pub struct InputSinks {
+    pub bridge: AudioSender,
+    pub events: Arc<AudioEvents>,
+    pub scope: Arc<AudioScope>,
+    pub gains: Arc<Mutex<Gains>>,
+}
+
+/// Captures an input device into projectM and the beat detector, and keeps
+/// trying to reconnect when the device goes away.
+pub struct LiveInput {
+    device: String,
+    sinks: InputSinks,
+    stream: Option<Stream>,
+    /// Set by the stream's error callback.
+    failed: Arc<AtomicBool>,
+    /// Beat detection on the captured samples.
+    beats: BeatWorker,
+    last_attempt: Instant,
+    pub status: String,
+}
+
+impl LiveInput {
+    pub fn new(device: &str, sinks: InputSinks, beat_sensitivity: f32) -> Self {
+        let mut input = Self {
+            device: device.to_string(),
+            beats: BeatWorker::spawn(sinks.events.clone(), beat_sensitivity),
+            sinks,
+            stream: None,
+            failed: Arc::new(AtomicBool::new(false)),
+            last_attempt: Instant::now(),
+            status: String::new(),
+        };
+        input.connect();
+        input
+    }
+
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    pub fn bpm(&self) -> Option<f32> {
+        self.beats.bpm()
+    }
+
+    pub fn set_beat_sensitivity(&mut self, sensitivity: f32) {
+        self.beats.set_sensitivity(sensitivity);
+    }
+
+    /// Drops a failed stream and reconnects once the device is back.
+    pub fn update(&mut self) {
+        if self.failed.swap(false, Ordering::Relaxed) {
+            self.stream = None;
+            self.status = String::from("Input device lost, reconnecting...");
+            log::warn!("Lost input device '{}'", self.device);
+        }
+        if self.stream.is_none() && self.last_attempt.elapsed() >= RECONNECT_INTERVAL {
+            self.connect();
+        }
+    }
+
+    fn connect(&mut self) {
+        self.last_attempt = Instant::now();
+        match self.open() {
+            Ok(stream) => {
+                self.stream = Some(stream);
+                self.status = String::new();
+            }
+            Err(err) => self.status = err,
+        }
+    }
+
+    fn open(&self) -> Result<Stream, String> {
+        let host = cpal::default_host();
+        let device = if let Some(source) = self.device.strip_prefix(MONITOR_PREFIX) {
+            // The sound server's ALSA plugin records from the source `select_monitor` named.
+            if std::env::var("PULSE_SOURCE").ok().as_deref() != Some(source) {
+                return Err(format!("Restart to capture from {}", source));
+            }
+            let device = host
+                .input_devices()
+                .map_err(|err| err.to_string())?
+                .find(|device| device.name().is_ok_and(|name| name == "pulse" || name == "pipewire"))
+                .ok_or_else(|| format!("No PulseAudio or PipeWire input to capture {} from", source))?;
+            Some(device)
+        } else if self.device.is_empty() {
+            host.default_input_device()
+        } else {
+            host.input_devices()
+                .map_err(|err| err.to_string())?
+                .find(|device| device.name().is_ok_and(|name| name == self.device))
+        }
+        .ok_or_else(|| format!("Input device '{}' not found", self.device))?;
+
+        let config = device
+            .default_input_config()
+            .map_err(|err| err.to_string())?;
+        self.beats.restart(Some(config.sample_rate().0));
+
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => self.build_stream::<f32>(&device, config.into()),
+            cpal::SampleFormat::I16 => self.build_stream::<i16>(&device, config.into()),
+            cpal::SampleFormat::U16 => self.build_stream::<u16>(&device, config.into()),
+            format => return Err(format!("Unsupported input sample format {}", format)),
+        }?;
+        stream.play().map_err(|err| err.to_string())?;
+        log::info!("Capturing from '{}'", self.device);
+        Ok(stream)
+    }
+
+    fn build_stream<T>(&self, device: &cpal::Device, config: StreamConfig) -> Result<Stream, String>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let sinks = self.sinks.clone();
+        let beats = self.beats.sender();
+        let failed = self.failed.clone();
+        let channels = config.channels as usize;
+        let mut captured: u64 = 0;
+
+        device
+            .build_input_stream(
+                &config,
+                move |data: &[T], _: &cpal::InputCallbackInfo| {
+                    let gain = sinks.gains.lock().unwrap().visual;
+                    let samples: Vec<f32> = data
+                        .iter()
+                        .map(|sample| sample.to_sample::<f32>() * gain)
+                        .collect();
+
+                    // The detector runs on its own thread, off this realtime one.
+                    beats.send(samples.clone(), channels, captured);
+                    captured += (samples.len() / channels.max(1)) as u64;
+
+                    sinks.scope.push(&samples, channels);
+                    // Captured audio is already being heard; only the manual offset applies.
+                    sinks.bridge.send(samples, channels, Duration::ZERO);
+                },
+                move |err| {
+                    log::error!("Input stream error: {}", err);
+                    failed.store(true, Ordering::Relaxed);
+                },
+                None,
+            )
+            .map_err(|err| err.to_string())
+    }
+}
@@ -0,0 +1,567 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::overlay::{Anchor, TextElementConfig, TextSource};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LyricsConfig {
+    pub enabled: bool,
+    /// The line being sung. Its text source is ignored.
+    pub current: TextElementConfig,
+    /// Color of the words of the current line that have been sung.
+    pub highlight_color: [u8; 4],
+    pub show_next_line: bool,
+    pub next: TextElementConfig,
+}
+
+impl Default for LyricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            current: TextElementConfig {
+                source: TextSource::Custom,
+                size: 72.0,
+                color: [255, 255, 255, 255],
+                anchor: Anchor::Bottom,
+                margin: [48.0, 330.0],
+                ..Default::default()
+            },
+            highlight_color: [255, 210, 60, 255],
+            show_next_line: true,
+            next: TextElementConfig {
+                source: TextSource::Custom,
+                size: 48.0,
+                color: [255, 255, 255, 160],
+                anchor: Anchor::Bottom,
+                margin: [48.0, 250.0],
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LyricWord {
+    /// Seconds into the track.
+    pub start: f64,
+    /// The word with the whitespace after it, so a line's words add up to its text.
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LyricLine {
+    pub start: f64,
+    pub text: String,
+    /// Word timings from enhanced LRC or syllable SYLT; empty when only the line is timed.
+    pub words: Vec<LyricWord>,
+}
+
+impl LyricLine {
+    /// Characters of the line sung by `time`. Lines without word timings are
+    /// sung as a whole.
+    pub fn sung_chars(&self, time: f64) -> usize {
+        if self.words.is_empty() {
+            return self.text.chars().count();
+        }
+        self.words
+            .iter()
+            .take_while(|word| word.start <= time)
+            .map(|word| word.text.chars().count())
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LyricsSource {
+    LrcFile(PathBuf),
+    /// An ID3 SYLT frame.
+    SyncedTag,
+    /// LRC text in a USLT or LYRICS tag.
+    LyricsTag,
+}
+
+impl LyricsSource {
+    pub fn label(&self) -> String {
+        match self {
+            LyricsSource::LrcFile(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            LyricsSource::SyncedTag => String::from("SYLT tag"),
+            LyricsSource::LyricsTag => String::from("lyrics tag"),
+        }
+    }
+}
+
+/// What the lyrics show at one moment.
+pub struct LyricsFrame<'a> {
+    /// The line being sung and how many of its characters have been.
+    pub current: Option<(&'a LyricLine, usize)>,
+    pub next: Option<&'a LyricLine>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lyrics {
+    /// Sorted by start time. Empty lines clear the display for instrumental parts.
+    pub lines: Vec<LyricLine>,
+    pub source: LyricsSource,
+}
+
+impl Lyrics {
+    /// The lines around `time`, in seconds into the track.
+    pub fn at(&self, time: f64) -> LyricsFrame<'_> {
+        let started = self.lines.partition_point(|line| line.start <= time);
+        let current = started
+            .checked_sub(1)
+            .map(|index| &self.lines[index])
+            .filter(|line| !line.text.is_empty())
+            .map(|line| (line, line.sung_chars(time)));
+        let next = self.lines[started..].iter().find(|line| !line.text.is_empty());
+        LyricsFrame { current, next }
+    }
+
+    /// The lines with text and when `at` shows each: from its start until
+    /// the next line starts, the last one until `end`.
+    pub fn spans(&self, end: f64) -> Vec<(f64, f64, &LyricLine)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !line.text.is_empty())
+            .map(|(index, line)| {
+                let until = self.lines.get(index + 1).map_or(end, |next| next.start);
+                (line.start, until, line)
+            })
+            .collect()
+    }
+}
+
+/// Lyrics for `audio`: an `.lrc` file beside it, a SYLT frame, or LRC text
+/// in the lyrics tag, in that order.
+pub fn load_lyrics(audio: &Path, lyrics_tag: Option<&str>) -> Option<Lyrics> {
+    for extension in ["lrc", "LRC"] {
+        let path = audio.with_extension(extension);
+        let Ok(bytes) = fs::read(&path) else {
+            continue;
+        };
+        match parse_lrc(&String::from_utf8_lossy(&bytes)) {
+            Some(lines) => {
+                return Some(Lyrics {
+                    lines,
+                    source: LyricsSource::LrcFile(path),
+                })
+            }
+            None => log::warn!("No timed lines in {}", path.display()),
+        }
+    }
+    if let Some(lines) = read_sylt(audio) {
+        return Some(Lyrics {
+            lines,
+            source: LyricsSource::SyncedTag,
+        });
+    }
+    lyrics_tag.and_then(parse_lrc).map(|lines| Lyrics {
+        lines,
+        source: LyricsSource::LyricsTag,
+    })
+}
+
+/// Parses `mm:ss`, `mm:ss.xx` or `mm:ss:xx` into seconds.
+fn parse_timestamp(text: &str) -> Option<f64> {
+    let (minutes, rest) = text.trim().split_once(':')?;
+    let minutes: u32 = minutes.parse().ok()?;
+    let (seconds, fraction) = match rest.split_once(['.', ':']) {
+        Some((seconds, fraction)) => (seconds, fraction),
+        None => (rest, ""),
+    };
+    let seconds: u32 = seconds.parse().ok()?;
+    if seconds >= 60 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let fraction = if fraction.is_empty() {
+        0.0
+    } else {
+        fraction.parse::<f64>().ok()? / 10f64.powi(fraction.len() as i32)
+    };
+    Some(minutes as f64 * 60.0 + seconds as f64 + fraction)
+}
+
+/// Splits enhanced-LRC text like `<00:12.00>Hello <00:12.50>world` into
+/// timed words. Text before the first word tag starts with the line.
+fn parse_words(text: &str, line_start: f64) -> Vec<LyricWord> {
+    let mut words: Vec<LyricWord> = Vec::new();
+    let mut start = line_start;
+    let mut rest = text;
+    loop {
+        let tag = rest
+            .find('<')
+            .and_then(|open| Some((open, open + rest[open..].find('>')?)))
+            .and_then(|(open, close)| Some((open, close, parse_timestamp(&rest[open + 1..close])?)));
+        let Some((open, close, time)) = tag else {
+            break;
+        };
+        if !rest[..open].is_empty() {
+            words.push(LyricWord {
+                start,
+                text: rest[..open].to_string(),
+            });
+        }
+        start = time;
+        rest = &rest[close + 1..];
+    }
+    if !rest.is_empty() {
+        words.push(LyricWord {
+            start,
+            text: rest.to_string(),
+        });
+    }
+    words
+}
+
+/// Builds a line from its timed pieces, trimming the whitespace around it.
+fn line_from_words(start: f64, mut words: Vec<LyricWord>, timed: bool) -> LyricLine {
+    if let Some(first) = words.first_mut() {
+        first.text = first.text.trim_start().to_string();
+    }
+    if let Some(last) = words.last_mut() {
+        last.text = last.text.trim_end().to_string();
+    }
+    words.retain(|word| !word.text.is_empty());
+    let text = words.iter().map(|word| word.text.as_str()).collect();
+    LyricLine {
+        start,
+        text,
+        words: if timed { words } else { Vec::new() },
+    }
+}
+
+/// Timed lines of an LRC file, or `None` if it has none.
+pub fn parse_lrc(text: &str) -> Option<Vec<LyricLine>> {
+    let mut offset = 0.0;
+    let mut lines = Vec::new();
+    for raw in text.lines() {
+        let mut rest = raw.trim();
+        let mut times = Vec::new();
+        while let Some(tag) = rest.strip_prefix('[') {
+            let Some(close) = tag.find(']') else {
+                break;
+            };
+            let content = &tag[..close];
+            if let Some(time) = parse_timestamp(content) {
+                times.push(time);
+            } else if let Some(value) = content.strip_prefix("offset:") {
+                // Positive offsets make the lyrics come earlier.
+                offset = value.trim().parse::<f64>().unwrap_or(0.0) / 1000.0;
+            } else if !content.contains(':') {
+                break;
+            }
+            rest = &tag[close + 1..];
+        }
+        for time in times {
+            let words = parse_words(rest, time);
+            let timed = words.len() > 1 || rest.trim_start().starts_with('<');
+            lines.push(line_from_words(time, words, timed));
+        }
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    for line in &mut lines {
+        line.start -= offset;
+        for word in &mut line.words {
+            word.start -= offset;
+        }
+    }
+    lines.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Some(lines)
+}
+
+/// Removes the zero bytes ID3 unsynchronisation inserts after every 0xFF.
+fn resynchronise(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (index, byte) in data.iter().enumerate() {
+        if *byte == 0 && index > 0 && data[index - 1] == 0xFF {
+            continue;
+        }
+        out.push(*byte);
+    }
+    out
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |size, byte| (size << 7) | (*byte & 0x7F) as usize)
+}
+
+/// Lines from the first millisecond-timed SYLT frame of the ID3v2.3/2.4 tag
+/// at the start of `path`. Symphonia skips these frames, so they are read here.
+fn read_sylt(path: &Path) -> Option<Vec<LyricLine>> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0u8; 10];
+    file.read_exact(&mut header).ok()?;
+    let version = header[3];
+    if &header[..3] != b"ID3" || !(3..=4).contains(&version) {
+        return None;
+    }
+    let flags = header[5];
+    let mut tag = vec![0u8; syncsafe(&header[6..10])];
+    file.read_exact(&mut tag).ok()?;
+    if version == 3 && flags & 0x80 != 0 {
+        tag = resynchronise(&tag);
+    }
+
+    let mut pos = 0;
+    if flags & 0x40 != 0 {
+        let size = tag.get(..4)?;
+        pos = if version == 4 {
+            syncsafe(size)
+        } else {
+            u32::from_be_bytes(size.try_into().ok()?) as usize + 4
+        };
+    }
+    while pos + 10 <= tag.len() && tag[pos] != 0 {
+        let id = &tag[pos..pos + 4];
+        let size_bytes = &tag[pos + 4..pos + 8];
+        let size = if version == 4 {
+            syncsafe(size_bytes)
+        } else {
+            u32::from_be_bytes(size_bytes.try_into().ok()?) as usize
+        };
+        let format = tag[pos + 9];
+        let body = tag.get(pos + 10..pos + 10 + size)?;
+        pos += 10 + size;
+        if id != b"SYLT" {
+            continue;
+        }
+
+        let body = if version == 4 {
+            // Compressed or encrypted frames can't be read without more machinery.
+            if format & 0x0C != 0 {
+                continue;
+            }
+            let body = if format & 0x01 != 0 { body.get(4..)? } else { body };
+            if format & 0x02 != 0 || flags & 0x80 != 0 {
+                resynchronise(body)
+            } else {
+                body.to_vec()
+            }
+        } else {
+            if format & 0xC0 != 0 {
+                continue;
+            }
+            if format & 0x20 != 0 { body.get(1..)? } else { body }.to_vec()
+        };
+        if let Some(lines) = parse_sylt(&body) {
+            return Some(lines);
+        }
+    }
+    None
+}
+
+/// Reads a string in ID3 text `encoding` up to its terminator and returns it
+/// with the bytes after the terminator.
+fn read_id3_text(data: &[u8], encoding: u8) -> Option<(String, &[u8])> {
+    if encoding == 0 || encoding == 3 {
+        let end = data.iter().position(|byte| *byte == 0).unwrap_or(data.len());
+        let text = if encoding == 0 {
+            data[..end].iter().map(|byte| *byte as char).collect()
+        } else {
+            String::from_utf8_lossy(&data[..end]).to_string()
+        };
+        return Some((text, data.get(end + 1..).unwrap_or(&[])));
+    }
+
+    let end = data
+        .chunks_exact(2)
+        .position(|pair| pair == [0, 0])
+        .map_or(data.len() / 2 * 2, |index| index * 2);
+    let mut units = data[..end].chunks_exact(2);
+    let mut big_endian = encoding == 2;
+    let mut text = Vec::new();
+    for (index, pair) in units.by_ref().enumerate() {
+        match (index, pair) {
+            (0, [0xFF, 0xFE]) => big_endian = false,
+            (0, [0xFE, 0xFF]) => big_endian = true,
+            (_, [a, b]) if big_endian => text.push(u16::from_be_bytes([*a, *b])),
+            (_, [a, b]) => text.push(u16::from_le_bytes([*a, *b])),
+            _ => unreachable!(),
+        }
+    }
+    Some((
+        String::from_utf16_lossy(&text),
+        data.get(end + 2..).unwrap_or(&[]),
+    ))
+}
+
+/// Parses a SYLT frame body. Syllable-timed frames mark new lines with a
+/// leading line break; otherwise every entry is a line.
+fn parse_sylt(body: &[u8]) -> Option<Vec<LyricLine>> {
+    let encoding = *body.first()?;
+    // 2 is milliseconds; MPEG frame timestamps would need the stream's frame rate.
+    if body.get(4) != Some(&2) {
+        return None;
+    }
+    let (_, mut rest) = read_id3_text(body.get(6..)?, encoding)?;
+    let mut entries = Vec::new();
+    while !rest.is_empty() {
+        let (text, after) = read_id3_text(rest, encoding)?;
+        let time = u32::from_be_bytes(after.get(..4)?.try_into().ok()?);
+        entries.push((time as f64 / 1000.0, text));
+        rest = &after[4..];
+    }
+
+    let syllables = entries
+        .iter()
+        .skip(1)
+        .any(|(_, text)| text.starts_with(['\n', '\r']));
+    let mut lines = Vec::new();
+    let mut words: Vec<LyricWord> = Vec::new();
+    for (start, text) in entries {
+        if !syllables {
+            lines.push(line_from_words(start, vec![LyricWord { start, text }], false));
+            continue;
+        }
+        if text.starts_with(['\n', '\r']) && !words.is_empty() {
+            let line_start = words[0].start;
+            lines.push(line_from_words(line_start, std::mem::take(&mut words), true));
+        }
+        words.push(LyricWord {
+            start,
+            text: text.trim_start_matches(['\n', '\r']).to_string(),
+        });
+    }
+    if !words.is_empty() {
+        let line_start = words[0].start;
+        lines.push(line_from_words(line_start, words, true));
+    }
+    lines.sort_by(|a, b| a.start.total_cmp(&b.start));
+    (!lines.is_empty()).then_some(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(lines: &[LyricLine]) -> Vec<(f64, &str)> {
+        lines.iter().map(|line| (line.start, line.text.as_str())).collect()
+    }
+
+    /// A string in ID3 text `encoding` with its terminator.
+    fn id3_text(text: &str, encoding: u8) -> Vec<u8> {
+        match encoding {
+            0 => text.chars().map(|c| c as u8).chain([0]).collect(),
+            1 => [0xFF, 0xFE]
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .chain([0, 0])
+                .collect(),
+            _ => text.bytes().chain([0]).collect(),
+        }
+    }
+
+    /// A millisecond-timed SYLT frame body.
+    fn sylt(encoding: u8, entries: &[(&str, u32)]) -> Vec<u8> {
+        let mut body = vec![encoding, b'e', b'n', b'g', 2, 1];
+        body.extend(id3_text("", encoding));
+        for (text, time) in entries {
+            body.extend(id3_text(text, encoding));
+            body.extend(time.to_be_bytes());
+        }
+        body
+    }
+
+    /// An ID3v2.3 tag holding one SYLT frame, which claims `extra` more bytes than it has.
+    fn id3_file(body: &[u8], extra: u32, name: &str) -> PathBuf {
+        let mut frame = b"SYLT".to_vec();
+        frame.extend((body.len() as u32 + extra).to_be_bytes());
+        frame.extend([0, 0]);
+        frame.extend(body);
+        let size = frame.len() as u32;
+        let mut file = vec![b'I', b'D', b'3', 3, 0, 0];
+        file.extend([21, 14, 7, 0].map(|shift| (size >> shift) as u8 & 0x7F));
+        file.extend(frame);
+
+        let path = std::env::temp_dir().join(format!("aurora-{}-{}.mp3", name, std::process::id()));
+        fs::write(&path, file).unwrap();
+        path
+    }
+
+    #[test]
+    fn parses_enhanced_lrc_words() {
+        let lines = parse_lrc("[00:12.00]<00:12.00>Hello <00:12.50>world").unwrap();
+        assert_eq!(texts(&lines), [(12.0, "Hello world")]);
+        let words: Vec<_> = lines[0].words.iter().map(|w| (w.start, w.text.as_str())).collect();
+        assert_eq!(words, [(12.0, "Hello "), (12.5, "world")]);
+        assert_eq!(lines[0].sung_chars(12.2), 6);
+
+        // Text before the first word tag starts with the line.
+        let lines = parse_lrc("[00:01.00]Oh <00:02.00>yes").unwrap();
+        assert_eq!(lines[0].words[0].start, 1.0);
+        assert!(parse_lrc("[00:01.00]Plain line").unwrap()[0].words.is_empty());
+    }
+
+    #[test]
+    fn applies_the_offset_tag() {
+        let lines = parse_lrc("[ar:Someone]\n[offset:500]\n[00:10.00]<00:10.50>Line").unwrap();
+        assert_eq!(texts(&lines), [(9.5, "Line")]);
+        assert_eq!(lines[0].words[0].start, 10.0);
+    }
+
+    #[test]
+    fn repeats_lines_with_several_timestamps() {
+        let lines = parse_lrc("[00:01.00][00:05:50]Chorus\n[00:03]Verse\n[00:07.00]").unwrap();
+        assert_eq!(
+            texts(&lines),
+            [(1.0, "Chorus"), (3.0, "Verse"), (5.5, "Chorus"), (7.0, "")]
+        );
+        assert_eq!(parse_lrc("[ti:Title]\nNo timestamps"), None);
+    }
+
+    #[test]
+    fn parses_sylt_lines() {
+        for encoding in [0, 1, 3] {
+            let body = sylt(encoding, &[("First line", 1000), ("Second", 2500)]);
+            let lines = parse_sylt(&body).unwrap();
+            assert_eq!(texts(&lines), [(1.0, "First line"), (2.5, "Second")], "{}", encoding);
+            assert!(lines[0].words.is_empty());
+        }
+    }
+
+    #[test]
+    fn parses_sylt_syllables() {
+        for encoding in [0, 1, 3] {
+            let entries = [("Hel", 1000), ("lo ", 1500), ("world", 1800), ("\nNext", 3000)];
+            let lines = parse_sylt(&sylt(encoding, &entries)).unwrap();
+            assert_eq!(texts(&lines), [(1.0, "Hello world"), (3.0, "Next")], "{}", encoding);
+            let starts: Vec<f64> = lines[0].words.iter().map(|word| word.start).collect();
+            assert_eq!(starts, [1.0, 1.5, 1.8]);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_sylt() {
+        let body = sylt(1, &[("First", 1000), ("Second", 2500)]);
+        for len in 0..body.len() {
+            // Cutting into the last timestamp loses that entry, or the whole frame.
+            assert!(parse_sylt(&body[..len]).is_none_or(|lines| lines.len() < 2));
+        }
+        // Timed in MPEG frames rather than milliseconds.
+        let mut mpeg_frames = sylt(3, &[("Line", 1000)]);
+        mpeg_frames[4] = 1;
+        assert_eq!(parse_sylt(&mpeg_frames), None);
+    }
+
+    #[test]
+    fn reads_sylt_from_id3_tags() {
+        let body = sylt(3, &[("Tagged", 750)]);
+        let path = id3_file(&body, 0, "sylt");
+        assert_eq!(texts(&read_sylt(&path).unwrap()), [(0.75, "Tagged")]);
+        fs::remove_file(&path).unwrap();
+
+        let path = id3_file(&body, 100, "sylt-truncated");
+        assert_eq!(read_sylt(&path), None);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +1,43 @@
+mod audio_bridge;
+mod audio_meter;
+mod beat_detector;
 mod config;
+mod favorites;
+mod hotkeys;
+mod latency_calibration;
+mod live_input;
+mod lyrics;
+mod metadata;
+mod midi;
+#[cfg(feature = "mpris")]
+mod mpris;
+mod normalization;
+mod osc;
+mod output_profile;
+mod output_stage;
+mod overlay;
+mod overlay_animation;
+mod overlay_image;
+mod overlay_widgets;
 mod playback;
 mod preset_blocklist;
+mod preset_director;
+mod preset_rotation;
+mod preset_tags;
 mod projectm_widget;
+mod recorder;
+mod remote_control;
+mod render_target;
+mod seek_bar;
+mod settings_window;
+mod sprite_renderer;
+mod subtitles;
+mod tap_tempo;
+mod text_layout;
+mod text_raster;
+mod track_analysis;
+#[cfg(feature = "transcription")]
+mod transcription;
 mod ui;
 mod main_app;
 
@@ -11,10 +47,38 @@ use main_app::MusicVisualizerApp;
 impl App for MusicVisualizerApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         ctx.request_repaint();
+        self.handle_preset_switch_requests();
+        self.sync_overlay_track();
+        self.handle_audio_events();
+        self.playback.update();
+        #[cfg(feature = "mpris")]
+        self.handle_mpris(ctx);
+        self.handle_remote_commands();
+        self.handle_midi();
+        #[cfg(feature = "transcription")]
+        self.handle_transcription();
+        if !self.settings.is_capturing() {
+            for action in self.config.hotkeys.pressed(ctx) {
+                self.run_hotkey(action);
+            }
+        }
+        if self.recorder.lock().unwrap().mode() == Some(recorder::RenderMode::Live)
+            && self.playback.is_finished()
+        {
+            self.stop_recording();
+        }
         ui::draw_ui(ctx, self);
+        self.save_pending_config(false);
     }
 
-    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+    fn on_exit(&mut self, gl: Option<&eframe::glow::Context>) {
+        self.save_pending_config(true);
+        if let Some(finalizer) = self.stop_recording() {
+            let _ = finalizer.join();
+        }
+        if let Some(gl) = gl {
+            self.overlay.lock().unwrap().destroy(gl);
+        }
         self.painter.destroy();
     }
 }
@@ -23,6 +87,7 @@ fn main() -> Result<(), eframe::Error> {
     env_logger::init();
 
     let config = config::load_config();
+    live_input::select_monitor(&config.input);
 
     let options = eframe::NativeOptions {
         renderer: eframe::Renderer::Glow,
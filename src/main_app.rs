@@ -1,28 +1,85 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use egui_glow::Painter;
 use projectm::core::ProjectM;
-use projectm::playlist::Playlist;
 
+use crate::audio_bridge::AudioBridge;
+use crate::beat_detector::{AudioEvent, AudioEvents};
 use crate::config;
+use crate::favorites::Favorites;
+use crate::hotkeys::HotkeyAction;
+use crate::latency_calibration::LatencyCalibration;
+use crate::metadata::read_metadata;
+use crate::midi::{MidiLink, MidiParameter, MidiTarget};
+#[cfg(feature = "mpris")]
+use crate::mpris::{MprisCommand, MprisServer};
+use crate::osc::OscLink;
+use crate::output_stage::VOLUME_STEP;
+use crate::overlay::{Overlay, TextSource};
 use crate::playback::Playback;
 use crate::preset_blocklist::PresetBlocklist;
+use crate::preset_director::{CurrentPreset, PresetDirector};
+use crate::preset_rotation::{
+    scan_presets, PresetRotation, PresetSchedule, PresetSource, SwitchRequests,
+};
+use crate::preset_tags::PresetTags;
 use crate::projectm_widget::ProjectMVisualizer;
+use crate::recorder::{RenderJob, RenderMode, Recorder};
+use crate::remote_control::{OverlaySlot, RemoteCommand, RemoteServer, RemoteState};
+use crate::settings_window::SettingsWindow;
+use crate::tap_tempo::TapTempo;
+#[cfg(feature = "transcription")]
+use crate::transcription::Transcriber;
+
+/// How long edits must pause before `save_config_later` writes the config.
+const CONFIG_SAVE_DELAY: Duration = Duration::from_secs(1);
 
 pub struct MusicVisualizerApp {
     pub projectm: Arc<ProjectM>,
-    pub playlist: Playlist,
+    /// What projectM is showing; every preset load goes through it.
+    pub current_preset: CurrentPreset,
     pub visualizer: ProjectMVisualizer,
     pub painter: Painter,
     pub playback: Playback,
     pub preset_blocklist: Arc<Mutex<PresetBlocklist>>,
+    pub favorites: Favorites,
+    pub preset_tags: PresetTags,
+    /// Every preset under `Config::preset_path`, scanned once at startup.
+    pub presets: Vec<String>,
+    pub director: PresetDirector,
+    started: Instant,
+    pub switch_requests: Arc<SwitchRequests>,
+    /// The app's own subscription, for overlay pulses and the preset director.
+    audio_events: Receiver<AudioEvent>,
+    /// Where tapped beats are published.
+    events: Arc<AudioEvents>,
+    pub tap_tempo: TapTempo,
+    /// When the last beat reached the app and whether it was a downbeat, for the beat indicator.
+    pub last_beat: Option<(Instant, bool)>,
+    pub recorder: Arc<Mutex<Recorder>>,
+    pub overlay: Arc<Mutex<Overlay>>,
+    pub config: config::Config,
+    /// When `config` was last changed without being saved.
+    unsaved_since: Option<Instant>,
+    pub settings: SettingsWindow,
+    pub calibration: LatencyCalibration,
+    remote: RemoteServer,
+    pub osc: OscLink,
+    pub midi: MidiLink,
+    #[cfg(feature = "transcription")]
+    pub transcriber: Transcriber,
+    /// `None` when there is no session bus.
+    #[cfg(feature = "mpris")]
+    mpris: Option<MprisServer>,
 }
 
 impl MusicVisualizerApp {
     pub fn new(cc: &eframe::CreationContext, config: &config::Config) -> Self {
         let projectm = Arc::new(ProjectM::create());
         projectm.set_window_size(config.width as usize, config.height as usize);
-        let playlist = Playlist::create(&projectm);
 
         let preset_blocklist = Arc::new(Mutex::new(PresetBlocklist::new()));
         let blocklist_clone = preset_blocklist.clone();
@@ -37,19 +94,681 @@ impl MusicVisualizerApp {
             }
         });
 
-        playlist.add_path(config.preset_path.to_str().unwrap(), true);
+        // projectM's timer only raises a flag; see `handle_preset_switch_requests`.
+        let switch_requests = Arc::new(SwitchRequests::default());
+        let requests_clone = switch_requests.clone();
+        projectm.set_preset_switch_requested_event_callback(move |_is_hard_cut| {
+            requests_clone.raise();
+        });
+        // Beat-driven switching uses our own detector; projectM's hard cuts would fight it.
+        projectm.set_hard_cut_enabled(false);
 
-        let visualizer = ProjectMVisualizer::new(projectm.clone());
+        let (audio_sender, audio_bridge) = AudioBridge::new();
+        let visualizer = ProjectMVisualizer::new(projectm.clone(), audio_bridge);
         let painter = Painter::new(cc.gl.clone().unwrap(), "", None).unwrap();
-        let playback = Playback::new(projectm.clone());
+        let audio_events = Arc::new(AudioEvents::default());
+        let app_events = audio_events.subscribe();
+        let playback = Playback::new(
+            audio_sender,
+            audio_events.clone(),
+            config.beat_sensitivity,
+        );
+        let current_preset = CurrentPreset::default();
+        let recorder = Arc::new(Mutex::new(Recorder::new(
+            &config.recording,
+            audio_events.subscribe(),
+            current_preset.clone(),
+        )));
 
-        Self {
+        let mut app = Self {
             projectm,
-            playlist,
+            current_preset,
             visualizer,
             painter,
             playback,
             preset_blocklist,
+            favorites: Favorites::new(),
+            preset_tags: PresetTags::new(),
+            presets: scan_presets(&config.preset_path),
+            director: PresetDirector::new(),
+            started: Instant::now(),
+            switch_requests,
+            audio_events: app_events,
+            events: audio_events,
+            tap_tempo: TapTempo::new(),
+            last_beat: None,
+            recorder,
+            overlay: Arc::new(Mutex::new(Overlay::new(config.overlay.clone()))),
+            config: config.clone(),
+            unsaved_since: None,
+            settings: SettingsWindow::new(),
+            calibration: LatencyCalibration::new(),
+            remote: RemoteServer::start(&config.remote),
+            osc: OscLink::new(),
+            midi: MidiLink::new(),
+            #[cfg(feature = "transcription")]
+            transcriber: Transcriber::new(),
+            #[cfg(feature = "mpris")]
+            mpris: MprisServer::new()
+                .map_err(|err| log::warn!("MPRIS service unavailable: {}", err))
+                .ok(),
+        };
+        app.apply_config();
+        app.next_preset();
+        app
+    }
+
+    /// Saves the config once it has stopped changing, for edits that arrive
+    /// every frame or keystroke.
+    pub fn save_config_later(&mut self) {
+        self.unsaved_since = Some(Instant::now());
+    }
+
+    /// Writes changes from `save_config_later` that have settled, or all of
+    /// them with `now`.
+    pub fn save_pending_config(&mut self, now: bool) {
+        if self
+            .unsaved_since
+            .is_some_and(|since| now || since.elapsed() >= CONFIG_SAVE_DELAY)
+        {
+            self.unsaved_since = None;
+            config::save_config(&self.config);
+        }
+    }
+
+    /// Pushes `config`, with the settings window's unsaved edits on top, to everything
+    /// that reads it, so settings changes show up immediately.
+    pub fn apply_config(&mut self) {
+        let config = self.settings.preview(&self.config);
+        self.projectm.set_beat_sensitivity(config.beat_sensitivity);
+        self.playback.set_beat_sensitivity(config.beat_sensitivity);
+        self.playback
+            .set_normalization(config.normalization.clone());
+        self.playback.set_output(&config.output);
+        self.playback.set_input(&config.input);
+        let offset = config
+            .av_offsets
+            .get(&self.playback.latency_key())
+            .copied()
+            .unwrap_or(0.0);
+        self.playback.set_visual_offset(offset);
+        self.projectm.set_fps(config.frame_rate);
+        self.projectm.set_preset_duration(config.preset_duration);
+        self.projectm
+            .set_soft_cut_duration(config.director.transition_duration);
+        if !config.texture_path.as_os_str().is_empty() {
+            let paths = [config.texture_path.to_string_lossy().to_string()];
+            self.projectm.set_texture_search_paths(&paths, paths.len());
+        }
+        self.overlay.lock().unwrap().config = config.overlay.clone();
+        self.osc.configure(&config.osc);
+        self.midi.configure(&config.midi);
+
+        let mut recorder = self.recorder.lock().unwrap();
+        if !recorder.is_recording() {
+            recorder.apply_config(&config.recording);
+        }
+    }
+
+    pub fn run_hotkey(&mut self, action: HotkeyAction) {
+        match action {
+            HotkeyAction::NextPreset => self.next_preset(),
+            HotkeyAction::PreviousPreset => self.previous_preset(),
+            HotkeyAction::ToggleOverlay => {
+                self.config.overlay.enabled = !self.config.overlay.enabled;
+                self.apply_config();
+                config::save_config(&self.config);
+            }
+            HotkeyAction::ToggleRecording => {
+                if self.recorder.lock().unwrap().is_recording() {
+                    self.stop_recording();
+                } else {
+                    self.start_recording(RenderMode::Live);
+                }
+            }
+            HotkeyAction::OpenSettings => self.settings.open(&self.config),
+            HotkeyAction::VolumeUp => self.change_volume(VOLUME_STEP),
+            HotkeyAction::VolumeDown => self.change_volume(-VOLUME_STEP),
+            HotkeyAction::ToggleMute => {
+                let muted = self.playback.is_muted();
+                self.playback.set_muted(!muted);
+            }
+            HotkeyAction::ToggleFavorite => {
+                if let Some(preset) = self.current_preset.get() {
+                    if self.favorites.contains(&preset) {
+                        self.favorites.remove(&preset);
+                    } else {
+                        self.favorites.add(&preset);
+                    }
+                }
+            }
+            HotkeyAction::ToggleBlackout => self.visualizer.blackout = !self.visualizer.blackout,
+            HotkeyAction::TapTempo => self.tap_beat(),
+            HotkeyAction::NudgeBeatEarlier => self.tap_tempo.nudge(-1),
+            HotkeyAction::NudgeBeatLater => self.tap_tempo.nudge(1),
+            HotkeyAction::HalveTempo => self.tap_tempo.halve(),
+            HotkeyAction::DoubleTempo => self.tap_tempo.double(),
+            HotkeyAction::AutoTempo => self.tap_tempo.clear(),
+        }
+    }
+
+    pub fn change_volume(&mut self, step: f32) {
+        self.set_volume(self.config.output.volume + step);
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        let output = &mut self.config.output;
+        output.volume = volume.clamp(0.0, 1.0);
+        self.playback.set_output(output);
+        self.save_config_later();
+    }
+
+    /// Carries out what media keys and `playerctl` asked for and publishes the new state.
+    #[cfg(feature = "mpris")]
+    pub fn handle_mpris(&mut self, ctx: &egui::Context) {
+        let Some(mpris) = &self.mpris else {
+            return;
+        };
+        for command in mpris.commands() {
+            match command {
+                MprisCommand::Raise => ctx.send_viewport_cmd(egui::ViewportCommand::Focus),
+                MprisCommand::Quit => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                MprisCommand::Play => self.playback.play(),
+                MprisCommand::Pause => self.playback.set_paused(true),
+                MprisCommand::PlayPause => self.playback.set_paused(self.playback.is_playing()),
+                MprisCommand::Stop => self.playback.stop(),
+                MprisCommand::Next => self.playback.next_track(),
+                MprisCommand::Previous => self.playback.previous_track(),
+                MprisCommand::Seek(offset) => {
+                    let position = self.playback.position_secs() + offset as f64 / 1e6;
+                    if position >= self.playback.duration_secs() && self.playback.has_next_track() {
+                        self.playback.next_track();
+                    } else {
+                        self.playback.seek(position);
+                    }
+                }
+                MprisCommand::SetPosition(position) => self.playback.seek(position as f64 / 1e6),
+                MprisCommand::SetVolume(volume) => self.set_volume(volume as f32),
+                MprisCommand::Open(path) => {
+                    if !path.is_file() {
+                        log::warn!("MPRIS asked to open missing file {}", path.display());
+                        self.playback.status = format!("No file at {}", path.display());
+                        continue;
+                    }
+                    let index = self.playback.queue.len();
+                    // Adding to an empty queue already starts the track.
+                    self.playback.add_files(vec![path]);
+                    if self.playback.current_track_index != Some(index) {
+                        self.playback.play_track(index);
+                    }
+                }
+            }
+        }
+        if let Some(mpris) = &mut self.mpris {
+            mpris.update(&self.playback, self.config.output.volume);
+        }
+    }
+
+    /// Carries out commands from remote-control clients and OSC controllers,
+    /// and tells them what changed.
+    pub fn handle_remote_commands(&mut self) {
+        for request in self.remote.requests() {
+            let result = self.run_remote(request.command.clone());
+            request.reply(result, &self.remote_state());
+        }
+        for command in self.osc.commands() {
+            if let Err(err) = self.run_remote(command) {
+                log::warn!("OSC command failed: {}", err);
+            }
         }
+        let state = self.remote_state();
+        self.osc.send_preset(state.preset.as_deref());
+        self.remote.publish(&state);
+    }
+
+    /// Runs the actions bound to controls that were pressed and moves the
+    /// parameters bound to knobs. While the settings window is learning, the
+    /// first control touched is bound instead.
+    pub fn handle_midi(&mut self) {
+        for message in self.midi.messages() {
+            if let Some(target) = self.settings.midi_learning() {
+                if message.position().is_some() {
+                    self.config.midi.bind(target, Some(message.control));
+                    self.settings.stop_midi_learning();
+                }
+                continue;
+            }
+            let targets: Vec<MidiTarget> = self
+                .config
+                .midi
+                .bindings()
+                .iter()
+                .filter(|binding| binding.control == message.control)
+                .map(|binding| binding.target)
+                .collect();
+            for target in targets {
+                match target {
+                    MidiTarget::Action(action) if message.pressed => self.run_hotkey(action),
+                    MidiTarget::Action(_) => {}
+                    MidiTarget::Parameter(parameter) => {
+                        if let Some(position) = message.position() {
+                            self.set_parameter(parameter, parameter.value(position));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Shows freshly transcribed lyrics if their track is still playing.
+    #[cfg(feature = "transcription")]
+    pub fn handle_transcription(&mut self) {
+        let Some(track) = self.transcriber.update() else {
+            return;
+        };
+        let mut overlay = self.overlay.lock().unwrap();
+        if overlay.track_path() == Some(track.as_path()) {
+            overlay.reload_lyrics();
+        }
+    }
+
+    /// Applies a knob's value right away and saves it once the knob rests.
+    fn set_parameter(&mut self, parameter: MidiParameter, value: f32) {
+        match parameter {
+            MidiParameter::BeatSensitivity => {
+                self.config.beat_sensitivity = value;
+                self.projectm.set_beat_sensitivity(value);
+                self.playback.set_beat_sensitivity(value);
+                self.save_config_later();
+            }
+            MidiParameter::Volume => self.set_volume(value),
+            MidiParameter::OverlayOpacity => {
+                self.config.overlay.opacity = value;
+                self.overlay.lock().unwrap().config.opacity = value;
+                self.save_config_later();
+            }
+        }
+    }
+
+    fn run_remote(&mut self, command: RemoteCommand) -> Result<(), String> {
+        match command {
+            RemoteCommand::Status => {}
+            RemoteCommand::NextPreset => self.run_hotkey(HotkeyAction::NextPreset),
+            RemoteCommand::PreviousPreset => self.run_hotkey(HotkeyAction::PreviousPreset),
+            RemoteCommand::LoadPreset { preset } => {
+                let path = if Path::new(&preset).is_file() {
+                    preset
+                } else {
+                    self.presets
+                        .iter()
+                        .find(|path| {
+                            let path = Path::new(path);
+                            path.file_stem().is_some_and(|stem| *stem == *preset)
+                                || path.file_name().is_some_and(|name| *name == *preset)
+                        })
+                        .cloned()
+                        .ok_or_else(|| format!("No preset called {}", preset))?
+                };
+                self.load_preset(&path);
+            }
+            RemoteCommand::Favorite { favorite } => {
+                let preset = self.current_preset.get().ok_or("No preset is showing")?;
+                if favorite {
+                    self.favorites.add(&preset);
+                } else {
+                    self.favorites.remove(&preset);
+                }
+            }
+            RemoteCommand::Block => {
+                let preset = self.current_preset.get().ok_or("No preset is showing")?;
+                self.preset_blocklist.lock().unwrap().add(&preset);
+                self.next_preset();
+            }
+            RemoteCommand::Tap => self.tap_beat(),
+            RemoteCommand::QueueAdd { files } => {
+                if let Some(missing) = files.iter().find(|file| !file.is_file()) {
+                    return Err(format!("No file at {}", missing.display()));
+                }
+                self.playback.add_files(files);
+            }
+            RemoteCommand::Play { index: Some(index) } => {
+                if index >= self.playback.queue.len() {
+                    return Err(format!("The queue has {} tracks", self.playback.queue.len()));
+                }
+                self.playback.play_track(index);
+            }
+            RemoteCommand::Play { index: None } => self.playback.play(),
+            RemoteCommand::Pause => self.playback.set_paused(true),
+            RemoteCommand::NextTrack => self.playback.next_track(),
+            RemoteCommand::PreviousTrack => self.playback.previous_track(),
+            RemoteCommand::StartRecording { offline } => {
+                if self.recorder.lock().unwrap().is_recording() {
+                    return Err(String::from("Already recording"));
+                }
+                let mode = if offline {
+                    RenderMode::Offline
+                } else {
+                    RenderMode::Live
+                };
+                self.start_recording(mode);
+                let recorder = self.recorder.lock().unwrap();
+                if !recorder.is_recording() {
+                    return Err(recorder.status.clone());
+                }
+            }
+            RemoteCommand::StopRecording => {
+                self.stop_recording();
+            }
+            RemoteCommand::SetOverlayText { text, element } => {
+                let overlay = &mut self.config.overlay;
+                let element = match element {
+                    OverlaySlot::Title => &mut overlay.title,
+                    OverlaySlot::Artist => &mut overlay.artist,
+                    OverlaySlot::Url => &mut overlay.url,
+                };
+                element.enabled = true;
+                element.source = TextSource::Custom;
+                element.custom_text = text;
+                overlay.enabled = true;
+                self.apply_config();
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts a beat on the tapped grid now; see `TapTempo::tap`.
+    pub fn tap_beat(&mut self) {
+        self.tap_tempo.tap();
+        self.publish_tapped_beats();
+    }
+
+    /// Publishes the tapped grid's beats, and holds back the detector's while
+    /// the grid has a tempo.
+    fn publish_tapped_beats(&mut self) {
+        self.events.set_manual_beats(self.tap_tempo.is_active());
+        if let Some(beat) = self.tap_tempo.update() {
+            self.events.publish(AudioEvent::Beat {
+                time: self.playback.position_secs(),
+                bpm: beat.bpm.or_else(|| self.playback.bpm()).unwrap_or(0.0),
+                downbeat: beat.downbeat,
+            });
+        }
+    }
+
+    pub fn remote_state(&self) -> RemoteState {
+        let preset = self.current_preset.get();
+        RemoteState {
+            favorite: preset
+                .as_deref()
+                .is_some_and(|preset| self.favorites.contains(preset)),
+            preset,
+            queue: self.playback.queue.clone(),
+            track_index: self.playback.current_track_index,
+            playing: self.playback.is_playing(),
+            recording: self.recorder.lock().unwrap().is_recording(),
+            overlay_enabled: self.config.overlay.enabled,
+            volume: self.config.output.volume,
+        }
+    }
+
+    /// With the director off, projectM's timer steps through the presets.
+    /// Otherwise the director or, while recording, the recorder's preset
+    /// rotation decides and the request is dropped.
+    pub fn handle_preset_switch_requests(&mut self) {
+        let recording = self.recorder.lock().unwrap().is_recording();
+        if self.switch_requests.take() && !recording && !self.config.director.enabled {
+            self.next_preset();
+        }
+    }
+
+    /// Passes detected beats on to the overlay's pulse animations and lets the
+    /// director switch presets.
+    pub fn handle_audio_events(&mut self) {
+        // Offline renders pulse the overlay from their own detector, so that a
+        // render repeats exactly; wall-clock beats must not reach it meanwhile.
+        let offline = self.recorder.lock().unwrap().mode() == Some(RenderMode::Offline);
+        if !offline {
+            self.publish_tapped_beats();
+        }
+        let events: Vec<AudioEvent> = self.audio_events.try_iter().collect();
+
+        let mut overlay = self.overlay.lock().unwrap();
+        for event in &events {
+            if let AudioEvent::Beat {
+                time,
+                bpm,
+                downbeat,
+            } = *event
+            {
+                if !offline {
+                    overlay.beat(time);
+                }
+                self.osc.send_beat(bpm, downbeat);
+                self.last_beat = Some((Instant::now(), downbeat));
+            }
+        }
+        drop(overlay);
+
+        let recording = self.recorder.lock().unwrap().is_recording();
+        if !self.config.director.enabled || recording {
+            return;
+        }
+        let now = self.started.elapsed().as_secs_f64();
+        if self.director.update(
+            now,
+            &events,
+            &self.config.director,
+            self.config.preset_duration,
+        ) {
+            self.next_preset();
+        }
+    }
+
+    pub fn next_preset(&mut self) {
+        if !self.config.director.enabled {
+            if let Some(preset) = self.step_preset(1) {
+                self.current_preset.load(&self.projectm, &preset, false);
+            }
+            return;
+        }
+        let pool = self.director_pool();
+        let now = self.started.elapsed().as_secs_f64();
+        if let Some(preset) = self.director.choose(now, &pool) {
+            self.current_preset
+                .load(&self.projectm, &preset, self.config.director.smooth_transitions);
+        }
+    }
+
+    pub fn previous_preset(&mut self) {
+        if !self.config.director.enabled {
+            if let Some(preset) = self.step_preset(-1) {
+                self.current_preset.load(&self.projectm, &preset, false);
+            }
+            return;
+        }
+        let now = self.started.elapsed().as_secs_f64();
+        if let Some(preset) = self.director.previous(now) {
+            self.current_preset
+                .load(&self.projectm, &preset, self.config.director.smooth_transitions);
+        }
+    }
+
+    /// The unblocked preset `step` places after the current one in `presets`,
+    /// wrapping around at either end.
+    fn step_preset(&self, step: isize) -> Option<String> {
+        let blocklist = self.preset_blocklist.lock().unwrap();
+        let len = self.presets.len() as isize;
+        let current = self
+            .current_preset
+            .get()
+            .and_then(|current| self.presets.iter().position(|preset| *preset == current));
+        // With nothing showing yet, stepping forward starts at the first preset.
+        let start = current.map_or(if step > 0 { -1 } else { 0 }, |index| index as isize);
+        (1..=len)
+            .map(|offset| &self.presets[(start + step * offset).rem_euclid(len) as usize])
+            .find(|preset| !blocklist.contains(preset))
+            .cloned()
+    }
+
+    /// Shows `preset` right away and lets the director carry on from it.
+    pub fn load_preset(&mut self, preset: &str) {
+        let now = self.started.elapsed().as_secs_f64();
+        self.director.show(now, preset);
+        self.current_preset
+            .load(&self.projectm, preset, self.config.director.smooth_transitions);
+    }
+
+    /// Presets matching the current intensity if asked for and tagged, otherwise all of them.
+    fn director_pool(&self) -> Vec<String> {
+        let blocklist = self.preset_blocklist.lock().unwrap();
+        let mut pool: Vec<String> = Vec::new();
+        if self.config.director.match_intensity {
+            pool = self
+                .preset_tags
+                .presets_with_tag(self.director.intensity().tag())
+                .filter(|preset| !blocklist.contains(preset))
+                .cloned()
+                .collect();
+        }
+        if pool.is_empty() {
+            pool = self
+                .presets
+                .iter()
+                .filter(|preset| !blocklist.contains(preset))
+                .cloned()
+                .collect();
+        }
+        pool
+    }
+
+    /// Reloads the overlay's title and artist when a different track starts,
+    /// and those of the up next card when the following track changes.
+    pub fn sync_overlay_track(&mut self) {
+        let current = self.playback.current_track();
+        let mut overlay = self.overlay.lock().unwrap();
+        if overlay.track_path() != current.map(PathBuf::as_path) {
+            let metadata = current.map(|path| read_metadata(path)).unwrap_or_default();
+            let duration = self.playback.duration_secs();
+            overlay.set_track(current.map(PathBuf::as_path), metadata, duration);
+            overlay.lyrics_offset = self.lyrics_offset() as f64 / 1000.0;
+        }
+        let upcoming = self.playback.upcoming_track();
+        if overlay.next_track_path() != upcoming.map(PathBuf::as_path) {
+            let metadata = upcoming.map(|path| read_metadata(path)).unwrap_or_default();
+            overlay.set_next_track(upcoming.map(PathBuf::as_path), metadata);
+        }
+    }
+
+    /// The current track's lyric offset in milliseconds.
+    pub fn lyrics_offset(&self) -> f32 {
+        self.playback
+            .current_track()
+            .and_then(|track| self.config.lyric_offsets.get(&*track.to_string_lossy()))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    pub fn set_lyrics_offset(&mut self, offset_ms: f32) {
+        let Some(track) = self.playback.current_track() else {
+            return;
+        };
+        let key = track.to_string_lossy().to_string();
+        if offset_ms == 0.0 {
+            self.config.lyric_offsets.remove(&key);
+        } else {
+            self.config.lyric_offsets.insert(key, offset_ms);
+        }
+        self.overlay.lock().unwrap().lyrics_offset = offset_ms as f64 / 1000.0;
+        self.save_config_later();
+    }
+
+    /// The presets a new render may choose from, sorted so a seed reproduces the same picks.
+    /// Fails rather than rotating through presets the user didn't pick.
+    fn rotation_pool(&self) -> Result<Vec<String>, String> {
+        let source = &self.config.recording.rotation.source;
+        let mut pool: Vec<String> = match source {
+            PresetSource::All => self.presets.clone(),
+            PresetSource::Favorites => self.favorites.iter().cloned().collect(),
+            PresetSource::Tag(tag) => self.preset_tags.presets_with_tag(tag).cloned().collect(),
+        };
+
+        let blocklist = self.preset_blocklist.lock().unwrap();
+        pool.retain(|preset| !blocklist.contains(preset));
+        if pool.is_empty() {
+            return Err(match source {
+                PresetSource::All => String::from("No presets to rotate"),
+                PresetSource::Favorites => String::from("No favorite presets to rotate"),
+                PresetSource::Tag(tag) => format!("No presets tagged \"{}\" to rotate", tag),
+            });
+        }
+        pool.sort();
+        Ok(pool)
+    }
+
+    /// Records the current track with every active output profile.
+    pub fn start_recording(&mut self, mode: RenderMode) {
+        let Some(audio_path) = self.playback.current_track().cloned() else {
+            self.recorder.lock().unwrap().status = String::from("Add a track to record first");
+            return;
+        };
+
+        let pool = match self.rotation_pool() {
+            Ok(pool) => pool,
+            Err(err) => {
+                self.recorder.lock().unwrap().status = err;
+                return;
+            }
+        };
+        let rotation = PresetRotation::new(
+            audio_path.clone(),
+            self.config.recording.rotation.clone(),
+            pool,
+        );
+        self.start_render(mode, audio_path, rotation);
+    }
+
+    /// Re-renders a track offline with the exact preset timeline of an earlier render.
+    pub fn replay_schedule(&mut self, schedule_path: &Path) {
+        match PresetSchedule::load(schedule_path) {
+            Ok(schedule) => {
+                let audio_path = schedule.audio.clone();
+                self.start_render(
+                    RenderMode::Offline,
+                    audio_path,
+                    PresetRotation::replay(schedule),
+                );
+            }
+            Err(err) => {
+                self.recorder.lock().unwrap().status =
+                    format!("Could not read {}: {}", schedule_path.display(), err);
+            }
+        }
+    }
+
+    fn start_render(&mut self, mode: RenderMode, audio_path: PathBuf, rotation: PresetRotation) {
+        match mode {
+            RenderMode::Live => self.playback.restart(),
+            RenderMode::Offline => self.playback.stop(),
+        }
+
+        let job = RenderJob {
+            audio_path,
+            profiles: self.config.recording.active(),
+            mode,
+            rotation,
+            beat_sensitivity: self.config.beat_sensitivity,
+            cues: self.overlay.lock().unwrap().timeline(),
+            normalization: self.config.normalization.clone(),
+        };
+
+        let mut recorder = self.recorder.lock().unwrap();
+        recorder.apply_config(&self.config.recording);
+        if let Err(err) = recorder.start(job) {
+            recorder.status = format!("Recording failed: {}", err);
+        }
+    }
+
+    pub fn stop_recording(&mut self) -> Option<std::thread::JoinHandle<()>> {
+        self.recorder.lock().unwrap().stop()
     }
 }
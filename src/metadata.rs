@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey, StandardVisualKey};
+use symphonia::core::probe::Hint;
+
+/// Tag values read from an audio file. Every field is optional because many
+/// of the files we get are untagged exports.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Unsynced lyrics (USLT or LYRICS), which sometimes hold LRC text.
+    pub lyrics: Option<String>,
+    pub replay_gain: ReplayGain,
+    /// Encoded bytes of the embedded front cover, or of the first picture if
+    /// none is marked as the front cover.
+    pub cover_art: Option<Arc<[u8]>>,
+}
+
+/// ReplayGain values in dB relative to the ReplayGain 2 reference of -18 LUFS;
+/// peaks are linear sample levels.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+/// Parses values like `-6.48 dB`.
+fn parse_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    number.trim().parse().ok()
+}
+
+/// Opus `R128_*_GAIN` tags are Q7.8 fixed point relative to -23 LUFS.
+fn parse_r128_gain(value: &str) -> Option<f32> {
+    let steps: i32 = value.trim().parse().ok()?;
+    Some(steps as f32 / 256.0 + 5.0)
+}
+
+impl TrackMetadata {
+    fn merge_revision(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let value = tag.value.to_string();
+            if value.trim().is_empty() {
+                continue;
+            }
+            let replay_gain = &mut self.replay_gain;
+            let gain = match tag.std_key {
+                Some(StandardTagKey::ReplayGainTrackGain) => {
+                    Some((&mut replay_gain.track_gain, parse_gain(&value)))
+                }
+                Some(StandardTagKey::ReplayGainAlbumGain) => {
+                    Some((&mut replay_gain.album_gain, parse_gain(&value)))
+                }
+                Some(StandardTagKey::ReplayGainTrackPeak) => {
+                    Some((&mut replay_gain.track_peak, value.trim().parse().ok()))
+                }
+                Some(StandardTagKey::ReplayGainAlbumPeak) => {
+                    Some((&mut replay_gain.album_peak, value.trim().parse().ok()))
+                }
+                _ if tag.key.eq_ignore_ascii_case("R128_TRACK_GAIN") => {
+                    Some((&mut replay_gain.track_gain, parse_r128_gain(&value)))
+                }
+                _ if tag.key.eq_ignore_ascii_case("R128_ALBUM_GAIN") => {
+                    Some((&mut replay_gain.album_gain, parse_r128_gain(&value)))
+                }
+                _ => None,
+            };
+            if let Some((field, parsed)) = gain {
+                if field.is_none() {
+                    *field = parsed;
+                }
+                continue;
+            }
+
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                Some(StandardTagKey::Lyrics) => &mut self.lyrics,
+                _ => continue,
+            };
+            field.get_or_insert(value);
+        }
+
+        let visuals = revision.visuals();
+        let front = visuals
+            .iter()
+            .find(|visual| visual.usage == Some(StandardVisualKey::FrontCover))
+            .or_else(|| visuals.first());
+        if let Some(visual) = front.filter(|_| self.cover_art.is_none()) {
+            self.cover_art = Some(Arc::from(&*visual.data));
+        }
+    }
+}
+
+/// Reads the title, artist, album, lyrics, ReplayGain and cover art tags of `path`, if it can be probed at all.
+pub fn read_metadata(path: &Path) -> TrackMetadata {
+    let mut metadata = TrackMetadata::default();
+
+    let Ok(file) = File::open(path) else {
+        return metadata;
+    };
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let Ok(mut probed) = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    ) else {
+        return metadata;
+    };
+
+    // Tags found while probing (e.g. ID3v2 in front of an MP3) come first,
+    // then whatever the container itself carries.
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        metadata.merge_revision(revision);
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        metadata.merge_revision(revision);
+    }
+
+    metadata
+}
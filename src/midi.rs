@@ -0,0 +1,450 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::{channel, Receiver};
+
+use midir::{Ignore, MidiInput, MidiInputConnection};
+use serde::{Deserialize, Serialize};
+
+use crate::hotkeys::HotkeyAction;
+
+const CLIENT_NAME: &str = "Aurora Visualizer";
+/// Port other programs connect to when no input port is chosen.
+pub const VIRTUAL_PORT: &str = "Aurora Visualizer In";
+pub const DEFAULT_PROFILE: &str = "Default";
+/// A CC crossing this from below counts as a button press.
+const PRESS_LEVEL: u8 = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlKind {
+    Note,
+    Cc,
+}
+
+/// A note or CC number on one MIDI channel.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MidiControl {
+    pub kind: ControlKind,
+    /// 0 to 15; shown as 1 to 16.
+    pub channel: u8,
+    pub number: u8,
+}
+
+impl MidiControl {
+    pub fn label(&self) -> String {
+        let kind = match self.kind {
+            ControlKind::Note => "Note",
+            ControlKind::Cc => "CC",
+        };
+        format!("{} {} (ch {})", kind, self.number, self.channel + 1)
+    }
+}
+
+/// Settings a knob or fader sets across their whole range.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiParameter {
+    BeatSensitivity,
+    Volume,
+    OverlayOpacity,
+}
+
+impl MidiParameter {
+    pub const ALL: [MidiParameter; 3] = [
+        MidiParameter::BeatSensitivity,
+        MidiParameter::Volume,
+        MidiParameter::OverlayOpacity,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MidiParameter::BeatSensitivity => "Beat sensitivity",
+            MidiParameter::Volume => "Volume",
+            MidiParameter::OverlayOpacity => "Overlay opacity",
+        }
+    }
+
+    /// The setting's value with the control at `position`, from 0 to 1.
+    pub fn value(self, position: f32) -> f32 {
+        match self {
+            MidiParameter::BeatSensitivity => position * 5.0,
+            MidiParameter::Volume | MidiParameter::OverlayOpacity => position,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiTarget {
+    /// Runs on a note-on or when a CC goes past half way.
+    Action(HotkeyAction),
+    /// Follows the control's value.
+    Parameter(MidiParameter),
+}
+
+impl MidiTarget {
+    pub fn all() -> Vec<MidiTarget> {
+        HotkeyAction::ALL
+            .into_iter()
+            .map(MidiTarget::Action)
+            .chain(MidiParameter::ALL.into_iter().map(MidiTarget::Parameter))
+            .collect()
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MidiTarget::Action(action) => action.label(),
+            MidiTarget::Parameter(parameter) => parameter.label(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MidiBinding {
+    pub control: MidiControl,
+    pub target: MidiTarget,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct MidiConfig {
+    pub enabled: bool,
+    /// Input port to connect to, by name. Empty opens a virtual port that
+    /// controllers are connected to with `aconnect` or a patchbay.
+    pub port: String,
+    /// Profile whose bindings are in use.
+    pub profile: String,
+    /// Bindings per controller setup, by profile name.
+    pub profiles: BTreeMap<String, Vec<MidiBinding>>,
+}
+
+impl Default for MidiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: String::new(),
+            profile: DEFAULT_PROFILE.to_string(),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), Vec::new())]),
+        }
+    }
+}
+
+impl MidiConfig {
+    pub fn bindings(&self) -> &[MidiBinding] {
+        self.profiles.get(&self.profile).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn control_for(&self, target: MidiTarget) -> Option<MidiControl> {
+        self.bindings()
+            .iter()
+            .find(|binding| binding.target == target)
+            .map(|binding| binding.control)
+    }
+
+    /// Binds `control` to `target` in the active profile, replacing whatever
+    /// `target` was bound to before. `None` unbinds it.
+    pub fn bind(&mut self, target: MidiTarget, control: Option<MidiControl>) {
+        let bindings = self.profiles.entry(self.profile.clone()).or_default();
+        bindings.retain(|binding| binding.target != target);
+        if let Some(control) = control {
+            bindings.push(MidiBinding { control, target });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiMessage {
+    pub control: MidiControl,
+    /// Velocity or CC value, 0 to 127. A note-off is 0.
+    pub value: u8,
+    /// A note-on, or a CC that just went past half way.
+    pub pressed: bool,
+}
+
+impl MidiMessage {
+    /// Note and CC messages; everything else is ignored.
+    pub fn parse(bytes: &[u8]) -> Option<(MidiControl, u8)> {
+        let [status, number, value, ..] = *bytes else {
+            return None;
+        };
+        let channel = status & 0x0F;
+        let (kind, value) = match status & 0xF0 {
+            0x80 => (ControlKind::Note, 0),
+            0x90 => (ControlKind::Note, value),
+            0xB0 => (ControlKind::Cc, value),
+            _ => return None,
+        };
+        let control = MidiControl {
+            kind,
+            channel,
+            number: number & 0x7F,
+        };
+        Some((control, value & 0x7F))
+    }
+
+    /// Knobs follow this; a note-off would snap them to zero.
+    pub fn position(&self) -> Option<f32> {
+        (self.control.kind == ControlKind::Cc || self.value > 0)
+            .then(|| self.value as f32 / 127.0)
+    }
+}
+
+/// Input port names, or why they couldn't be listed.
+pub fn input_ports() -> Result<Vec<String>, String> {
+    let input = MidiInput::new(CLIENT_NAME).map_err(|err| err.to_string())?;
+    Ok(input
+        .ports()
+        .iter()
+        .filter_map(|port| input.port_name(port).ok())
+        .collect())
+}
+
+/// The MIDI input the app listens on, reopened when its settings change.
+pub struct MidiLink {
+    /// Port the connection was opened for, or `None` when closed.
+    port: Option<String>,
+    connection: Option<MidiInputConnection<()>>,
+    messages: Option<Receiver<Vec<u8>>>,
+    /// Last value per control, to tell presses from held buttons.
+    levels: HashMap<MidiControl, u8>,
+    pub status: String,
+}
+
+impl MidiLink {
+    pub fn new() -> Self {
+        Self {
+            port: None,
+            connection: None,
+            messages: None,
+            levels: HashMap::new(),
+            status: String::new(),
+        }
+    }
+
+    /// Opens or closes the input where `config` differs from what it was opened with.
+    pub fn configure(&mut self, config: &MidiConfig) {
+        let port = config.enabled.then(|| config.port.clone());
+        if port == self.port {
+            return;
+        }
+        self.connection = None;
+        self.messages = None;
+        self.levels.clear();
+        self.status = String::new();
+        self.port = port.clone();
+        if let Some(port) = port {
+            if let Err(err) = self.open(&port) {
+                log::warn!("MIDI: {}", err);
+                self.status = err;
+            }
+        }
+    }
+
+    fn open(&mut self, port: &str) -> Result<(), String> {
+        let mut input = MidiInput::new(CLIENT_NAME).map_err(|err| err.to_string())?;
+        input.ignore(Ignore::All);
+        let (sender, messages) = channel();
+        let callback = move |_: u64, bytes: &[u8], _: &mut ()| {
+            let _ = sender.send(bytes.to_vec());
+        };
+
+        let connection = if port.is_empty() {
+            open_virtual(input, callback)?
+        } else {
+            let found = input
+                .ports()
+                .into_iter()
+                .find(|found| input.port_name(found).is_ok_and(|name| name == port))
+                .ok_or_else(|| format!("No MIDI input called {}", port))?;
+            input
+                .connect(&found, CLIENT_NAME, callback, ())
+                .map_err(|err| format!("Could not open {}: {}", port, err))?
+        };
+        self.connection = Some(connection);
+        self.messages = Some(messages);
+        Ok(())
+    }
+
+    /// Note and CC messages received since the last call.
+    pub fn messages(&mut self) -> Vec<MidiMessage> {
+        let Some(messages) = &self.messages else {
+            return Vec::new();
+        };
+        let parsed: Vec<(MidiControl, u8)> = messages
+            .try_iter()
+            .filter_map(|bytes| MidiMessage::parse(&bytes))
+            .collect();
+        parsed
+            .into_iter()
+            .map(|(control, value)| {
+                let previous = self.levels.insert(control, value).unwrap_or(0);
+                let pressed = match control.kind {
+                    ControlKind::Note => value > 0,
+                    ControlKind::Cc => value >= PRESS_LEVEL && previous < PRESS_LEVEL,
+                };
+                MidiMessage {
+                    control,
+                    value,
+                    pressed,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(unix)]
+fn open_virtual<F>(input: MidiInput, callback: F) -> Result<MidiInputConnection<()>, String>
+where
+    F: FnMut(u64, &[u8], &mut ()) + Send + 'static,
+{
+    use midir::os::unix::VirtualInput;
+
+    input
+        .create_virtual(VIRTUAL_PORT, callback, ())
+        .map_err(|err| format!("Could not create a virtual port: {}", err))
+}
+
+#[cfg(not(unix))]
+fn open_virtual<F>(_input: MidiInput, _callback: F) -> Result<MidiInputConnection<()>, String>
+where
+    F: FnMut(u64, &[u8], &mut ()) + Send + 'static,
+{
+    Err(String::from("Virtual ports need ALSA or CoreMIDI; choose an input port"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::Sender;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn note(number: u8) -> MidiControl {
+        MidiControl {
+            kind: ControlKind::Note,
+            channel: 0,
+            number,
+        }
+    }
+
+    fn cc(channel: u8, number: u8) -> MidiControl {
+        MidiControl {
+            kind: ControlKind::Cc,
+            channel,
+            number,
+        }
+    }
+
+    #[test]
+    fn parses_notes_and_ccs() {
+        assert_eq!(MidiMessage::parse(&[0x90, 60, 100]), Some((note(60), 100)));
+        // Running note-offs are often sent as a note-on with velocity zero.
+        assert_eq!(MidiMessage::parse(&[0x90, 60, 0]), Some((note(60), 0)));
+        assert_eq!(MidiMessage::parse(&[0x80, 60, 64]), Some((note(60), 0)));
+        assert_eq!(MidiMessage::parse(&[0xB3, 7, 127]), Some((cc(3, 7), 127)));
+    }
+
+    #[test]
+    fn ignores_other_messages() {
+        // Aftertouch, program change, pitch bend, clock, SysEx.
+        for bytes in [
+            &[0xA0, 60, 10][..],
+            &[0xC0, 5, 0],
+            &[0xE0, 0, 64],
+            &[0xF8, 0, 0],
+            &[0xF0, 0x7E, 0x7F, 0xF7],
+            &[0x90, 60],
+            &[],
+        ] {
+            assert_eq!(MidiMessage::parse(bytes), None, "{:02X?}", bytes);
+        }
+    }
+
+    #[test]
+    fn note_off_keeps_knob_position() {
+        let (control, value) = MidiMessage::parse(&[0x90, 60, 0]).unwrap();
+        let message = MidiMessage {
+            control,
+            value,
+            pressed: false,
+        };
+        assert_eq!(message.position(), None);
+        let (control, value) = MidiMessage::parse(&[0xB0, 1, 0]).unwrap();
+        assert_eq!(MidiMessage { control, value, pressed: false }.position(), Some(0.0));
+    }
+
+    /// A link fed by the returned sender instead of a port.
+    fn test_link() -> (MidiLink, Sender<Vec<u8>>) {
+        let (sender, messages) = channel();
+        let link = MidiLink {
+            messages: Some(messages),
+            ..MidiLink::new()
+        };
+        (link, sender)
+    }
+
+    fn presses(link: &mut MidiLink, sender: &Sender<Vec<u8>>, messages: &[[u8; 3]]) -> Vec<bool> {
+        for bytes in messages {
+            sender.send(bytes.to_vec()).unwrap();
+        }
+        link.messages().iter().map(|message| message.pressed).collect()
+    }
+
+    #[test]
+    fn cc_presses_on_rising_edge() {
+        let (mut link, sender) = test_link();
+        let levels = [0, 63, 64, 100, 127, 20, 90, 90];
+        let messages: Vec<[u8; 3]> = levels.iter().map(|value| [0xB0, 20, *value]).collect();
+        assert_eq!(
+            presses(&mut link, &sender, &messages),
+            [false, false, true, false, false, false, true, false]
+        );
+
+        // Each control and channel has its own level.
+        assert_eq!(presses(&mut link, &sender, &[[0xB1, 20, 127], [0xB0, 21, 127]]), [true, true]);
+        assert_eq!(presses(&mut link, &sender, &[[0xB0, 20, 127]]), [false]);
+    }
+
+    #[test]
+    fn every_note_on_is_a_press() {
+        let (mut link, sender) = test_link();
+        let messages = [[0x90, 36, 100], [0x90, 36, 0], [0x90, 36, 1], [0x90, 36, 90], [0x80, 36, 0]];
+        assert_eq!(
+            presses(&mut link, &sender, &messages),
+            [true, false, true, true, false]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs an ALSA sequencer"]
+    fn receives_on_virtual_port() {
+        let mut link = MidiLink::new();
+        link.configure(&MidiConfig {
+            enabled: true,
+            ..MidiConfig::default()
+        });
+        assert!(link.status.is_empty(), "{}", link.status);
+
+        let output = midir::MidiOutput::new("Aurora test").unwrap();
+        let port = output
+            .ports()
+            .into_iter()
+            .find(|port| output.port_name(port).is_ok_and(|name| name.contains(VIRTUAL_PORT)))
+            .unwrap();
+        let mut connection = output.connect(&port, "Aurora test").unwrap();
+        connection.send(&[0x90, 60, 100]).unwrap();
+        connection.send(&[0xB2, 7, 127]).unwrap();
+
+        let mut received = Vec::new();
+        let started = Instant::now();
+        while received.len() < 2 && started.elapsed() < Duration::from_secs(5) {
+            received.extend(link.messages());
+            thread::sleep(Duration::from_millis(10));
+        }
+        let received: Vec<_> = received
+            .iter()
+            .map(|message| (message.control, message.value, message.pressed))
+            .collect();
+        assert_eq!(received, [(note(60), 100, true), (cc(2, 7), 127, true)]);
+    }
+}
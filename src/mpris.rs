@@ -0,0 +1,553 @@
+use std::collections::HashMap;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use zbus::blocking::object_server::InterfaceRef;
+use zbus::blocking::Connection;
+use zbus::interface;
+use zbus::object_server::SignalContext;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+
+use crate::metadata::read_metadata;
+use crate::playback::Playback;
+
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.aurora_visualizer";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+/// A position further than this from where playback should be counts as a seek.
+const SEEK_TOLERANCE_US: i64 = 500_000;
+
+/// Requests from D-Bus clients, carried out on the UI thread.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MprisCommand {
+    Raise,
+    Quit,
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    /// Relative seek in microseconds.
+    Seek(i64),
+    /// Absolute position in microseconds.
+    SetPosition(i64),
+    SetVolume(f64),
+    Open(PathBuf),
+}
+
+/// What the player reports to D-Bus clients, refreshed every frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct PlayerState {
+    status: &'static str,
+    track_id: String,
+    path: Option<PathBuf>,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    length_us: i64,
+    position_us: i64,
+    volume: f64,
+    can_go_next: bool,
+    can_go_previous: bool,
+}
+
+struct Root {
+    commands: Sender<MprisCommand>,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {
+        let _ = self.commands.send(MprisCommand::Raise);
+    }
+
+    fn quit(&self) {
+        let _ = self.commands.send(MprisCommand::Quit);
+    }
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        String::from("Music Visualizer")
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec![String::from("file")]
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        ["audio/mpeg", "audio/flac", "audio/ogg", "audio/wav"]
+            .map(String::from)
+            .to_vec()
+    }
+}
+
+struct Player {
+    commands: Sender<MprisCommand>,
+    state: Arc<Mutex<PlayerState>>,
+}
+
+impl Player {
+    fn send(&self, command: MprisCommand) {
+        let _ = self.commands.send(command);
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) {
+        self.send(MprisCommand::Next);
+    }
+
+    fn previous(&self) {
+        self.send(MprisCommand::Previous);
+    }
+
+    fn pause(&self) {
+        self.send(MprisCommand::Pause);
+    }
+
+    fn play_pause(&self) {
+        self.send(MprisCommand::PlayPause);
+    }
+
+    fn stop(&self) {
+        self.send(MprisCommand::Stop);
+    }
+
+    fn play(&self) {
+        self.send(MprisCommand::Play);
+    }
+
+    fn seek(&self, offset: i64) {
+        self.send(MprisCommand::Seek(offset));
+    }
+
+    /// Ignored unless `track_id` is still the current track, as the spec asks.
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        if self.state.lock().unwrap().track_id == track_id.as_str() && position >= 0 {
+            self.send(MprisCommand::SetPosition(position));
+        }
+    }
+
+    fn open_uri(&self, uri: &str) {
+        if let Some(path) = uri.strip_prefix("file://") {
+            self.send(MprisCommand::Open(PathBuf::from(percent_decode(path))));
+        }
+    }
+
+    #[zbus(signal)]
+    async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        self.state.lock().unwrap().status.to_string()
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        metadata(&self.state.lock().unwrap())
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.state.lock().unwrap().volume
+    }
+
+    #[zbus(property)]
+    fn set_volume(&mut self, volume: f64) {
+        let volume = volume.clamp(0.0, 1.0);
+        self.state.lock().unwrap().volume = volume;
+        self.send(MprisCommand::SetVolume(volume));
+    }
+
+    /// Changes continuously, so clients poll it instead of waiting for signals.
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        self.state.lock().unwrap().position_us
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        self.state.lock().unwrap().can_go_next
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        self.state.lock().unwrap().can_go_previous
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn metadata(state: &PlayerState) -> HashMap<String, OwnedValue> {
+    let mut metadata = HashMap::new();
+    let mut insert = |key: &str, value: Value| {
+        metadata.insert(key.to_string(), value.try_to_owned().unwrap());
+    };
+    insert(
+        "mpris:trackid",
+        Value::from(ObjectPath::try_from(state.track_id.as_str()).unwrap()),
+    );
+    let Some(path) = &state.path else {
+        return metadata;
+    };
+    insert("mpris:length", Value::from(state.length_us));
+    insert("xesam:url", Value::from(format!("file://{}", percent_encode(path))));
+    let title = state.title.clone().unwrap_or_else(|| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    insert("xesam:title", Value::from(title));
+    if let Some(artist) = &state.artist {
+        insert("xesam:artist", Value::from(vec![artist.clone()]));
+    }
+    if let Some(album) = &state.album {
+        insert("xesam:album", Value::from(album.clone()));
+    }
+    metadata
+}
+
+/// Escapes a path for a file URI: everything but unreserved characters and
+/// `/` becomes `%XX`, the inverse of `percent_decode`.
+fn percent_encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Decodes `%XX` escapes in a file URI path.
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// MPRIS2 service on the session bus, so desktop media keys and `playerctl`
+/// control playback. It follows `DBUS_SESSION_BUS_ADDRESS`, so it can be
+/// tried against a private `dbus-daemon --session --print-address`.
+pub struct MprisServer {
+    connection: Connection,
+    player: InterfaceRef<Player>,
+    state: Arc<Mutex<PlayerState>>,
+    commands: Receiver<MprisCommand>,
+    /// Where playback was at the last update, to notice seeks.
+    last_position: (i64, Instant),
+}
+
+impl MprisServer {
+    /// Connects to the session bus, or returns why it couldn't.
+    pub fn new() -> zbus::Result<Self> {
+        Self::serve(Connection::session()?)
+    }
+
+    fn serve(connection: Connection) -> zbus::Result<Self> {
+        let (sender, commands) = channel();
+        let state = Arc::new(Mutex::new(PlayerState {
+            status: "Stopped",
+            track_id: NO_TRACK.to_string(),
+            volume: 1.0,
+            ..PlayerState::default()
+        }));
+        connection.object_server().at(
+            OBJECT_PATH,
+            Root {
+                commands: sender.clone(),
+            },
+        )?;
+        connection.object_server().at(
+            OBJECT_PATH,
+            Player {
+                commands: sender,
+                state: state.clone(),
+            },
+        )?;
+        // A second instance takes a unique name, as the spec suggests.
+        if connection.request_name(BUS_NAME).is_err() {
+            connection.request_name(format!("{}.instance{}", BUS_NAME, std::process::id()))?;
+        }
+        let player = connection
+            .object_server()
+            .interface::<_, Player>(OBJECT_PATH)?;
+        log::info!("MPRIS service running as {}", BUS_NAME);
+
+        Ok(Self {
+            connection,
+            player,
+            state,
+            commands,
+            last_position: (0, Instant::now()),
+        })
+    }
+
+    /// Commands received since the last call.
+    pub fn commands(&self) -> Vec<MprisCommand> {
+        self.commands.try_iter().collect()
+    }
+
+    /// Publishes the player's current state and signals what changed.
+    pub fn update(&mut self, playback: &Playback, volume: f32) {
+        let mut state = self.state.lock().unwrap().clone();
+
+        state.status = if playback.is_playing() {
+            "Playing"
+        } else if playback.current_track().is_some() && !playback.is_finished() {
+            "Paused"
+        } else {
+            "Stopped"
+        };
+        let path = playback.current_track().cloned();
+        if path != state.path {
+            let metadata = path.as_deref().map(read_metadata).unwrap_or_default();
+            state.title = metadata.title;
+            state.artist = metadata.artist;
+            state.album = metadata.album;
+            state.path = path;
+        }
+        state.track_id = match playback.current_track_index {
+            Some(index) if state.path.is_some() => format!("{}/track/{}", OBJECT_PATH, index),
+            _ => NO_TRACK.to_string(),
+        };
+        state.length_us = (playback.duration_secs() * 1e6) as i64;
+        state.position_us = (playback.position_secs() * 1e6) as i64;
+        state.volume = volume as f64;
+        state.can_go_next = playback.has_next_track();
+        state.can_go_previous = playback.current_track().is_some();
+        self.publish(state);
+    }
+
+    /// Replaces the reported state, signalling what changed.
+    fn publish(&mut self, state: PlayerState) {
+        let previous = self.state.lock().unwrap().clone();
+        let (last, at) = self.last_position;
+        let expected = if previous.status == "Playing" {
+            last + at.elapsed().as_micros() as i64
+        } else {
+            last
+        };
+        let seeked = state.track_id == previous.track_id
+            && (state.position_us - expected).abs() > SEEK_TOLERANCE_US;
+        self.last_position = (state.position_us, Instant::now());
+
+        *self.state.lock().unwrap() = state.clone();
+        if let Err(err) = self.notify(&previous, &state, seeked) {
+            log::warn!("Failed to send MPRIS signals: {}", err);
+        }
+    }
+
+    fn notify(&self, previous: &PlayerState, state: &PlayerState, seeked: bool) -> zbus::Result<()> {
+        let context = self.player.signal_context();
+        let player = self.player.get();
+        zbus::block_on(async {
+            if state.status != previous.status {
+                player.playback_status_changed(context).await?;
+            }
+            if (&state.track_id, &state.title, &state.artist, &state.album, state.length_us)
+                != (
+                    &previous.track_id,
+                    &previous.title,
+                    &previous.artist,
+                    &previous.album,
+                    previous.length_us,
+                )
+            {
+                player.metadata_changed(context).await?;
+            }
+            if state.volume != previous.volume {
+                player.volume_changed(context).await?;
+            }
+            if state.can_go_next != previous.can_go_next {
+                player.can_go_next_changed(context).await?;
+            }
+            if state.can_go_previous != previous.can_go_previous {
+                player.can_go_previous_changed(context).await?;
+            }
+            if seeked {
+                Player::seeked(context, state.position_us).await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Drop for MprisServer {
+    fn drop(&mut self) {
+        let _ = self.connection.release_name(BUS_NAME);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+
+    use zbus::blocking::{connection, Proxy};
+
+    use super::*;
+
+    #[test]
+    fn encodes_file_urls() {
+        let path = Path::new("/music/AC DC/100% #1?.flac");
+        assert_eq!(percent_encode(path), "/music/AC%20DC/100%25%20%231%3F.flac");
+        assert_eq!(percent_decode(&percent_encode(path)), path.to_str().unwrap());
+
+        let path = Path::new("/musique/Éric/かな.ogg");
+        assert_eq!(percent_decode(&percent_encode(path)), path.to_str().unwrap());
+    }
+
+    /// A private session bus that goes away with the test.
+    struct Bus(Child);
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    fn private_bus() -> (Bus, String) {
+        let mut child = Command::new("dbus-daemon")
+            .args(["--session", "--print-address", "--nofork"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut address = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        (Bus(child), address.trim().to_string())
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn controls_and_signals_over_dbus() {
+        let (_bus, address) = private_bus();
+        let connect = || connection::Builder::address(address.as_str()).unwrap().build().unwrap();
+        let mut server = MprisServer::serve(connect()).unwrap();
+
+        let client = connect();
+        let proxy = |interface| Proxy::new(&client, BUS_NAME, OBJECT_PATH, interface).unwrap();
+        let player = proxy("org.mpris.MediaPlayer2.Player");
+        let properties = proxy("org.freedesktop.DBus.Properties");
+        let mut changes = properties.receive_signal("PropertiesChanged").unwrap();
+
+        player.call_method("PlayPause", &()).unwrap();
+        player.call_method("Seek", &(-5_000_000i64)).unwrap();
+        player.set_property("Volume", 1.5f64).unwrap();
+        // Replies come after the handlers ran, so the commands are queued by now.
+        assert_eq!(
+            server.commands(),
+            [
+                MprisCommand::PlayPause,
+                MprisCommand::Seek(-5_000_000),
+                MprisCommand::SetVolume(1.0),
+            ]
+        );
+
+        server.publish(PlayerState {
+            status: "Playing",
+            track_id: format!("{}/track/0", OBJECT_PATH),
+            path: Some(PathBuf::from("/music/A Song #1.flac")),
+            title: Some(String::from("A Song")),
+            length_us: 180_000_000,
+            volume: 1.0,
+            ..PlayerState::default()
+        });
+
+        let mut changed = HashMap::new();
+        while !(changed.contains_key("PlaybackStatus") && changed.contains_key("Metadata")) {
+            let message = changes.next().unwrap();
+            let (interface, properties, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+                message.body().deserialize().unwrap();
+            assert_eq!(interface, "org.mpris.MediaPlayer2.Player");
+            changed.extend(properties);
+        }
+        assert_eq!(
+            String::try_from(changed["PlaybackStatus"].try_clone().unwrap()).unwrap(),
+            "Playing"
+        );
+        let metadata: HashMap<String, OwnedValue> =
+            changed["Metadata"].try_clone().unwrap().try_into().unwrap();
+        assert_eq!(
+            String::try_from(metadata["xesam:url"].try_clone().unwrap()).unwrap(),
+            "file:///music/A%20Song%20%231.flac"
+        );
+    }
+}
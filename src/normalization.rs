@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::ReplayGain;
+use crate::track_analysis::TrackAnalysis;
+
+/// The level ReplayGain 2 tags are computed against.
+const REPLAY_GAIN_REFERENCE: f32 = -18.0;
+/// Highest true peak the output is allowed to reach when preventing clipping.
+const PEAK_CEILING_DB: f32 = -1.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GainSource {
+    Off,
+    /// ReplayGain track tags, or the measured loudness of untagged files.
+    Track,
+    /// ReplayGain album tags, falling back to track tags and then to measured loudness.
+    Album,
+    /// Always the measured EBU R128 loudness, ignoring tags.
+    Measured,
+}
+
+impl GainSource {
+    pub const ALL: [GainSource; 4] = [
+        GainSource::Off,
+        GainSource::Track,
+        GainSource::Album,
+        GainSource::Measured,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            GainSource::Off => "Off",
+            GainSource::Track => "ReplayGain (track)",
+            GainSource::Album => "ReplayGain (album)",
+            GainSource::Measured => "Measured loudness",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct NormalizationConfig {
+    pub source: GainSource,
+    /// Loudness every track is brought to, in LUFS.
+    pub target_lufs: f32,
+    /// Lower the output gain where the track's peak would otherwise clip.
+    pub prevent_clipping: bool,
+    /// Normalize what the listener hears.
+    pub normalize_output: bool,
+    /// Extra output gain in dB, applied whether or not the output is normalized.
+    pub output_trim_db: f32,
+    /// Normalize what projectM receives, so the visuals react the same to quiet and loud tracks.
+    pub normalize_visuals: bool,
+    /// Extra gain in dB for the audio projectM receives.
+    pub visual_trim_db: f32,
+}
+
+impl Default for NormalizationConfig {
+    fn default() -> Self {
+        Self {
+            source: GainSource::Track,
+            target_lufs: REPLAY_GAIN_REFERENCE,
+            prevent_clipping: true,
+            normalize_output: false,
+            output_trim_db: 0.0,
+            normalize_visuals: true,
+            visual_trim_db: 0.0,
+        }
+    }
+}
+
+/// Linear gains for the output and for projectM.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gains {
+    pub output: f32,
+    pub visual: f32,
+}
+
+impl Default for Gains {
+    fn default() -> Self {
+        Self {
+            output: 1.0,
+            visual: 1.0,
+        }
+    }
+}
+
+/// A normalization gain in dB and where it came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackGain {
+    pub gain_db: f32,
+    /// Linear peak the gain is applied to, if known.
+    pub peak: Option<f32>,
+    pub from_tags: bool,
+}
+
+impl NormalizationConfig {
+    /// The gain that brings the track to `target_lufs`, from tags or the
+    /// analysis depending on `source`. `None` until something is known.
+    pub fn track_gain(
+        &self,
+        replay_gain: &ReplayGain,
+        analysis: Option<&TrackAnalysis>,
+    ) -> Option<TrackGain> {
+        let adjust = self.target_lufs - REPLAY_GAIN_REFERENCE;
+        let tagged = |gain: Option<f32>, peak: Option<f32>| {
+            gain.map(|gain| TrackGain {
+                gain_db: gain + adjust,
+                peak,
+                from_tags: true,
+            })
+        };
+        let measured = || {
+            let analysis = analysis?;
+            Some(TrackGain {
+                gain_db: self.target_lufs - analysis.integrated_lufs?,
+                peak: analysis.true_peak_db.map(|db| 10f32.powf(db / 20.0)),
+                from_tags: false,
+            })
+        };
+
+        match self.source {
+            GainSource::Off => None,
+            GainSource::Track => {
+                tagged(replay_gain.track_gain, replay_gain.track_peak).or_else(measured)
+            }
+            GainSource::Album => tagged(replay_gain.album_gain, replay_gain.album_peak)
+                .or_else(|| tagged(replay_gain.track_gain, replay_gain.track_peak))
+                .or_else(measured),
+            GainSource::Measured => measured(),
+        }
+    }
+
+    pub fn gains(&self, track_gain: Option<TrackGain>) -> Gains {
+        let mut output_db = self.output_trim_db;
+        let mut visual_db = self.visual_trim_db;
+        if let Some(track_gain) = track_gain {
+            if self.normalize_output {
+                let mut gain_db = track_gain.gain_db;
+                if self.prevent_clipping {
+                    if let Some(peak) = track_gain.peak.filter(|peak| *peak > 0.0) {
+                        gain_db = gain_db.min(PEAK_CEILING_DB - 20.0 * peak.log10());
+                    }
+                }
+                output_db += gain_db;
+            }
+            // projectM takes floats, so the visual path can't clip.
+            if self.normalize_visuals {
+                visual_db += track_gain.gain_db;
+            }
+        }
+        let linear = |db: f32| 10f32.powf(db / 20.0);
+        Gains {
+            output: linear(output_db),
+            visual: linear(visual_db),
+        }
+    }
+}
@@ -0,0 +1,648 @@
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::remote_control::{OverlaySlot, RemoteCommand};
+
+/// Largest UDP datagram accepted.
+const MAX_PACKET: usize = 65536;
+/// How soon a listener notices it should close its socket.
+const STOP_POLL: Duration = Duration::from_millis(100);
+/// Bundles nested deeper than this are dropped, so a crafted packet can't
+/// recurse without bound.
+const MAX_BUNDLE_DEPTH: usize = 8;
+
+/// What an incoming OSC address does.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OscAction {
+    NextPreset,
+    PreviousPreset,
+    /// Takes the preset's path or file name.
+    LoadPreset,
+    /// Favorites the current preset.
+    Favorite,
+    Block,
+    /// Takes the text for the title overlay.
+    OverlayText,
+    /// A tap of the beat.
+    Beat,
+    Play,
+    Pause,
+    NextTrack,
+    PreviousTrack,
+    StartRecording,
+    StopRecording,
+}
+
+impl OscAction {
+    pub const ALL: [OscAction; 13] = [
+        OscAction::NextPreset,
+        OscAction::PreviousPreset,
+        OscAction::LoadPreset,
+        OscAction::Favorite,
+        OscAction::Block,
+        OscAction::OverlayText,
+        OscAction::Beat,
+        OscAction::Play,
+        OscAction::Pause,
+        OscAction::NextTrack,
+        OscAction::PreviousTrack,
+        OscAction::StartRecording,
+        OscAction::StopRecording,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            OscAction::NextPreset => "Next preset",
+            OscAction::PreviousPreset => "Previous preset",
+            OscAction::LoadPreset => "Load preset (name)",
+            OscAction::Favorite => "Favorite preset",
+            OscAction::Block => "Block preset",
+            OscAction::OverlayText => "Overlay text (text)",
+            OscAction::Beat => "Tap beat",
+            OscAction::Play => "Play",
+            OscAction::Pause => "Pause",
+            OscAction::NextTrack => "Next track",
+            OscAction::PreviousTrack => "Previous track",
+            OscAction::StartRecording => "Start recording",
+            OscAction::StopRecording => "Stop recording",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OscMapping {
+    pub address: String,
+    pub action: OscAction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct OscConfig {
+    pub enabled: bool,
+    /// UDP port to listen on, on all interfaces so controllers on the network reach it.
+    pub listen_port: u16,
+    pub mappings: Vec<OscMapping>,
+    /// `host:port` that beats and preset changes are sent to, or empty to send nothing.
+    pub send_to: String,
+    /// Sent on every beat with the tempo as a float and 1 or 0 for a downbeat.
+    pub beat_address: String,
+    /// Sent with the preset's file name whenever it changes.
+    pub preset_address: String,
+}
+
+impl Default for OscConfig {
+    fn default() -> Self {
+        let mapping = |address: &str, action| OscMapping {
+            address: address.to_string(),
+            action,
+        };
+        Self {
+            enabled: false,
+            listen_port: 9000,
+            mappings: vec![
+                mapping("/preset/next", OscAction::NextPreset),
+                mapping("/preset/prev", OscAction::PreviousPreset),
+                mapping("/preset/load", OscAction::LoadPreset),
+                mapping("/preset/favorite", OscAction::Favorite),
+                mapping("/preset/block", OscAction::Block),
+                mapping("/overlay/text", OscAction::OverlayText),
+                mapping("/beat", OscAction::Beat),
+                mapping("/track/play", OscAction::Play),
+                mapping("/track/pause", OscAction::Pause),
+                mapping("/track/next", OscAction::NextTrack),
+                mapping("/track/prev", OscAction::PreviousTrack),
+                mapping("/record/start", OscAction::StartRecording),
+                mapping("/record/stop", OscAction::StopRecording),
+            ],
+            send_to: String::new(),
+            beat_address: String::from("/aurora/beat"),
+            preset_address: String::from("/aurora/preset"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Blob(Vec<u8>),
+    Bool(bool),
+    Nil,
+    Impulse,
+}
+
+impl OscArg {
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            OscArg::Int(value) => Some(value as f64),
+            OscArg::Long(value) => Some(value as f64),
+            OscArg::Float(value) => Some(value as f64),
+            OscArg::Double(value) => Some(value),
+            OscArg::Bool(value) => Some(value as u8 as f64),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            OscArg::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut packet = Vec::new();
+        write_string(&mut packet, &self.address);
+        let mut tags = String::from(",");
+        let mut data = Vec::new();
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(value) => {
+                    tags.push('i');
+                    data.extend(value.to_be_bytes());
+                }
+                OscArg::Long(value) => {
+                    tags.push('h');
+                    data.extend(value.to_be_bytes());
+                }
+                OscArg::Float(value) => {
+                    tags.push('f');
+                    data.extend(value.to_be_bytes());
+                }
+                OscArg::Double(value) => {
+                    tags.push('d');
+                    data.extend(value.to_be_bytes());
+                }
+                OscArg::String(value) => {
+                    tags.push('s');
+                    write_string(&mut data, value);
+                }
+                OscArg::Blob(value) => {
+                    tags.push('b');
+                    data.extend((value.len() as u32).to_be_bytes());
+                    data.extend(value);
+                    data.resize(data.len().next_multiple_of(4), 0);
+                }
+                OscArg::Bool(value) => tags.push(if *value { 'T' } else { 'F' }),
+                OscArg::Nil => tags.push('N'),
+                OscArg::Impulse => tags.push('I'),
+            }
+        }
+        write_string(&mut packet, &tags);
+        packet.extend(data);
+        packet
+    }
+
+    /// What the message asks for under `mappings`, if anything. Buttons on
+    /// most controllers send 1 when pressed and 0 when released; the release
+    /// is ignored for actions that take no value.
+    pub fn command(&self, mappings: &[OscMapping]) -> Option<RemoteCommand> {
+        let action = mappings
+            .iter()
+            .find(|mapping| mapping.address == self.address)?
+            .action;
+        let number = self.args.first().and_then(OscArg::as_f64);
+        let text = self.args.first().and_then(OscArg::as_str).map(str::to_string);
+        let pressed = number.is_none_or(|value| value != 0.0);
+
+        let command = match action {
+            OscAction::LoadPreset => RemoteCommand::LoadPreset { preset: text? },
+            OscAction::OverlayText => RemoteCommand::SetOverlayText {
+                text: text?,
+                element: OverlaySlot::Title,
+            },
+            _ if !pressed => return None,
+            OscAction::Favorite => RemoteCommand::Favorite { favorite: true },
+            OscAction::NextPreset => RemoteCommand::NextPreset,
+            OscAction::PreviousPreset => RemoteCommand::PreviousPreset,
+            OscAction::Block => RemoteCommand::Block,
+            OscAction::Beat => RemoteCommand::Tap,
+            OscAction::Play => RemoteCommand::Play { index: None },
+            OscAction::Pause => RemoteCommand::Pause,
+            OscAction::NextTrack => RemoteCommand::NextTrack,
+            OscAction::PreviousTrack => RemoteCommand::PreviousTrack,
+            OscAction::StartRecording => RemoteCommand::StartRecording { offline: false },
+            OscAction::StopRecording => RemoteCommand::StopRecording,
+        };
+        Some(command)
+    }
+}
+
+/// Appends a NUL-terminated string padded to a multiple of four bytes.
+fn write_string(packet: &mut Vec<u8>, value: &str) {
+    packet.extend(value.as_bytes());
+    packet.push(0);
+    packet.resize(packet.len().next_multiple_of(4), 0);
+}
+
+/// Reads OSC's big-endian, four-byte-aligned fields.
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        let bytes = self.data.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len.next_multiple_of(4);
+        Some(bytes)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let rest = self.data.get(self.offset..)?;
+        let len = rest.iter().position(|byte| *byte == 0)?;
+        let value = String::from_utf8_lossy(&rest[..len]).to_string();
+        self.offset += (len + 1).next_multiple_of(4);
+        Some(value)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.bytes(N).and_then(|bytes| bytes.try_into().ok())
+    }
+}
+
+/// Every message in a packet, looking inside bundles. Malformed messages are skipped.
+pub fn decode_packet(packet: &[u8]) -> Vec<OscMessage> {
+    let mut messages = Vec::new();
+    decode_into(packet, 0, &mut messages);
+    messages
+}
+
+fn decode_into(packet: &[u8], depth: usize, messages: &mut Vec<OscMessage>) {
+    let mut reader = Reader {
+        data: packet,
+        offset: 0,
+    };
+    if packet.starts_with(b"#bundle\0") {
+        if depth >= MAX_BUNDLE_DEPTH {
+            return;
+        }
+        // Bundles carry a time tag; everything in them is handled right away.
+        reader.offset = 16;
+        while let Some(size) = reader.array::<4>() {
+            match reader.bytes(u32::from_be_bytes(size) as usize) {
+                Some(element) => decode_into(element, depth + 1, messages),
+                None => break,
+            }
+        }
+        return;
+    }
+    if let Some(message) = decode_message(&mut reader) {
+        messages.push(message);
+    }
+}
+
+fn decode_message(reader: &mut Reader) -> Option<OscMessage> {
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        return None;
+    }
+    // Very old senders omit the type tags.
+    let tags = if reader.offset < reader.data.len() {
+        reader.string()?
+    } else {
+        String::from(",")
+    };
+    let mut args = Vec::new();
+    for tag in tags.strip_prefix(',')?.chars() {
+        args.push(match tag {
+            'i' => OscArg::Int(i32::from_be_bytes(reader.array()?)),
+            'h' => OscArg::Long(i64::from_be_bytes(reader.array()?)),
+            'f' => OscArg::Float(f32::from_be_bytes(reader.array()?)),
+            'd' => OscArg::Double(f64::from_be_bytes(reader.array()?)),
+            's' | 'S' => OscArg::String(reader.string()?),
+            'b' => {
+                let len = u32::from_be_bytes(reader.array()?) as usize;
+                OscArg::Blob(reader.bytes(len)?.to_vec())
+            }
+            'T' => OscArg::Bool(true),
+            'F' => OscArg::Bool(false),
+            'N' => OscArg::Nil,
+            'I' => OscArg::Impulse,
+            _ => return None,
+        });
+    }
+    Some(OscMessage { address, args })
+}
+
+/// A socket thread receiving OSC on one port.
+struct Listener {
+    port: u16,
+    messages: Receiver<OscMessage>,
+    /// Tells the thread to let go of the port.
+    stop: Arc<AtomicBool>,
+}
+
+impl Listener {
+    fn start(port: u16) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        socket.set_read_timeout(Some(STOP_POLL))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, messages) = channel();
+        let stop_clone = stop.clone();
+        thread::spawn(move || {
+            let mut buffer = vec![0; MAX_PACKET];
+            while !stop_clone.load(Ordering::Relaxed) {
+                let len = match socket.recv(&mut buffer) {
+                    Ok(len) => len,
+                    Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                        continue;
+                    }
+                    Err(err) => {
+                        log::warn!("OSC receive failed: {}", err);
+                        return;
+                    }
+                };
+                for message in decode_packet(&buffer[..len]) {
+                    let _ = sender.send(message);
+                }
+            }
+        });
+        log::info!("OSC listening on UDP port {}", port);
+        Ok(Self {
+            port,
+            messages,
+            stop,
+        })
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Listens for OSC from controllers and sends beats and preset changes to the
+/// rest of the rig. Reconfigured in place when its settings change.
+pub struct OscLink {
+    config: OscConfig,
+    listener: Option<Listener>,
+    sender: Option<UdpSocket>,
+    last_preset: Option<String>,
+    pub status: String,
+}
+
+impl OscLink {
+    pub fn new() -> Self {
+        Self {
+            config: OscConfig {
+                enabled: false,
+                ..OscConfig::default()
+            },
+            listener: None,
+            sender: None,
+            last_preset: None,
+            status: String::new(),
+        }
+    }
+
+    /// Opens or closes sockets where `config` differs from what they were opened with.
+    pub fn configure(&mut self, config: &OscConfig) {
+        if *config == self.config {
+            return;
+        }
+        let old = std::mem::replace(&mut self.config, config.clone());
+        self.status = String::new();
+
+        let port = config.enabled.then_some(config.listen_port);
+        if port != self.listener.as_ref().map(|listener| listener.port) {
+            self.listener = None;
+            if let Some(port) = port {
+                match Listener::start(port) {
+                    Ok(listener) => self.listener = Some(listener),
+                    Err(err) => self.fail(format!("Could not listen on port {}: {}", port, err)),
+                }
+            }
+        }
+
+        let target = |config: &OscConfig| {
+            (config.enabled && !config.send_to.is_empty()).then(|| config.send_to.clone())
+        };
+        if target(config) != target(&old) || self.sender.is_none() {
+            self.sender = None;
+            if let Some(target) = target(config) {
+                let socket = UdpSocket::bind(("0.0.0.0", 0))
+                    .and_then(|socket| socket.connect(&target).map(|()| socket));
+                match socket {
+                    Ok(socket) => self.sender = Some(socket),
+                    Err(err) => self.fail(format!("Could not send to {}: {}", target, err)),
+                }
+            }
+        }
+    }
+
+    fn fail(&mut self, status: String) {
+        log::warn!("OSC: {}", status);
+        self.status = status;
+    }
+
+    /// Commands from the messages received since the last call.
+    pub fn commands(&self) -> Vec<RemoteCommand> {
+        let Some(listener) = &self.listener else {
+            return Vec::new();
+        };
+        listener
+            .messages
+            .try_iter()
+            .filter_map(|message| message.command(&self.config.mappings))
+            .collect()
+    }
+
+    pub fn send_beat(&self, bpm: f32, downbeat: bool) {
+        self.send(OscMessage {
+            address: self.config.beat_address.clone(),
+            args: vec![OscArg::Float(bpm), OscArg::Int(downbeat as i32)],
+        });
+    }
+
+    /// Announces `preset` if it isn't the one announced last.
+    pub fn send_preset(&mut self, preset: Option<&str>) {
+        if preset == self.last_preset.as_deref() {
+            return;
+        }
+        self.last_preset = preset.map(str::to_string);
+        let Some(preset) = preset else {
+            return;
+        };
+        let name = Path::new(preset)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| preset.to_string());
+        self.send(OscMessage {
+            address: self.config.preset_address.clone(),
+            args: vec![OscArg::String(name)],
+        });
+    }
+
+    fn send(&self, message: OscMessage) {
+        if let Some(socket) = &self.sender {
+            // Nobody listening is normal on a rig that is still being set up.
+            let _ = socket.send(&message.encode());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn message(address: &str, args: Vec<OscArg>) -> OscMessage {
+        OscMessage {
+            address: address.to_string(),
+            args,
+        }
+    }
+
+    fn bundle(elements: &[Vec<u8>]) -> Vec<u8> {
+        let mut packet = b"#bundle\0".to_vec();
+        // Time tag 1 means "immediately".
+        packet.extend(1u64.to_be_bytes());
+        for element in elements {
+            packet.extend((element.len() as u32).to_be_bytes());
+            packet.extend(element);
+        }
+        packet
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            message("/preset/next", vec![]),
+            message("/a", vec![OscArg::Int(-7), OscArg::Long(1 << 40)]),
+            message("/floats", vec![OscArg::Float(0.5), OscArg::Double(-1e100)]),
+            // Strings of every length modulo four, to cover the padding.
+            message(
+                "/overlay/text",
+                vec![
+                    OscArg::String(String::new()),
+                    OscArg::String(String::from("abc")),
+                    OscArg::String(String::from("abcd")),
+                    OscArg::String(String::from("héllo")),
+                ],
+            ),
+            message("/blob", vec![OscArg::Blob(vec![1, 2, 3, 4, 5]), OscArg::Blob(vec![])]),
+            message(
+                "/flags",
+                vec![OscArg::Bool(true), OscArg::Bool(false), OscArg::Nil, OscArg::Impulse],
+            ),
+        ];
+        for message in messages {
+            assert_eq!(decode_packet(&message.encode()), [message]);
+        }
+    }
+
+    #[test]
+    fn bundles_are_unpacked() {
+        let first = message("/beat", vec![OscArg::Int(1)]);
+        let second = message("/preset/load", vec![OscArg::String(String::from("Rain"))]);
+        let third = message("/track/next", vec![]);
+        let packet = bundle(&[first.encode(), bundle(&[second.encode(), third.encode()])]);
+        assert_eq!(decode_packet(&packet), [first, second, third]);
+    }
+
+    #[test]
+    fn deep_bundles_are_dropped() {
+        let inner = message("/beat", vec![]);
+        let nest = |depth| (0..depth).fold(inner.encode(), |packet, _| bundle(&[packet]));
+
+        assert_eq!(decode_packet(&nest(MAX_BUNDLE_DEPTH)), [message("/beat", vec![])]);
+        assert!(decode_packet(&nest(MAX_BUNDLE_DEPTH + 1)).is_empty());
+        assert!(decode_packet(&nest(10_000)).is_empty());
+    }
+
+    #[test]
+    fn malformed_packets_are_skipped() {
+        let mut truncated = message("/a", vec![OscArg::Int(1)]).encode();
+        truncated.truncate(truncated.len() - 2);
+        assert!(decode_packet(&truncated).is_empty());
+        assert!(decode_packet(b"no slash\0\0\0\0").is_empty());
+        assert!(decode_packet(&[]).is_empty());
+
+        // A bundle element claiming more bytes than are left ends the bundle.
+        let mut packet = bundle(&[message("/beat", vec![]).encode()]);
+        packet.extend(1000u32.to_be_bytes());
+        assert_eq!(decode_packet(&packet), [message("/beat", vec![])]);
+    }
+
+    #[test]
+    fn button_release_is_ignored() {
+        let mappings = OscConfig::default().mappings;
+        let press = message("/preset/next", vec![OscArg::Float(1.0)]);
+        let release = message("/preset/next", vec![OscArg::Float(0.0)]);
+        assert_eq!(press.command(&mappings), Some(RemoteCommand::NextPreset));
+        assert_eq!(release.command(&mappings), None);
+        assert_eq!(message("/unmapped", vec![]).command(&mappings), None);
+    }
+
+    /// A UDP port nothing is bound to right now.
+    fn free_port() -> u16 {
+        UdpSocket::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn receives_and_sends_over_udp() {
+        let receiver = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let port = free_port();
+        let mut link = OscLink::new();
+        link.configure(&OscConfig {
+            enabled: true,
+            listen_port: port,
+            send_to: receiver.local_addr().unwrap().to_string(),
+            ..OscConfig::default()
+        });
+        assert!(link.status.is_empty(), "{}", link.status);
+
+        let socket = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        socket.connect(("127.0.0.1", port)).unwrap();
+        socket.send(&message("/preset/next", vec![OscArg::Int(1)]).encode()).unwrap();
+        let load = message("/preset/load", vec![OscArg::String(String::from("Rain"))]);
+        socket.send(&bundle(&[load.encode(), message("/track/pause", vec![]).encode()])).unwrap();
+
+        let expected = [
+            RemoteCommand::NextPreset,
+            RemoteCommand::LoadPreset {
+                preset: String::from("Rain"),
+            },
+            RemoteCommand::Pause,
+        ];
+        let mut commands = Vec::new();
+        let started = Instant::now();
+        while commands.len() < expected.len() && started.elapsed() < Duration::from_secs(5) {
+            commands.extend(link.commands());
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(commands, expected);
+
+        link.send_beat(128.0, true);
+        let mut buffer = [0; MAX_PACKET];
+        let len = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(
+            decode_packet(&buffer[..len]),
+            [message("/aurora/beat", vec![OscArg::Float(128.0), OscArg::Int(1)])]
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// How the video encoder is asked to spend bits.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateControl {
+    /// Constant rate factor (lower is better quality, 18-28 is the usual range for x264).
+    Crf(u8),
+    /// Target video bitrate in kbit/s.
+    Bitrate(u32),
+}
+
+/// A named set of encoder settings for one target platform.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct OutputProfile {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    /// ffmpeg video encoder, e.g. `libx264`, `libx265` or `libvpx-vp9`.
+    pub codec: String,
+    /// Encoder speed preset, e.g. `medium` or `slow`. Empty to leave it to ffmpeg.
+    pub codec_preset: String,
+    pub rate_control: RateControl,
+    pub pixel_format: String,
+    /// Longest video the platform accepts, in seconds.
+    pub max_duration: Option<f64>,
+    pub audio_codec: String,
+    /// Audio bitrate in kbit/s.
+    pub audio_bitrate: u32,
+    /// Container extension of the produced file.
+    pub container: String,
+}
+
+impl Default for OutputProfile {
+    fn default() -> Self {
+        Self {
+            name: String::from("Custom"),
+            width: 1920,
+            height: 1080,
+            fps: 60,
+            codec: String::from("libx264"),
+            codec_preset: String::from("medium"),
+            rate_control: RateControl::Crf(18),
+            pixel_format: String::from("yuv420p"),
+            max_duration: None,
+            audio_codec: String::from("aac"),
+            audio_bitrate: 320,
+            container: String::from("mp4"),
+        }
+    }
+}
+
+impl OutputProfile {
+    pub fn builtins() -> Vec<OutputProfile> {
+        vec![
+            OutputProfile {
+                name: String::from("YouTube 1080p"),
+                ..Default::default()
+            },
+            OutputProfile {
+                name: String::from("Shorts/Reels 1080x1920"),
+                width: 1080,
+                height: 1920,
+                fps: 30,
+                rate_control: RateControl::Bitrate(12_000),
+                max_duration: Some(60.0),
+                audio_bitrate: 256,
+                ..Default::default()
+            },
+            OutputProfile {
+                name: String::from("Square 1080"),
+                width: 1080,
+                height: 1080,
+                fps: 30,
+                rate_control: RateControl::Crf(20),
+                max_duration: Some(60.0),
+                audio_bitrate: 256,
+                ..Default::default()
+            },
+        ]
+    }
+
+    /// A filesystem-friendly version of the profile name, used in output file names.
+    pub fn slug(&self) -> String {
+        let mut slug = String::new();
+        for c in self.name.chars() {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c.to_ascii_lowercase());
+            } else if !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+        slug.trim_matches('-').to_string()
+    }
+
+    /// Arguments for an ffmpeg process that reads raw RGBA frames of this profile's size
+    /// from stdin, muxes them with `audio` and writes `output`.
+    pub fn ffmpeg_args(&self, audio: &Path, output: &Path) -> Vec<String> {
+        let mut args: Vec<String> = vec![
+            "-y".into(),
+            "-loglevel".into(),
+            "error".into(),
+            "-f".into(),
+            "rawvideo".into(),
+            "-pix_fmt".into(),
+            "rgba".into(),
+            "-s".into(),
+            format!("{}x{}", self.width, self.height),
+            "-r".into(),
+            self.fps.to_string(),
+            "-i".into(),
+            "-".into(),
+            "-i".into(),
+            audio.display().to_string(),
+            "-map".into(),
+            "0:v".into(),
+            "-map".into(),
+            "1:a".into(),
+            // glReadPixels hands us the image bottom row first.
+            "-vf".into(),
+            "vflip".into(),
+            "-c:v".into(),
+            self.codec.clone(),
+        ];
+
+        if !self.codec_preset.is_empty() {
+            args.extend(["-preset".into(), self.codec_preset.clone()]);
+        }
+
+        match self.rate_control {
+            RateControl::Crf(crf) => {
+                args.extend(["-crf".into(), crf.to_string()]);
+                // libvpx only honours CRF in constrained-quality mode with a zero bitrate.
+                if self.codec.starts_with("libvpx") {
+                    args.extend(["-b:v".into(), "0".into()]);
+                }
+            }
+            RateControl::Bitrate(kbps) => {
+                args.extend(["-b:v".into(), format!("{}k", kbps)]);
+            }
+        }
+
+        args.extend([
+            "-pix_fmt".into(),
+            self.pixel_format.clone(),
+            "-c:a".into(),
+            self.audio_codec.clone(),
+            "-b:a".into(),
+            format!("{}k", self.audio_bitrate),
+        ]);
+
+        if let Some(max_duration) = self.max_duration {
+            args.extend(["-t".into(), format!("{:.3}", max_duration)]);
+        }
+
+        args.extend(["-shortest".into(), output.display().to_string()]);
+        args
+    }
+
+    /// ffmpeg's text subtitle encoder for this profile's container, if it has one.
+    pub fn subtitle_codec(&self) -> Option<&'static str> {
+        match self.container.as_str() {
+            "mp4" | "m4v" | "mov" => Some("mov_text"),
+            "mkv" => Some("srt"),
+            "webm" => Some("webvtt"),
+            _ => None,
+        }
+    }
+
+    /// Arguments for an ffmpeg process that copies `video` to `output` with
+    /// the SRT file `subtitles` added as a subtitle track, or `None` if the
+    /// container can't hold one.
+    pub fn subtitle_mux_args(&self, video: &Path, subtitles: &Path, output: &Path) -> Option<Vec<String>> {
+        let codec = self.subtitle_codec()?;
+        Some(vec![
+            "-y".into(),
+            "-loglevel".into(),
+            "error".into(),
+            "-i".into(),
+            video.display().to_string(),
+            "-i".into(),
+            subtitles.display().to_string(),
+            "-map".into(),
+            "0".into(),
+            "-map".into(),
+            "1:s".into(),
+            "-c".into(),
+            "copy".into(),
+            "-c:s".into(),
+            codec.into(),
+            "-metadata:s:s:0".into(),
+            "title=Overlay text".into(),
+            output.display().to_string(),
+        ])
+    }
+}
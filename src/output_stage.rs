@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// Time for volume changes to settle, long enough to avoid zipper noise.
+const VOLUME_SMOOTHING: f32 = 0.02;
+/// Volume step of the volume hotkeys.
+pub const VOLUME_STEP: f32 = 0.05;
+
+/// What the listener hears. None of it changes the level projectM receives.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct OutputConfig {
+    /// Master volume from 0 to 1. It is squared before use, so equal steps
+    /// sound roughly equally loud.
+    pub volume: f32,
+    /// Catch peaks above `limiter_ceiling_db` before they clip.
+    pub limiter: bool,
+    pub limiter_ceiling_db: f32,
+    /// How far ahead the limiter looks for peaks; adds this much output latency.
+    pub limiter_lookahead_ms: f32,
+    /// How fast the gain recovers after a peak.
+    pub limiter_release_ms: f32,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            limiter: false,
+            limiter_ceiling_db: -1.0,
+            limiter_lookahead_ms: 5.0,
+            limiter_release_ms: 100.0,
+        }
+    }
+}
+
+/// Output settings shared with the output callback.
+#[derive(Debug, Clone, Default)]
+pub struct OutputControl {
+    pub config: OutputConfig,
+    pub muted: bool,
+}
+
+/// Volume, mute and limiter applied to the output in the audio callback.
+pub struct OutputStage {
+    sample_rate: u32,
+    channels: usize,
+    /// Volume gain as it ramps towards the target.
+    gain: f32,
+    limiter: Option<Limiter>,
+}
+
+impl OutputStage {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1),
+            channels: channels.max(1),
+            gain: 1.0,
+            limiter: None,
+        }
+    }
+
+    /// Applies the volume and limiter to interleaved `samples` in place.
+    pub fn process(&mut self, samples: &mut [f32], control: &OutputControl) {
+        let config = &control.config;
+        let target = if control.muted {
+            0.0
+        } else {
+            config.volume.clamp(0.0, 1.0).powi(2)
+        };
+        let smoothing = 1.0 - (-1.0 / (VOLUME_SMOOTHING * self.sample_rate as f32)).exp();
+        for frame in samples.chunks_mut(self.channels) {
+            self.gain += (target - self.gain) * smoothing;
+            for sample in frame {
+                *sample *= self.gain;
+            }
+        }
+
+        if !config.limiter {
+            self.limiter = None;
+            return;
+        }
+        let lookahead = (config.limiter_lookahead_ms / 1000.0 * self.sample_rate as f32) as usize;
+        if self
+            .limiter
+            .as_ref()
+            .is_none_or(|limiter| limiter.lookahead != lookahead)
+        {
+            self.limiter = Some(Limiter::new(lookahead, self.channels));
+        }
+        let release =
+            1.0 - (-1000.0 / (config.limiter_release_ms.max(1.0) * self.sample_rate as f32)).exp();
+        let ceiling = 10f32.powf(config.limiter_ceiling_db / 20.0);
+        self.limiter
+            .as_mut()
+            .unwrap()
+            .process(samples, ceiling, release);
+    }
+}
+
+/// Look-ahead peak limiter. The gain each frame needs is held at its minimum
+/// over the look-ahead window and then box-averaged over the same window. The
+/// gain has fully come down by the time a delayed peak plays, without any
+/// instant steps.
+struct Limiter {
+    lookahead: usize,
+    channels: usize,
+    /// Frames waiting to be played, interleaved.
+    delay: VecDeque<f32>,
+    /// Candidates for the windowed minimum: frame number and gain, gains increasing.
+    minimum: VecDeque<(u64, f32)>,
+    frame: u64,
+    envelope: f32,
+    /// The last `lookahead + 1` envelope values and their sum.
+    window: VecDeque<f32>,
+    window_sum: f64,
+}
+
+impl Limiter {
+    fn new(lookahead: usize, channels: usize) -> Self {
+        let length = lookahead + 1;
+        Self {
+            lookahead,
+            channels,
+            delay: VecDeque::from(vec![0.0; lookahead * channels]),
+            minimum: VecDeque::with_capacity(length + 1),
+            frame: 0,
+            envelope: 1.0,
+            window: VecDeque::from(vec![1.0; length]),
+            window_sum: length as f64,
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32], ceiling: f32, release: f32) {
+        let length = self.lookahead + 1;
+        for frame in samples.chunks_mut(self.channels) {
+            let peak = frame.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            let required = if peak > ceiling { ceiling / peak } else { 1.0 };
+
+            while self.minimum.back().is_some_and(|(_, gain)| *gain >= required) {
+                self.minimum.pop_back();
+            }
+            self.minimum.push_back((self.frame, required));
+            while self
+                .minimum
+                .front()
+                .is_some_and(|(frame, _)| frame + (length as u64) <= self.frame)
+            {
+                self.minimum.pop_front();
+            }
+            let held = self.minimum.front().unwrap().1;
+
+            if held < self.envelope {
+                self.envelope = held;
+            } else {
+                self.envelope += (held - self.envelope) * release;
+            }
+            self.window_sum += (self.envelope - self.window.pop_front().unwrap()) as f64;
+            self.window.push_back(self.envelope);
+            let gain = ((self.window_sum / length as f64) as f32).min(1.0);
+            self.frame += 1;
+
+            for sample in frame {
+                self.delay.push_back(*sample);
+                *sample = self.delay.pop_front().unwrap() * gain;
+            }
+        }
+    }
+}
@@ -0,0 +1,937 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use ab_glyph::FontArc;
+use egui_glow::glow;
+use serde::{Deserialize, Serialize};
+
+use crate::lyrics::{self, Lyrics, LyricsConfig};
+use crate::metadata::TrackMetadata;
+use crate::overlay_animation::{self, AnimationConfig, AnimationFrame, Motion};
+use crate::overlay_image::{self, ImageElementConfig, ImageSource};
+use crate::overlay_widgets::{CardConfig, ProgressBarConfig};
+use crate::seek_bar;
+use crate::sprite_renderer::{BlendMode, Sprite, SpriteRenderer};
+use crate::subtitles::Cue;
+use crate::text_layout::{self, SanitizeConfig};
+use crate::text_raster::{self, HAlign, TextStyle};
+
+/// Sizes and margins in the overlay config are in pixels of a 1080 pixel tall
+/// frame and scaled to the actual target, so every output looks the same.
+pub const REFERENCE_HEIGHT: f32 = 1080.0;
+/// Cached text sprites or images, across all targets, before the cache is cleared.
+const MAX_CACHED_SPRITES: usize = 24;
+/// How long an image's height must stay put before it is decoded again at
+/// that height; until then the old decode is stretched.
+const IMAGE_RESIZE_DELAY: Duration = Duration::from_millis(300);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    pub const ALL: [Anchor; 9] = [
+        Anchor::TopLeft,
+        Anchor::Top,
+        Anchor::TopRight,
+        Anchor::Left,
+        Anchor::Center,
+        Anchor::Right,
+        Anchor::BottomLeft,
+        Anchor::Bottom,
+        Anchor::BottomRight,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Anchor::TopLeft => "Top left",
+            Anchor::Top => "Top",
+            Anchor::TopRight => "Top right",
+            Anchor::Left => "Left",
+            Anchor::Center => "Center",
+            Anchor::Right => "Right",
+            Anchor::BottomLeft => "Bottom left",
+            Anchor::Bottom => "Bottom",
+            Anchor::BottomRight => "Bottom right",
+        }
+    }
+
+    /// Horizontal and vertical position as fractions of the free space (0, 0.5 or 1).
+    pub fn factors(self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+
+    fn align(self) -> HAlign {
+        match self.factors().0 {
+            x if x < 0.25 => HAlign::Left,
+            x if x > 0.75 => HAlign::Right,
+            _ => HAlign::Center,
+        }
+    }
+}
+
+/// Where an element's text comes from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextSource {
+    /// `TextElementConfig::custom_text`.
+    Custom,
+    /// The title tag, or the file name if the track has none.
+    TrackTitle,
+    /// The file name, ignoring tags.
+    FileName,
+    /// The artist tag, or `custom_text` if the track has none.
+    TrackArtist,
+    /// How far into the track playback is, as `m:ss`.
+    Elapsed,
+    /// Time left in the track, as `-m:ss`.
+    Remaining,
+    /// Elapsed time and track length, as `m:ss / m:ss`.
+    ElapsedAndTotal,
+}
+
+impl TextSource {
+    pub const ALL: [TextSource; 7] = [
+        TextSource::Custom,
+        TextSource::TrackTitle,
+        TextSource::FileName,
+        TextSource::TrackArtist,
+        TextSource::Elapsed,
+        TextSource::Remaining,
+        TextSource::ElapsedAndTotal,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TextSource::Custom => "Custom text",
+            TextSource::TrackTitle => "Track title",
+            TextSource::FileName => "File name",
+            TextSource::TrackArtist => "Track artist",
+            TextSource::Elapsed => "Elapsed time",
+            TextSource::Remaining => "Remaining time",
+            TextSource::ElapsedAndTotal => "Elapsed / total time",
+        }
+    }
+
+    /// Whether the text changes as the track plays.
+    pub fn is_clock(self) -> bool {
+        matches!(
+            self,
+            TextSource::Elapsed | TextSource::Remaining | TextSource::ElapsedAndTotal
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct TextElementConfig {
+    pub enabled: bool,
+    pub source: TextSource,
+    pub custom_text: String,
+    /// TTF/OTF file; empty uses the built-in font.
+    pub font_path: PathBuf,
+    pub size: f32,
+    pub color: [u8; 4],
+    pub outline_width: f32,
+    pub outline_color: [u8; 4],
+    pub shadow_offset: [f32; 2],
+    pub shadow_color: [u8; 4],
+    pub anchor: Anchor,
+    /// Distance from the anchored edges, x then y.
+    pub margin: [f32; 2],
+    /// Break lines wider than `OverlayConfig::max_line_width` into balanced lines.
+    pub wrap: bool,
+    /// Panel behind the text; transparent leaves it out.
+    pub background: [u8; 4],
+    /// Space between the text and the panel's edges.
+    pub padding: f32,
+    pub corner_radius: f32,
+    pub animation: AnimationConfig,
+}
+
+impl Default for TextElementConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            source: TextSource::Custom,
+            custom_text: String::new(),
+            font_path: PathBuf::new(),
+            size: 72.0,
+            color: [255, 255, 255, 255],
+            outline_width: 3.0,
+            outline_color: [0, 0, 0, 200],
+            shadow_offset: [4.0, 4.0],
+            shadow_color: [0, 0, 0, 128],
+            anchor: Anchor::Center,
+            margin: [48.0, 48.0],
+            wrap: true,
+            background: [0, 0, 0, 0],
+            padding: 24.0,
+            corner_radius: 12.0,
+            animation: AnimationConfig::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct OverlayConfig {
+    pub enabled: bool,
+    /// Fades every element together, on top of their own animations.
+    pub opacity: f32,
+    pub title: TextElementConfig,
+    pub artist: TextElementConfig,
+    pub url: TextElementConfig,
+    /// Elapsed or remaining time, or any other text source.
+    pub timer: TextElementConfig,
+    pub progress: ProgressBarConfig,
+    /// Slides in with the track's title and artist as it starts.
+    pub now_playing: CardConfig,
+    /// Slides in with the next track's title and artist before this one ends.
+    pub up_next: CardConfig,
+    /// Cleanup applied to file names shown as titles.
+    pub title_cleanup: SanitizeConfig,
+    /// Widest a wrapped line may be, as a fraction of the frame width.
+    pub max_line_width: f32,
+    pub max_lines: usize,
+    /// Synced lyrics of the track, when it has any.
+    pub lyrics: LyricsConfig,
+    /// Logos and cover art, drawn under the text.
+    pub images: Vec<ImageElementConfig>,
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            opacity: 1.0,
+            title: TextElementConfig {
+                source: TextSource::TrackTitle,
+                size: 96.0,
+                anchor: Anchor::Center,
+                margin: [48.0, 0.0],
+                ..Default::default()
+            },
+            artist: TextElementConfig {
+                source: TextSource::TrackArtist,
+                size: 64.0,
+                anchor: Anchor::Bottom,
+                margin: [48.0, 160.0],
+                ..Default::default()
+            },
+            url: TextElementConfig {
+                source: TextSource::Custom,
+                size: 36.0,
+                color: [255, 255, 255, 200],
+                outline_width: 0.0,
+                shadow_color: [0, 0, 0, 0],
+                anchor: Anchor::BottomRight,
+                margin: [32.0, 32.0],
+                wrap: false,
+                ..Default::default()
+            },
+            timer: TextElementConfig {
+                enabled: false,
+                source: TextSource::ElapsedAndTotal,
+                size: 36.0,
+                outline_width: 2.0,
+                anchor: Anchor::BottomRight,
+                margin: [96.0, 120.0],
+                wrap: false,
+                ..Default::default()
+            },
+            progress: ProgressBarConfig::default(),
+            now_playing: CardConfig::now_playing(),
+            up_next: CardConfig::up_next(),
+            title_cleanup: SanitizeConfig::default(),
+            max_line_width: 0.33,
+            max_lines: 3,
+            lyrics: LyricsConfig::default(),
+            images: vec![
+                ImageElementConfig {
+                    enabled: false,
+                    ..Default::default()
+                },
+                ImageElementConfig {
+                    enabled: false,
+                    source: ImageSource::CoverArt,
+                    size: 360.0,
+                    anchor: Anchor::Left,
+                    margin: [96.0, 0.0],
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+}
+
+impl OverlayConfig {
+    pub fn elements(&self) -> [&TextElementConfig; 4] {
+        [&self.title, &self.artist, &self.url, &self.timer]
+    }
+}
+
+/// Sprite cache slots of everything drawn as text; each needs its own.
+const TITLE_SLOT: usize = 0;
+const ARTIST_SLOT: usize = 1;
+const URL_SLOT: usize = 2;
+const TIMER_SLOT: usize = 3;
+const LYRIC_SLOT: usize = 4;
+const NEXT_LYRIC_SLOT: usize = 5;
+const NOW_PLAYING_SLOT: usize = 6;
+const UP_NEXT_SLOT: usize = 7;
+
+/// An element to draw: its sprite cache slot, style, text and highlighted characters.
+type TextItem = (usize, TextElementConfig, String, Option<(usize, [u8; 4])>);
+
+/// What a cached sprite was rendered from, so it is only redrawn when something changes.
+#[derive(PartialEq)]
+struct SpriteKey {
+    text: String,
+    element: TextElementConfig,
+    target_width: u32,
+    target_height: u32,
+    max_line_width: f32,
+    max_lines: usize,
+    visible_chars: Option<usize>,
+    highlight: Option<(usize, [u8; 4])>,
+}
+
+/// What a cached image sprite was decoded from.
+#[derive(PartialEq)]
+struct ImageKey {
+    source: ImageSource,
+    path: PathBuf,
+    /// The track whose cover art it shows.
+    track: Option<PathBuf>,
+}
+
+struct CachedImage {
+    key: ImageKey,
+    /// Height it was decoded at, in pixels.
+    px: u32,
+    /// `None` when the image could not be loaded, so it isn't retried every frame.
+    sprite: Option<Sprite>,
+    /// A different height asked for since, and when it was first asked for.
+    resize: Option<(u32, Instant)>,
+}
+
+/// Text and images drawn on top of projectM, into the same framebuffer, so
+/// they show up both on screen and in recordings.
+pub struct Overlay {
+    pub config: OverlayConfig,
+    track_path: Option<PathBuf>,
+    metadata: TrackMetadata,
+    /// Length of the track in seconds, or 0 when unknown.
+    track_duration: f64,
+    /// Media time of the most recent beat, for pulse animations.
+    last_beat: Option<f64>,
+    lyrics: Option<Lyrics>,
+    /// The track after this one, for the up next card.
+    next_track: Option<(PathBuf, TrackMetadata)>,
+    /// Seconds the lyrics are shown later than their timestamps say.
+    pub lyrics_offset: f64,
+    /// The track's file name after `title_cleanup`, and the cleanup it was made with.
+    display_name: (SanitizeConfig, String),
+    renderer: Option<SpriteRenderer>,
+    fonts: HashMap<PathBuf, FontArc>,
+    default_font: FontArc,
+    /// Rendered text per (element index, target height).
+    sprites: HashMap<(usize, u32), (SpriteKey, Sprite)>,
+    /// Decoded images per (image index, target height).
+    images: HashMap<(usize, u32), CachedImage>,
+    /// One-pixel textures of the progress bar's colors, stretched into bars.
+    swatches: HashMap<[u8; 4], Sprite>,
+}
+
+impl Overlay {
+    pub fn new(config: OverlayConfig) -> Self {
+        Self {
+            config,
+            track_path: None,
+            metadata: TrackMetadata::default(),
+            track_duration: 0.0,
+            last_beat: None,
+            lyrics: None,
+            next_track: None,
+            lyrics_offset: 0.0,
+            display_name: (SanitizeConfig::default(), String::new()),
+            renderer: None,
+            fonts: HashMap::new(),
+            default_font: text_raster::default_font(),
+            sprites: HashMap::new(),
+            images: HashMap::new(),
+            swatches: HashMap::new(),
+        }
+    }
+
+    pub fn set_track(&mut self, path: Option<&Path>, metadata: TrackMetadata, duration: f64) {
+        self.track_path = path.map(Path::to_path_buf);
+        self.metadata = metadata;
+        self.track_duration = duration;
+        self.last_beat = None;
+        self.reload_lyrics();
+        self.refresh_display_name();
+    }
+
+    pub fn set_next_track(&mut self, path: Option<&Path>, metadata: TrackMetadata) {
+        self.next_track = path.map(|path| (path.to_path_buf(), metadata));
+    }
+
+    pub fn next_track_path(&self) -> Option<&Path> {
+        self.next_track.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    /// Looks for the track's lyrics again, e.g. after an `.lrc` file was added.
+    pub fn reload_lyrics(&mut self) {
+        self.lyrics = self
+            .track_path
+            .as_deref()
+            .and_then(|path| lyrics::load_lyrics(path, self.metadata.lyrics.as_deref()));
+    }
+
+    pub fn lyrics(&self) -> Option<&Lyrics> {
+        self.lyrics.as_ref()
+    }
+
+    pub fn track_path(&self) -> Option<&Path> {
+        self.track_path.as_deref()
+    }
+
+    /// Starts a pulse on every element that has one.
+    pub fn beat(&mut self, media_time: f64) {
+        self.last_beat = Some(overlay_animation::tick_time(media_time));
+    }
+
+    fn refresh_display_name(&mut self) {
+        let stem = self
+            .track_path
+            .as_ref()
+            .and_then(|path| path.file_stem())
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let cleanup = self.config.title_cleanup.clone();
+        let name = text_layout::sanitize_title(&stem, &cleanup);
+        self.display_name = (cleanup, name);
+    }
+
+    fn file_name(&self) -> String {
+        self.display_name.1.clone()
+    }
+
+    /// The text an element displays at `media_time`.
+    pub fn text_for(&self, element: &TextElementConfig, media_time: f64) -> String {
+        let duration = self.track_duration;
+        match element.source {
+            TextSource::Custom => element.custom_text.clone(),
+            TextSource::TrackTitle => self
+                .metadata
+                .title
+                .clone()
+                .unwrap_or_else(|| self.file_name()),
+            TextSource::FileName => self.file_name(),
+            TextSource::TrackArtist => self
+                .metadata
+                .artist
+                .clone()
+                .unwrap_or_else(|| element.custom_text.clone()),
+            TextSource::Elapsed => seek_bar::format_time(media_time),
+            TextSource::Remaining if duration > 0.0 => {
+                format!("-{}", seek_bar::format_time(duration - media_time))
+            }
+            TextSource::Remaining => String::new(),
+            TextSource::ElapsedAndTotal if duration > 0.0 => format!(
+                "{} / {}",
+                seek_bar::format_time(media_time),
+                seek_bar::format_time(duration)
+            ),
+            TextSource::ElapsedAndTotal => seek_bar::format_time(media_time),
+        }
+    }
+
+    /// The enabled cards with their text and, in place of their own timing,
+    /// when they slide in and out.
+    fn card_items(&self) -> Vec<TextItem> {
+        let mut items = Vec::new();
+        let now_playing = &self.config.now_playing;
+        if now_playing.style.enabled {
+            let title = self.metadata.title.clone().unwrap_or_else(|| self.file_name());
+            let start = now_playing.at;
+            let mut style = now_playing.style.clone();
+            style.animation = now_playing.animation(start, start + now_playing.show_for);
+            let text = now_playing.text(&title, self.metadata.artist.as_deref());
+            items.push((NOW_PLAYING_SLOT, style, text, None));
+        }
+
+        let up_next = &self.config.up_next;
+        if let Some((path, metadata)) = self.next_track.as_ref().filter(|_| up_next.style.enabled) {
+            let duration = self.track_duration;
+            if duration > 0.0 {
+                let title = metadata.title.clone().unwrap_or_else(|| {
+                    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                    text_layout::sanitize_title(&stem, &self.config.title_cleanup)
+                });
+                let start = (duration - up_next.at).max(0.0);
+                let mut style = up_next.style.clone();
+                style.animation = up_next.animation(start, (start + up_next.show_for).min(duration));
+                let text = up_next.text(&title, metadata.artist.as_deref());
+                items.push((UP_NEXT_SLOT, style, text, None));
+            }
+        }
+        items
+    }
+
+    /// The current lyric line, with its sung words highlighted, and the next one.
+    fn lyric_items(&self, media_time: f64) -> Vec<TextItem> {
+        let config = &self.config.lyrics;
+        let Some(lyrics) = self.lyrics.as_ref().filter(|_| config.enabled) else {
+            return Vec::new();
+        };
+        let frame = lyrics.at(media_time - self.lyrics_offset);
+        let mut items = Vec::new();
+        if let Some((line, sung)) = frame.current {
+            let highlight = Some((sung, config.highlight_color));
+            items.push((LYRIC_SLOT, config.current.clone(), line.text.clone(), highlight));
+        }
+        if let Some(line) = frame.next.filter(|_| config.show_next_line) {
+            items.push((NEXT_LYRIC_SLOT, config.next.clone(), line.text.clone(), None));
+        }
+        items
+    }
+
+    /// Everything `draw` shows over the track and when, for subtitle files:
+    /// each enabled element and card between its show and hide times, then
+    /// each lyric line. It leaves out clocks and the next-line preview and
+    /// ignores the overlay's own on/off switch, so subtitles can replace
+    /// burnt-in text.
+    pub fn timeline(&self) -> Vec<Cue> {
+        let end = self.track_duration;
+        let mut items: Vec<(TextElementConfig, String)> = self
+            .config
+            .elements()
+            .into_iter()
+            .filter(|element| element.enabled && !element.source.is_clock())
+            .map(|element| (element.clone(), self.text_for(element, 0.0)))
+            .collect();
+        items.extend(self.card_items().into_iter().map(|(_, style, text, _)| (style, text)));
+        let mut cues: Vec<Cue> = items
+            .into_iter()
+            .map(|(element, text)| {
+                let (start, hide_at) = element.animation.visible_span();
+                Cue {
+                    start,
+                    end: hide_at.unwrap_or(end),
+                    text,
+                    anchor: element.anchor,
+                }
+            })
+            .filter(|cue| !cue.text.trim().is_empty())
+            .collect();
+
+        let config = &self.config.lyrics;
+        if let Some(lyrics) = self.lyrics.as_ref().filter(|_| config.enabled) {
+            for (start, until, line) in lyrics.spans(end - self.lyrics_offset) {
+                cues.push(Cue {
+                    start: start + self.lyrics_offset,
+                    end: until + self.lyrics_offset,
+                    text: line.text.clone(),
+                    anchor: config.current.anchor,
+                });
+            }
+        }
+        cues.retain(|cue| cue.end > cue.start);
+        cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+        cues
+    }
+
+    fn font(&mut self, path: &Path) -> FontArc {
+        if path.as_os_str().is_empty() {
+            return self.default_font.clone();
+        }
+        if let Some(font) = self.fonts.get(path) {
+            return font.clone();
+        }
+        let font = text_raster::load_font(path).unwrap_or_else(|| self.default_font.clone());
+        self.fonts.insert(path.to_path_buf(), font.clone());
+        font
+    }
+
+    /// Draws every enabled image, the progress bar, and then the text elements
+    /// and cards, as animated at `media_time`, into the bound framebuffer of
+    /// the given size.
+    pub fn draw(&mut self, gl: &glow::Context, width: u32, height: u32, media_time: f64) {
+        if !self.config.enabled || width == 0 || height == 0 {
+            return;
+        }
+        if self.renderer.is_none() {
+            self.renderer = Some(SpriteRenderer::new(gl));
+        }
+        if self.display_name.0 != self.config.title_cleanup {
+            self.refresh_display_name();
+        }
+        self.draw_images(gl, width, height, media_time);
+        self.draw_progress(gl, width, height, media_time);
+
+        let scale = height as f32 / REFERENCE_HEIGHT;
+        let slots = [TITLE_SLOT, ARTIST_SLOT, URL_SLOT, TIMER_SLOT];
+        let mut items: Vec<TextItem> = self
+            .config
+            .elements()
+            .into_iter()
+            .zip(slots)
+            .map(|(element, slot)| {
+                (slot, element.clone(), self.text_for(element, media_time), None)
+            })
+            .collect();
+        items.extend(self.lyric_items(media_time));
+        items.extend(self.card_items());
+
+        for (index, element, text, highlight) in items {
+            if !element.enabled {
+                continue;
+            }
+            if text.trim().is_empty() {
+                continue;
+            }
+            let frame = element
+                .animation
+                .frame(media_time, self.track_duration, self.last_beat);
+            let opacity = frame.opacity * self.config.opacity;
+            if opacity <= 0.0 {
+                continue;
+            }
+            let visible_chars = frame
+                .visible_chars
+                .filter(|visible| *visible < text.chars().count());
+
+            let key = SpriteKey {
+                text,
+                element,
+                target_width: width,
+                target_height: height,
+                max_line_width: self.config.max_line_width,
+                max_lines: self.config.max_lines,
+                visible_chars,
+                highlight,
+            };
+            let cached = self
+                .sprites
+                .get(&(index, height))
+                .is_some_and(|(cached_key, _)| *cached_key == key);
+            if !cached {
+                // Targets come and go with window resizes and recordings; don't hoard their text.
+                if !self.sprites.contains_key(&(index, height))
+                    && self.sprites.len() >= MAX_CACHED_SPRITES
+                {
+                    for (_, (_, sprite)) in self.sprites.drain() {
+                        sprite.destroy(gl);
+                    }
+                }
+                let sprite = self.render_sprite(gl, &key, scale);
+                if let Some((_, old)) = self.sprites.insert((index, height), (key, sprite)) {
+                    old.destroy(gl);
+                }
+            }
+
+            let (key, sprite) = &self.sprites[&(index, height)];
+            let element = &key.element;
+            let base = [sprite.width as f32, sprite.height as f32];
+            let (pos, size) = place(
+                element.anchor,
+                element.margin,
+                &element.animation,
+                &frame,
+                base,
+                [width, height],
+                scale,
+            );
+            self.renderer.as_ref().unwrap().draw(
+                gl,
+                sprite,
+                pos,
+                size,
+                [width, height],
+                opacity,
+                BlendMode::Normal,
+            );
+        }
+    }
+
+    /// Draws the enabled images, decoding each one again only when its file or
+    /// track changes, or its size has settled on a new value.
+    fn draw_images(&mut self, gl: &glow::Context, width: u32, height: u32, media_time: f64) {
+        let scale = height as f32 / REFERENCE_HEIGHT;
+        for (index, image) in self.config.images.clone().into_iter().enumerate() {
+            if !image.enabled {
+                continue;
+            }
+            let frame = image
+                .animation
+                .frame(media_time, self.track_duration, self.last_beat);
+            let opacity = frame.opacity * image.opacity * self.config.opacity;
+            if opacity <= 0.0 {
+                continue;
+            }
+
+            let key = ImageKey {
+                source: image.source,
+                path: image.path.clone(),
+                track: self
+                    .track_path
+                    .clone()
+                    .filter(|_| image.source == ImageSource::CoverArt),
+            };
+            let px = (image.size * scale).round().max(1.0) as u32;
+            let decode = match self.images.get_mut(&(index, height)) {
+                Some(cached) if cached.key == key => {
+                    // Dragging the size slider would otherwise decode on every frame.
+                    if cached.px == px {
+                        cached.resize = None;
+                    } else if cached.resize.map(|(wanted, _)| wanted) != Some(px) {
+                        cached.resize = Some((px, Instant::now()));
+                    }
+                    cached
+                        .resize
+                        .is_some_and(|(_, since)| since.elapsed() >= IMAGE_RESIZE_DELAY)
+                }
+                _ => true,
+            };
+            if decode {
+                if !self.images.contains_key(&(index, height))
+                    && self.images.len() >= MAX_CACHED_SPRITES
+                {
+                    for (_, cached) in self.images.drain() {
+                        if let Some(sprite) = cached.sprite {
+                            sprite.destroy(gl);
+                        }
+                    }
+                }
+                let sprite = self.load_image(&key, px).map(|bitmap| {
+                    Sprite::upload(gl, bitmap.width(), bitmap.height(), bitmap.as_raw())
+                });
+                let cached = CachedImage {
+                    key,
+                    px,
+                    sprite,
+                    resize: None,
+                };
+                if let Some(old) = self.images.insert((index, height), cached) {
+                    if let Some(sprite) = old.sprite {
+                        sprite.destroy(gl);
+                    }
+                }
+            }
+
+            let cached = &self.images[&(index, height)];
+            let Some(sprite) = &cached.sprite else {
+                continue;
+            };
+            let stretch = px as f32 / cached.px as f32;
+            let base = [sprite.width as f32 * stretch, sprite.height as f32 * stretch];
+            let (pos, size) = place(
+                image.anchor,
+                image.margin,
+                &image.animation,
+                &frame,
+                base,
+                [width, height],
+                scale,
+            );
+            self.renderer.as_ref().unwrap().draw(
+                gl,
+                sprite,
+                pos,
+                size,
+                [width, height],
+                opacity,
+                image.blend,
+            );
+        }
+    }
+
+    fn draw_progress(&mut self, gl: &glow::Context, width: u32, height: u32, media_time: f64) {
+        let bar = &self.config.progress;
+        if !bar.enabled || self.track_duration <= 0.0 {
+            return;
+        }
+        let frame = bar
+            .animation
+            .frame(media_time, self.track_duration, self.last_beat);
+        let opacity = frame.opacity * self.config.opacity;
+        if opacity <= 0.0 {
+            return;
+        }
+
+        let scale = height as f32 / REFERENCE_HEIGHT;
+        let base = [width as f32 * bar.length, (bar.thickness * scale).max(1.0)];
+        let (pos, size) = place(
+            bar.anchor,
+            bar.margin,
+            &bar.animation,
+            &frame,
+            base,
+            [width, height],
+            scale,
+        );
+        let played = (media_time / self.track_duration).clamp(0.0, 1.0) as f32;
+        let parts = [(bar.background, size[0]), (bar.color, size[0] * played)];
+        for (color, length) in parts {
+            if color[3] == 0 || length <= 0.0 {
+                continue;
+            }
+            let sprite = self
+                .swatches
+                .entry(color)
+                .or_insert_with(|| Sprite::upload(gl, 1, 1, &color));
+            self.renderer.as_ref().unwrap().draw(
+                gl,
+                sprite,
+                pos,
+                [length, size[1]],
+                [width, height],
+                opacity,
+                BlendMode::Normal,
+            );
+        }
+    }
+
+    fn load_image(&self, key: &ImageKey, px: u32) -> Option<image::RgbaImage> {
+        if key.source == ImageSource::CoverArt {
+            if let Some(cover_art) = &self.metadata.cover_art {
+                let decoded = overlay_image::decode_image(cover_art, px);
+                if decoded.is_some() {
+                    return decoded;
+                }
+                log::warn!("Could not decode the cover art of {:?}", key.track);
+            }
+        }
+        if key.path.as_os_str().is_empty() {
+            return None;
+        }
+        overlay_image::load_image(&key.path, px)
+    }
+
+    fn render_sprite(&mut self, gl: &glow::Context, key: &SpriteKey, scale: f32) -> Sprite {
+        let element = &key.element;
+        let font = self.font(&element.font_path);
+        let px = element.size * scale;
+        // A marquee scrolls one long line instead of wrapping.
+        let text = if element.wrap && element.animation.motion != Motion::Marquee {
+            let max_width = key.target_width as f32 * key.max_line_width;
+            text_layout::balanced_wrap(&key.text, max_width, key.max_lines, |line| {
+                text_raster::measure_line(&font, px, line)
+            })
+            .join("\n")
+        } else {
+            key.text.clone()
+        };
+        let bitmap = text_raster::rasterize(
+            &text,
+            &TextStyle {
+                font: &font,
+                px,
+                color: element.color,
+                outline_width: element.outline_width * scale,
+                outline_color: element.outline_color,
+                shadow_offset: [
+                    element.shadow_offset[0] * scale,
+                    element.shadow_offset[1] * scale,
+                ],
+                shadow_color: element.shadow_color,
+                align: element.anchor.align(),
+                visible_chars: key.visible_chars,
+                highlight: key.highlight,
+            },
+        );
+        let bitmap = if element.background[3] > 0 {
+            text_raster::add_panel(
+                bitmap,
+                element.background,
+                element.padding * scale,
+                element.corner_radius * scale,
+            )
+        } else {
+            bitmap
+        };
+        Sprite::upload(gl, bitmap.width, bitmap.height, &bitmap.pixels)
+    }
+
+    pub fn destroy(&mut self, gl: &glow::Context) {
+        for (_, (_, sprite)) in self.sprites.drain() {
+            sprite.destroy(gl);
+        }
+        for (_, cached) in self.images.drain() {
+            if let Some(sprite) = cached.sprite {
+                sprite.destroy(gl);
+            }
+        }
+        for (_, sprite) in self.swatches.drain() {
+            sprite.destroy(gl);
+        }
+        if let Some(renderer) = self.renderer.take() {
+            renderer.destroy(gl);
+        }
+    }
+}
+
+/// Top-left corner and size, in target pixels, of a `base`-sized sprite that
+/// is anchored, moved and scaled as `animation` says at `frame`.
+fn place(
+    anchor: Anchor,
+    margin: [f32; 2],
+    animation: &AnimationConfig,
+    frame: &AnimationFrame,
+    base: [f32; 2],
+    target: [u32; 2],
+    scale: f32,
+) -> ([f32; 2], [f32; 2]) {
+    let [width, height] = [target[0] as f32, target[1] as f32];
+    let (fx, fy) = anchor.factors();
+    let margin = [margin[0] * scale, margin[1] * scale];
+    let free = [
+        width - base[0] - margin[0] * 2.0,
+        height - base[1] - margin[1] * 2.0,
+    ];
+    let mut pos = [margin[0] + free[0] * fx, margin[1] + free[1] * fy];
+
+    let travelled = frame.travelled * scale;
+    match animation.motion {
+        Motion::None => {}
+        Motion::Bounce => {
+            let angle = animation.bounce_angle.to_radians();
+            let range = [width - base[0], height - base[1]];
+            pos[0] = overlay_animation::bounce_axis(pos[0], travelled * angle.cos(), range[0]);
+            pos[1] = overlay_animation::bounce_axis(pos[1], -travelled * angle.sin(), range[1]);
+        }
+        Motion::Marquee => {
+            pos[0] = overlay_animation::marquee_x(travelled, width, base[0]);
+        }
+    }
+
+    // Scale around the sprite's center so pulses don't drift.
+    let size = [base[0] * frame.scale, base[1] * frame.scale];
+    pos[0] += frame.offset[0] * scale - (size[0] - base[0]) / 2.0;
+    pos[1] += frame.offset[1] * scale - (size[1] - base[1]) / 2.0;
+    (pos, size)
+}
@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+
+/// Animations advance in fixed steps of `1 / TICK_RATE` seconds of media time,
+/// independent of the frame rate, so a render of the same track always comes
+/// out identical.
+pub const TICK_RATE: f64 = 240.0;
+
+/// Rounds `media_time` down to the animation tick it falls in.
+pub fn tick_time(media_time: f64) -> f64 {
+    (media_time * TICK_RATE).floor() / TICK_RATE
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Motion {
+    None,
+    /// Travels diagonally and bounces off the frame edges, like the DVD logo.
+    Bounce,
+    /// Scrolls right to left across the frame and wraps around.
+    Marquee,
+}
+
+impl Motion {
+    pub const ALL: [Motion; 3] = [Motion::None, Motion::Bounce, Motion::Marquee];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Motion::None => "None",
+            Motion::Bounce => "Bounce",
+            Motion::Marquee => "Marquee",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    /// Smoothstep: slow at both ends.
+    Smooth,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Keyframe {
+    /// Track time in seconds.
+    pub time: f64,
+    /// Offset from the anchored position, in reference pixels.
+    pub offset: [f32; 2],
+    pub opacity: f32,
+    pub scale: f32,
+    /// How the values ease from the previous keyframe into this one.
+    pub easing: Easing,
+}
+
+impl Default for Keyframe {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            offset: [0.0, 0.0],
+            opacity: 1.0,
+            scale: 1.0,
+            easing: Easing::Smooth,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AnimationConfig {
+    pub motion: Motion,
+    /// Bounce and marquee speed in reference pixels per second.
+    pub speed: f32,
+    /// Bounce direction in degrees above the horizontal.
+    pub bounce_angle: f32,
+    /// Seconds of motion after `show_at` before the element comes to rest; 0 never stops.
+    pub motion_duration: f64,
+    /// Track second at which the element appears.
+    pub show_at: f64,
+    /// Track second at which the element disappears; 0 keeps it to the end.
+    pub hide_at: f64,
+    pub fade_in: f64,
+    /// Fades out before `hide_at`, or before the end of the track.
+    pub fade_out: f64,
+    /// Characters typed out per second from `show_at`; 0 shows the whole text at once.
+    pub typewriter_rate: f32,
+    /// Extra scale on each beat, e.g. 0.1 for 10% larger; 0 disables the pulse.
+    pub pulse_amount: f32,
+    /// Seconds for a pulse to fall back to about a third.
+    pub pulse_decay: f32,
+    /// Scale added per minute while the element moves, e.g. 0.1 for a slow
+    /// zoom in; negative zooms out.
+    pub zoom_rate: f32,
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            motion: Motion::None,
+            speed: 240.0,
+            bounce_angle: 35.0,
+            motion_duration: 0.0,
+            show_at: 0.0,
+            hide_at: 0.0,
+            fade_in: 0.0,
+            fade_out: 0.0,
+            typewriter_rate: 0.0,
+            pulse_amount: 0.0,
+            pulse_decay: 0.25,
+            zoom_rate: 0.0,
+            keyframes: Vec::new(),
+        }
+    }
+}
+
+/// The animated state of one element at one point in time.
+pub struct AnimationFrame {
+    pub opacity: f32,
+    pub scale: f32,
+    /// Offset from the anchored position, in reference pixels.
+    pub offset: [f32; 2],
+    /// Reference pixels travelled by the bounce or marquee so far.
+    pub travelled: f32,
+    /// How many characters the typewriter has revealed, or `None` for all of them.
+    pub visible_chars: Option<usize>,
+}
+
+impl AnimationConfig {
+    /// Track seconds at which the element appears and, unless it stays to the
+    /// end of the track, disappears. Fades and keyframes play inside this span.
+    pub fn visible_span(&self) -> (f64, Option<f64>) {
+        (self.show_at, Some(self.hide_at).filter(|hide_at| *hide_at > 0.0))
+    }
+
+    /// Evaluates the animation at `media_time`. `track_duration` is 0 when
+    /// unknown and `last_beat` is the time of the most recent beat, if any.
+    pub fn frame(&self, media_time: f64, track_duration: f64, last_beat: Option<f64>) -> AnimationFrame {
+        let time = tick_time(media_time);
+        let (show_at, hide_at) = self.visible_span();
+        let since_shown = time - show_at;
+
+        let mut opacity = 1.0;
+        if since_shown < 0.0 || hide_at.is_some_and(|hide_at| time >= hide_at) {
+            opacity = 0.0;
+        } else {
+            if self.fade_in > 0.0 {
+                opacity *= (since_shown / self.fade_in).min(1.0) as f32;
+            }
+            let fade_out_end = hide_at.unwrap_or(track_duration);
+            if self.fade_out > 0.0 && fade_out_end > 0.0 {
+                opacity *= ((fade_out_end - time) / self.fade_out).clamp(0.0, 1.0) as f32;
+            }
+        }
+
+        let moving = if self.motion_duration > 0.0 {
+            since_shown.min(self.motion_duration)
+        } else {
+            since_shown
+        };
+        let travelled = self.speed * moving.max(0.0) as f32;
+
+        let visible_chars = (self.typewriter_rate > 0.0)
+            .then(|| (since_shown.max(0.0) * self.typewriter_rate as f64).floor() as usize);
+
+        let mut scale = (1.0 + self.zoom_rate * moving.max(0.0) as f32 / 60.0).max(0.0);
+        if self.pulse_amount > 0.0 {
+            if let Some(beat) = last_beat.filter(|beat| *beat <= time) {
+                let age = (time - beat) as f32;
+                scale += self.pulse_amount * (-age / self.pulse_decay.max(0.001)).exp();
+            }
+        }
+
+        let keyframe = self.keyframe_at(time);
+        AnimationFrame {
+            opacity: opacity * keyframe.opacity,
+            scale: scale * keyframe.scale,
+            offset: keyframe.offset,
+            travelled,
+            visible_chars,
+        }
+    }
+
+    fn keyframe_at(&self, time: f64) -> Keyframe {
+        let Some(first) = self.keyframes.first() else {
+            return Keyframe::default();
+        };
+        if time <= first.time {
+            return first.clone();
+        }
+        for pair in self.keyframes.windows(2) {
+            let (from, to) = (&pair[0], &pair[1]);
+            if time >= to.time {
+                continue;
+            }
+            let mut t = ((time - from.time) / (to.time - from.time).max(f64::EPSILON)) as f32;
+            if to.easing == Easing::Smooth {
+                t = t * t * (3.0 - 2.0 * t);
+            }
+            let lerp = |a: f32, b: f32| a + (b - a) * t;
+            return Keyframe {
+                time,
+                offset: [lerp(from.offset[0], to.offset[0]), lerp(from.offset[1], to.offset[1])],
+                opacity: lerp(from.opacity, to.opacity),
+                scale: lerp(from.scale, to.scale),
+                easing: to.easing,
+            };
+        }
+        self.keyframes.last().unwrap().clone()
+    }
+}
+
+/// Position along one axis of something that started at `start` and has moved
+/// `travelled` pixels, bouncing between 0 and `range`.
+pub fn bounce_axis(start: f32, travelled: f32, range: f32) -> f32 {
+    if range <= 0.0 {
+        return 0.0;
+    }
+    let phase = (start + travelled).rem_euclid(range * 2.0);
+    if phase <= range {
+        phase
+    } else {
+        range * 2.0 - phase
+    }
+}
+
+/// Left edge of a marquee that enters at the right edge of a `frame_width`
+/// frame and wraps once it has fully left on the left.
+pub fn marquee_x(travelled: f32, frame_width: f32, sprite_width: f32) -> f32 {
+    frame_width - travelled.rem_euclid(frame_width + sprite_width)
+}
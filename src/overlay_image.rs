@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::imageops::FilterType;
+use image::RgbaImage;
+use resvg::{tiny_skia, usvg};
+use serde::{Deserialize, Serialize};
+
+use crate::overlay::Anchor;
+use crate::overlay_animation::AnimationConfig;
+use crate::sprite_renderer::BlendMode;
+
+/// Where an image element's picture comes from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSource {
+    /// `ImageElementConfig::path`.
+    File,
+    /// The picture embedded in the track's tags, preferring the front cover,
+    /// or `path` if the track has none.
+    CoverArt,
+}
+
+impl ImageSource {
+    pub const ALL: [ImageSource; 2] = [ImageSource::File, ImageSource::CoverArt];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ImageSource::File => "Image file",
+            ImageSource::CoverArt => "Cover art",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ImageElementConfig {
+    pub enabled: bool,
+    pub source: ImageSource,
+    /// PNG, JPEG or SVG file.
+    pub path: PathBuf,
+    /// Height in reference pixels; the width follows the image's aspect ratio.
+    pub size: f32,
+    pub opacity: f32,
+    pub blend: BlendMode,
+    pub anchor: Anchor,
+    /// Distance from the anchored edges, x then y.
+    pub margin: [f32; 2],
+    pub animation: AnimationConfig,
+}
+
+impl Default for ImageElementConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            source: ImageSource::File,
+            path: PathBuf::new(),
+            size: 160.0,
+            opacity: 1.0,
+            blend: BlendMode::Normal,
+            anchor: Anchor::TopLeft,
+            margin: [32.0, 32.0],
+            animation: AnimationConfig::default(),
+        }
+    }
+}
+
+/// Reads and decodes the image at `path`, scaled to `height` pixels tall.
+pub fn load_image(path: &Path, height: u32) -> Option<RgbaImage> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::warn!("Could not read image {}: {}", path.display(), err);
+            return None;
+        }
+    };
+    let is_svg = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg") || ext.eq_ignore_ascii_case("svgz"));
+    let decoded = if is_svg {
+        render_svg(&bytes, height)
+    } else {
+        decode_image(&bytes, height)
+    };
+    if decoded.is_none() {
+        log::warn!("Could not decode image {}", path.display());
+    }
+    decoded
+}
+
+/// Decodes PNG or JPEG `bytes`, scaled to `height` pixels tall.
+pub fn decode_image(bytes: &[u8], height: u32) -> Option<RgbaImage> {
+    let image = image::load_from_memory(bytes).ok()?;
+    if image.height() == 0 {
+        return None;
+    }
+    let height = height.max(1);
+    let width = (image.width() as f32 * height as f32 / image.height() as f32).round().max(1.0) as u32;
+    // Triangle keeps large logos from aliasing when they are shrunk a lot.
+    Some(image.resize_exact(width, height, FilterType::Triangle).to_rgba8())
+}
+
+/// Renders SVG `bytes` at `height` pixels tall, so logos stay sharp at any
+/// output size. Text in the SVG is not drawn; convert it to paths first.
+pub fn render_svg(bytes: &[u8], height: u32) -> Option<RgbaImage> {
+    let tree = usvg::Tree::from_data(bytes, &usvg::Options::default()).ok()?;
+    let size = tree.size();
+    let scale = height.max(1) as f32 / size.height();
+    let width = (size.width() * scale).round().max(1.0) as u32;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height.max(1))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    // tiny-skia works in premultiplied alpha; sprites are uploaded straight.
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    RgbaImage::from_raw(pixmap.width(), pixmap.height(), pixels)
+}
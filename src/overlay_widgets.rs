@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+
+use crate::overlay::{Anchor, TextElementConfig, TextSource};
+use crate::overlay_animation::{AnimationConfig, Keyframe};
+
+/// A bar that fills up as the track plays.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ProgressBarConfig {
+    pub enabled: bool,
+    /// Length as a fraction of the frame width.
+    pub length: f32,
+    /// Height in reference pixels.
+    pub thickness: f32,
+    /// The played part.
+    pub color: [u8; 4],
+    /// The rest of the track; transparent hides it.
+    pub background: [u8; 4],
+    pub anchor: Anchor,
+    /// Distance from the anchored edges, x then y.
+    pub margin: [f32; 2],
+    pub animation: AnimationConfig,
+}
+
+impl Default for ProgressBarConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            length: 0.9,
+            thickness: 8.0,
+            color: [255, 255, 255, 230],
+            background: [255, 255, 255, 60],
+            anchor: Anchor::Bottom,
+            margin: [0.0, 96.0],
+            animation: AnimationConfig::default(),
+        }
+    }
+}
+
+/// A panel that slides in for a while to name a track, like a lower third.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CardConfig {
+    /// First line of the card, above the title and artist; empty leaves it out.
+    pub heading: String,
+    /// Seconds after the track starts, or before it ends for the up next
+    /// card, at which the card slides in.
+    pub at: f64,
+    /// Seconds the card stays up, including sliding in and out.
+    pub show_for: f64,
+    /// Seconds it takes to slide and fade in, and again to leave.
+    pub slide_time: f64,
+    /// Reference pixels it travels while sliding, away from its anchored edge.
+    pub slide_distance: f32,
+    /// Font, colors, panel and position, and whether the card is shown at
+    /// all. The text source is ignored, and the animation's timing, fades and
+    /// keyframes are replaced by the card's.
+    pub style: TextElementConfig,
+}
+
+impl CardConfig {
+    pub fn now_playing() -> Self {
+        Self {
+            heading: String::from("Now playing"),
+            at: 1.0,
+            ..Default::default()
+        }
+    }
+
+    pub fn up_next() -> Self {
+        Self {
+            heading: String::from("Up next"),
+            at: 12.0,
+            style: TextElementConfig {
+                anchor: Anchor::BottomRight,
+                ..Self::default().style
+            },
+            ..Default::default()
+        }
+    }
+
+    /// `style.animation` with the card's slide in between `start` and `end`
+    /// track seconds.
+    pub fn animation(&self, start: f64, end: f64) -> AnimationConfig {
+        let slide_time = self.slide_time.clamp(0.0, (end - start).max(0.0) / 2.0);
+        let offset = match self.style.anchor.factors() {
+            (x, _) if x < 0.25 => [-self.slide_distance, 0.0],
+            (x, _) if x > 0.75 => [self.slide_distance, 0.0],
+            (_, y) if y < 0.25 => [0.0, -self.slide_distance],
+            _ => [0.0, self.slide_distance],
+        };
+        let keyframe = |time, offset| Keyframe {
+            time,
+            offset,
+            ..Default::default()
+        };
+        AnimationConfig {
+            show_at: start,
+            hide_at: end,
+            fade_in: slide_time,
+            fade_out: slide_time,
+            keyframes: vec![
+                keyframe(start, offset),
+                keyframe(start + slide_time, [0.0, 0.0]),
+                keyframe(end - slide_time, [0.0, 0.0]),
+                keyframe(end, offset),
+            ],
+            ..self.style.animation.clone()
+        }
+    }
+
+    /// The card's lines for a track, skipping the ones it has nothing for.
+    pub fn text(&self, title: &str, artist: Option<&str>) -> String {
+        [Some(self.heading.as_str()), Some(title), artist]
+            .into_iter()
+            .flatten()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Default for CardConfig {
+    fn default() -> Self {
+        Self {
+            heading: String::new(),
+            at: 0.0,
+            show_for: 6.0,
+            slide_time: 0.6,
+            slide_distance: 480.0,
+            style: TextElementConfig {
+                enabled: false,
+                source: TextSource::Custom,
+                size: 44.0,
+                outline_width: 0.0,
+                shadow_color: [0, 0, 0, 0],
+                background: [0, 0, 0, 170],
+                anchor: Anchor::BottomLeft,
+                margin: [64.0, 160.0],
+                wrap: false,
+                ..Default::default()
+            },
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+
+pub const CONFIG_FILE: &str = "config.toml";
+/// File name of the control socket inside the runtime directory.
+pub const SOCKET_NAME: &str = "control.sock";
+
+/// `$HOME/.config/aurora-visualizer-rs/` on Linux.
+pub fn config_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "aurora-visualizer-rs").map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+/// `$HOME/.cache/aurora-visualizer-rs/` on Linux.
+pub fn cache_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "aurora-visualizer-rs").map(|dirs| dirs.cache_dir().to_path_buf())
+}
+
+/// `$XDG_RUNTIME_DIR/aurora-visualizer-rs/` on Linux, or the temp directory
+/// where there is no runtime directory.
+pub fn runtime_dir() -> PathBuf {
+    ProjectDirs::from("", "", "aurora-visualizer-rs")
+        .and_then(|dirs| dirs.runtime_dir().map(|dir| dir.to_path_buf()))
+        .unwrap_or_else(|| std::env::temp_dir().join("aurora-visualizer-rs"))
+}
+
+/// The control socket at `configured`, or `control.sock` in the runtime
+/// directory if that is empty.
+pub fn control_socket(configured: &Path) -> PathBuf {
+    if configured.as_os_str().is_empty() {
+        runtime_dir().join(SOCKET_NAME)
+    } else {
+        configured.to_path_buf()
+    }
+}
@@ -1,12 +1,12 @@
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Stream, StreamConfig, SizedSample, Sample};
-use egui::Ui;
-use num_traits::{FromPrimitive, ToPrimitive};
-use projectm::core::{ProjectM, STEREO};
+use cpal::{FromSample, Sample, SizedSample, Stream, StreamConfig};
+use egui::{Button, Ui};
 use rfd::FileDialog;
 use symphonia::core::audio::SampleBuffer;
 use symphonia::core::codecs::DecoderOptions;
@@ -16,27 +16,125 @@ use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 
+use crate::audio_bridge::AudioSender;
+use crate::audio_meter::{AudioMeter, AudioScope};
+use crate::beat_detector::{AudioEvents, BeatWorker, DetectorSender};
+use crate::live_input::{input_devices, AudioSource, InputConfig, InputDevice, InputSinks, LiveInput};
+use crate::metadata::{read_metadata, ReplayGain};
+use crate::normalization::{Gains, NormalizationConfig, TrackGain};
+use crate::output_stage::{OutputConfig, OutputControl, OutputStage};
+use crate::seek_bar::seek_bar;
+use crate::track_analysis::{TrackAnalysis, TrackAnalyzer};
+
+/// Length of the latency calibration click track.
+const CLICK_TRACK_SECS: usize = 60;
+/// Clicks are a short 1 kHz burst, loud enough for the bridge to flag them.
+const CLICK_HZ: f32 = 1000.0;
+const CLICK_SECS: f32 = 0.005;
+
 pub struct Playback {
     pub queue: Vec<PathBuf>,
     pub current_track_index: Option<usize>,
     stream: Option<Stream>,
     audio_data: Arc<Mutex<Vec<f32>>>,
     audio_position: Arc<Mutex<usize>>,
-    projectm: Arc<Mutex<ProjectM>>,
+    /// Channels of the samples in `audio_data`, read by the output callback.
+    track_channels: Arc<AtomicUsize>,
+    /// Sample rate and channel count of the decoded track.
+    track_format: Option<(u32, usize)>,
+    /// Beat detection on the samples the output callback plays.
+    beats: BeatWorker,
+    beat_sensitivity: f32,
+    /// Whole-track analysis of everything in the queue, done in the background.
+    analyzer: TrackAnalyzer,
+    meter: AudioMeter,
+    normalization: NormalizationConfig,
+    /// ReplayGain tags of the current track.
+    replay_gain: ReplayGain,
+    track_gain: Option<TrackGain>,
+    /// Read by the output callback.
+    gains: Arc<Mutex<Gains>>,
+    output: Arc<Mutex<OutputControl>>,
+    /// Holds the position; the output plays silence meanwhile.
+    paused: Arc<AtomicBool>,
+    /// Capture device in live input mode; files don't play while it is set.
+    input: Option<LiveInput>,
+    sinks: InputSinks,
+    /// Inputs offered in the UI, listed when asked for because probing devices is slow.
+    input_devices: Vec<InputDevice>,
+    output_device: String,
+    output_sample_rate: u32,
+    /// Track, position and pause state the click track interrupted.
+    before_clicks: Option<(Option<usize>, f64, bool)>,
+    /// Why the last track couldn't be played, shown in the playback panel.
+    pub status: String,
+}
+
+/// Everything that hears the played audio, and the gains it is played at.
+struct Listeners {
+    bridge: AudioSender,
+    beats: DetectorSender,
+    scope: Arc<AudioScope>,
+    gains: Arc<Mutex<Gains>>,
+    output: Arc<Mutex<OutputControl>>,
+    paused: Arc<AtomicBool>,
+}
+
+/// Copies one track frame into one device frame: mono is spread over every
+/// channel, a mono device gets the average, and otherwise channels map one to
+/// one with extra device channels left silent.
+fn map_channels(track: &[f32], device: &mut [f32], gain: f32) {
+    if track.len() == 1 {
+        device.iter_mut().for_each(|sample| *sample = track[0] * gain);
+    } else if device.len() == 1 {
+        device[0] = track.iter().sum::<f32>() / track.len() as f32 * gain;
+    } else {
+        for (sample, value) in device.iter_mut().zip(track) {
+            *sample = value * gain;
+        }
+    }
+}
+
+/// A whole track decoded to interleaved `f32` samples.
+pub struct DecodedAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: usize,
 }
 
 impl Playback {
-    pub fn new(projectm: Arc<ProjectM>) -> Self {
+    pub fn new(bridge: AudioSender, events: Arc<AudioEvents>, beat_sensitivity: f32) -> Self {
         let audio_data = Arc::new(Mutex::new(Vec::new()));
+        let gains = Arc::new(Mutex::new(Gains::default()));
+        let output = Arc::new(Mutex::new(OutputControl::default()));
+        let paused = Arc::new(AtomicBool::new(false));
         let audio_position = Arc::new(Mutex::new(0));
+        let track_channels = Arc::new(AtomicUsize::new(2));
+        let beats = BeatWorker::spawn(events.clone(), beat_sensitivity);
 
         let audio_data_clone = audio_data.clone();
         let audio_position_clone = audio_position.clone();
-        let projectm_clone = Arc::new(Mutex::new((*projectm).clone()));
 
         let host = cpal::default_host();
         let device = host.default_output_device().unwrap();
         let config = device.default_output_config().unwrap();
+        let output_device = device.name().unwrap_or_default();
+        let output_sample_rate = config.sample_rate().0;
+        let scope = Arc::new(AudioScope::new(config.sample_rate().0));
+        let sinks = InputSinks {
+            bridge: bridge.clone(),
+            events: events.clone(),
+            scope: scope.clone(),
+            gains: gains.clone(),
+        };
+        let listeners = Listeners {
+            bridge,
+            beats: beats.sender(),
+            scope: scope.clone(),
+            gains: gains.clone(),
+            output: output.clone(),
+            paused: paused.clone(),
+        };
 
         let stream = match config.sample_format() {
             cpal::SampleFormat::F32 => Self::create_stream::<f32>(
@@ -44,21 +142,24 @@ impl Playback {
                 config.into(),
                 audio_data_clone,
                 audio_position_clone,
-                projectm_clone,
+                track_channels.clone(),
+                listeners,
             ),
             cpal::SampleFormat::I16 => Self::create_stream::<i16>(
                 device,
                 config.into(),
                 audio_data_clone,
                 audio_position_clone,
-                projectm_clone,
+                track_channels.clone(),
+                listeners,
             ),
             cpal::SampleFormat::U16 => Self::create_stream::<u16>(
                 device,
                 config.into(),
                 audio_data_clone,
                 audio_position_clone,
-                projectm_clone,
+                track_channels.clone(),
+                listeners,
             ),
             _ => panic!("Unsupported sample format"),
         };
@@ -71,7 +172,25 @@ impl Playback {
             stream: Some(stream),
             audio_data,
             audio_position,
-            projectm: Arc::new(Mutex::new((*projectm).clone())),
+            track_channels,
+            track_format: None,
+            beats,
+            beat_sensitivity,
+            analyzer: TrackAnalyzer::new(),
+            meter: AudioMeter::new(scope),
+            normalization: NormalizationConfig::default(),
+            replay_gain: ReplayGain::default(),
+            track_gain: None,
+            gains,
+            output,
+            paused,
+            input: None,
+            sinks,
+            input_devices: Vec::new(),
+            output_device,
+            output_sample_rate,
+            before_clicks: None,
+            status: String::new(),
         }
     }
 
@@ -80,34 +199,57 @@ impl Playback {
         config: StreamConfig,
         audio_data: Arc<Mutex<Vec<f32>>>,
         audio_position: Arc<Mutex<usize>>,
-        projectm: Arc<Mutex<ProjectM>>,
+        track_channels: Arc<AtomicUsize>,
+        listeners: Listeners,
     ) -> Stream
     where
-        T: SizedSample + Sample + FromPrimitive + ToPrimitive,
+        T: SizedSample + FromSample<f32>,
     {
+        let device_channels = (config.channels as usize).max(1);
+        let mut stage = OutputStage::new(config.sample_rate.0, device_channels);
         device
             .build_output_stream(
                 &config,
-                move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-                    let mut audio_data = audio_data.lock().unwrap();
+                move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+                    let audio_data = audio_data.lock().unwrap();
                     let mut audio_position = audio_position.lock().unwrap();
+                    let start = *audio_position;
+                    let channels = track_channels.load(Ordering::Relaxed).max(1);
+                    let gains = *listeners.gains.lock().unwrap();
 
-                    for sample in data.iter_mut() {
-                        if *audio_position < audio_data.len() {
-                            *sample = T::from_f32(audio_data[*audio_position]).unwrap();
-                            *audio_position += 1;
-                        } else {
-                            *sample = T::from_f32(0.0f32).unwrap();
+                    // projectM hears the track at the visual gain, whatever the output level.
+                    // Volume and limiting only touch the output.
+                    let mut pcm_data: Vec<f32> = Vec::with_capacity(data.len());
+                    let mut output: Vec<f32> = vec![0.0; data.len()];
+                    let paused = listeners.paused.load(Ordering::Relaxed);
+                    for frame in output.chunks_mut(device_channels) {
+                        if paused || *audio_position + channels > audio_data.len() {
+                            break;
                         }
+                        let track_frame = &audio_data[*audio_position..*audio_position + channels];
+                        map_channels(track_frame, frame, gains.output);
+                        pcm_data.extend(track_frame.iter().map(|value| value * gains.visual));
+                        *audio_position += channels;
+                    }
+                    stage.process(&mut output, &listeners.output.lock().unwrap());
+                    for (sample, value) in data.iter_mut().zip(&output) {
+                        *sample = value.to_sample::<T>();
+                    }
+
+                    if *audio_position > start {
+                        // The detector runs on its own thread, off this realtime one.
+                        let played = audio_data[start..*audio_position].to_vec();
+                        listeners.beats.send(played, channels, (start / channels) as u64);
                     }
 
-                    let pcm_data: Vec<f32> = data
-                        .iter()
-                        .map(|s| s.to_f32().unwrap())
-                        .collect();
-                    
-                    for chunk in pcm_data.chunks(512) {
-                        projectm.lock().unwrap().pcm_add_float(chunk, STEREO);
+                    if !pcm_data.is_empty() {
+                        listeners.scope.push(&pcm_data, channels);
+                        let timestamp = info.timestamp();
+                        let latency = timestamp
+                            .playback
+                            .duration_since(&timestamp.callback)
+                            .unwrap_or_default();
+                        listeners.bridge.send(pcm_data, channels, latency);
                     }
                 },
                 |err| eprintln!("an error occurred on stream: {}", err),
@@ -117,77 +259,334 @@ impl Playback {
     }
 
     pub fn add_files(&mut self, files: Vec<PathBuf>) {
+        for file in &files {
+            self.analyzer.request(file.clone());
+        }
         self.queue.extend(files);
-        if self.current_track_index.is_none() && !self.queue.is_empty() {
+        if self.current_track_index.is_none() && !self.queue.is_empty() && self.input.is_none() {
             self.play_track(0);
         }
     }
 
-    fn play_track(&mut self, index: usize) {
-        if let Some(path) = self.queue.get(index) {
-            let mut hint = Hint::new();
-            hint.with_extension(path.extension().unwrap().to_str().unwrap());
-            let source = Box::new(File::open(path).unwrap());
-            let mss = MediaSourceStream::new(source, Default::default());
-            let format_opts = FormatOptions {
-                enable_gapless: true,
-                ..Default::default()
-            };
-            let metadata_opts: MetadataOptions = Default::default();
-            let decoder_opts: DecoderOptions = Default::default();
-            let mut probed =
-                symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts).unwrap();
-
-            let track = probed.format.default_track().unwrap();
-            let track_id = track.id;
-            let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &decoder_opts).unwrap();
-
-            let mut samples_f32: Vec<f32> = Vec::new();
-
-            loop {
-                let packet = match probed.format.next_packet() {
-                    Ok(packet) => packet,
-                    Err(Error::ResetRequired) => {
-                        unimplemented!();
-                    }
-                    Err(Error::IoError(_)) => {
-                        break;
-                    }
-                    Err(err) => {
-                        panic!("{}", err);
-                    }
-                };
-
-                if packet.track_id() != track_id {
-                    continue;
+    pub fn play_track(&mut self, index: usize) {
+        if self.input.is_some() {
+            return;
+        }
+        if let Some(path) = self.queue.get(index).cloned() {
+            let decoded = match decode_file(&path) {
+                Ok(decoded) => decoded,
+                Err(err) => {
+                    log::warn!("Could not play {}: {}", path.display(), err);
+                    self.status = format!("Could not play {}: {}", path.display(), err);
+                    return;
                 }
+            };
+            self.status = String::new();
+            self.paused.store(false, Ordering::Relaxed);
 
-                match decoder.decode(&packet) {
-                    Ok(decoded) => {
-                        let mut sample_buf =
-                            SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
-                        sample_buf.copy_interleaved_ref(decoded);
-                        samples_f32.extend_from_slice(sample_buf.samples());
-                    }
-                    Err(Error::IoError(_)) => {
-                        break;
-                    }
-                    Err(Error::DecodeError(_)) => {
-                        continue;
-                    }
-                    Err(err) => {
-                        panic!("{}", err);
-                    }
-                }
-            }
+            // Settle the gains first so the new track starts at its own level.
+            self.current_track_index = Some(index);
+            self.replay_gain = read_metadata(&path).replay_gain;
+            self.update_gains();
 
             let mut audio_data = self.audio_data.lock().unwrap();
             let mut audio_position = self.audio_position.lock().unwrap();
 
-            *audio_data = samples_f32;
+            *audio_data = decoded.samples;
             *audio_position = 0;
-            self.current_track_index = Some(index);
+            self.track_channels.store(decoded.channels, Ordering::Relaxed);
+            self.track_format = Some((decoded.sample_rate, decoded.channels));
+            // Still holding the samples, so the callback can't send any of the
+            // new track before the detector has been told about it.
+            self.beats.restart(Some(decoded.sample_rate));
+        }
+    }
+
+    /// Switches between playing files and capturing an input device.
+    pub fn set_input(&mut self, config: &InputConfig) {
+        match config.source {
+            AudioSource::Files => {
+                if self.input.take().is_some() && !self.queue.is_empty() {
+                    self.play_track(0);
+                }
+            }
+            AudioSource::Input => {
+                if self
+                    .input
+                    .as_ref()
+                    .is_some_and(|input| input.device() == config.device)
+                {
+                    return;
+                }
+                // Drop the old stream before opening the new device.
+                self.input = None;
+                self.stop();
+                self.current_track_index = None;
+                self.track_format = None;
+                self.beats.restart(None);
+                self.update_gains();
+                self.input = Some(LiveInput::new(
+                    &config.device,
+                    self.sinks.clone(),
+                    self.beat_sensitivity,
+                ));
+            }
+        }
+    }
+
+    pub fn input_devices(&self) -> &[InputDevice] {
+        &self.input_devices
+    }
+
+    pub fn refresh_input_devices(&mut self) {
+        self.input_devices = input_devices();
+    }
+
+    /// Per-frame housekeeping: gains and reconnecting a lost input device.
+    pub fn update(&mut self) {
+        self.update_gains();
+        if let Some(input) = &mut self.input {
+            input.update();
+        }
+    }
+
+    /// Name the A/V offset of the current device is saved under: the output
+    /// device when playing files, the capture device in live input mode.
+    pub fn latency_key(&self) -> String {
+        match &self.input {
+            Some(input) => format!("input:{}", input.device()),
+            None => self.output_device.clone(),
+        }
+    }
+
+    /// Delays the visuals by `offset_ms` on top of the measured output latency.
+    pub fn set_visual_offset(&self, offset_ms: f32) {
+        self.sinks.bridge.set_offset_ms(offset_ms);
+    }
+
+    /// Output latency reported by the audio device.
+    pub fn output_latency(&self) -> Duration {
+        self.sinks.bridge.latency()
+    }
+
+    /// Plays a click every second in place of the current track, for lining
+    /// up the visuals with the sound, until `stop_click_track`. Does nothing
+    /// in live input mode.
+    pub fn play_click_track(&mut self) {
+        if self.input.is_some() {
+            return;
+        }
+        if self.before_clicks.is_none() {
+            self.before_clicks =
+                Some((self.current_track_index, self.position_secs(), self.is_paused()));
+        }
+        self.paused.store(false, Ordering::Relaxed);
+        let sample_rate = self.output_sample_rate;
+        let click_len = (CLICK_SECS * sample_rate as f32) as usize;
+        let mut samples = vec![0.0; CLICK_TRACK_SECS * sample_rate as usize];
+        for second in samples.chunks_mut(sample_rate as usize) {
+            for (i, sample) in second.iter_mut().take(click_len).enumerate() {
+                let t = i as f32 / sample_rate as f32;
+                *sample = 0.9 * (std::f32::consts::TAU * CLICK_HZ * t).sin().signum();
+            }
+        }
+
+        self.current_track_index = None;
+        self.replay_gain = ReplayGain::default();
+        self.update_gains();
+        self.beats.restart(None);
+        let mut audio_data = self.audio_data.lock().unwrap();
+        let mut audio_position = self.audio_position.lock().unwrap();
+        *audio_data = samples;
+        *audio_position = 0;
+        self.track_channels.store(1, Ordering::Relaxed);
+        self.track_format = Some((sample_rate, 1));
+    }
+
+    /// Goes back to where playback was before `play_click_track`.
+    pub fn stop_click_track(&mut self) {
+        let Some((index, position, paused)) = self.before_clicks.take() else {
+            return;
+        };
+        match index {
+            Some(index) => {
+                self.play_track(index);
+                self.seek(position);
+            }
+            None => self.stop(),
+        }
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn set_output(&mut self, config: &OutputConfig) {
+        self.output.lock().unwrap().config = config.clone();
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.output.lock().unwrap().muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.output.lock().unwrap().muted = muted;
+    }
+
+    pub fn set_normalization(&mut self, normalization: NormalizationConfig) {
+        self.normalization = normalization;
+        self.update_gains();
+    }
+
+    /// Recomputes the output and visual gains of the current track, picking
+    /// up its analysis once the background pass has finished.
+    fn update_gains(&mut self) {
+        let analysis = self.track_analysis();
+        let track_gain = self
+            .normalization
+            .track_gain(&self.replay_gain, analysis.as_deref());
+        *self.gains.lock().unwrap() = self.normalization.gains(track_gain);
+        self.track_gain = track_gain;
+    }
+
+    /// Resumes a paused track, starts a stopped one over, or starts the queue.
+    pub fn play(&mut self) {
+        if self.input.is_some() {
+            return;
+        }
+        if self.current_track_index.is_none() {
+            if !self.queue.is_empty() {
+                self.play_track(0);
+            }
+            return;
+        }
+        if self.is_finished() {
+            self.restart();
         }
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused {
+            self.paused.store(true, Ordering::Relaxed);
+        } else {
+            self.play();
+        }
+    }
+
+    /// True while a track is playing and not paused.
+    pub fn is_playing(&self) -> bool {
+        self.current_track_index.is_some() && !self.is_paused() && !self.is_finished()
+    }
+
+    pub fn has_next_track(&self) -> bool {
+        self.input.is_none()
+            && self
+                .current_track_index
+                .is_some_and(|index| index + 1 < self.queue.len())
+    }
+
+    /// The track `next_track` would play.
+    pub fn upcoming_track(&self) -> Option<&PathBuf> {
+        self.has_next_track()
+            .then(|| &self.queue[self.current_track_index.unwrap() + 1])
+    }
+
+    pub fn has_previous_track(&self) -> bool {
+        self.input.is_none() && self.current_track_index.is_some_and(|index| index > 0)
+    }
+
+    pub fn next_track(&mut self) {
+        if self.has_next_track() {
+            self.play_track(self.current_track_index.unwrap() + 1);
+        }
+    }
+
+    /// Goes back a track, or to the start of this one once it has played a few seconds.
+    pub fn previous_track(&mut self) {
+        if self.position_secs() > 3.0 || !self.has_previous_track() {
+            self.restart();
+        } else {
+            self.play_track(self.current_track_index.unwrap() - 1);
+        }
+    }
+
+    /// Starts the current track over from the beginning.
+    pub fn restart(&mut self) {
+        if self.current_track_index.is_some() {
+            *self.audio_position.lock().unwrap() = 0;
+        }
+    }
+
+    /// Jumps to `seconds` into the current track.
+    pub fn seek(&mut self, seconds: f64) {
+        if let Some((sample_rate, channels)) = self.track_format {
+            let len = self.audio_data.lock().unwrap().len();
+            let frame = (seconds.max(0.0) * sample_rate as f64) as usize;
+            *self.audio_position.lock().unwrap() = (frame * channels).min(len);
+        }
+    }
+
+    /// Silences the output without forgetting the queue.
+    pub fn stop(&mut self) {
+        let len = self.audio_data.lock().unwrap().len();
+        *self.audio_position.lock().unwrap() = len;
+    }
+
+    pub fn current_track(&self) -> Option<&PathBuf> {
+        self.current_track_index.and_then(|index| self.queue.get(index))
+    }
+
+    /// Playback position of the current track in seconds.
+    pub fn position_secs(&self) -> f64 {
+        match self.track_format {
+            Some((sample_rate, channels)) => {
+                let position = *self.audio_position.lock().unwrap();
+                position as f64 / channels as f64 / sample_rate as f64
+            }
+            None => 0.0,
+        }
+    }
+
+    pub fn set_beat_sensitivity(&mut self, sensitivity: f32) {
+        self.beat_sensitivity = sensitivity;
+        self.beats.set_sensitivity(sensitivity);
+        if let Some(input) = &mut self.input {
+            input.set_beat_sensitivity(sensitivity);
+        }
+    }
+
+    /// Tempo of the current track as detected so far, or from its analysis
+    /// before enough has played.
+    pub fn bpm(&self) -> Option<f32> {
+        if let Some(input) = &self.input {
+            return input.bpm();
+        }
+        self.beats
+            .bpm()
+            .or_else(|| self.track_analysis().and_then(|analysis| analysis.bpm))
+    }
+
+    /// Whole-track analysis of the current track, once the background pass has finished.
+    pub fn track_analysis(&self) -> Option<Arc<TrackAnalysis>> {
+        self.current_track().and_then(|path| self.analyzer.get(path))
+    }
+
+    /// Length of the current track in seconds.
+    pub fn duration_secs(&self) -> f64 {
+        match self.track_format {
+            Some((sample_rate, channels)) => {
+                let len = self.audio_data.lock().unwrap().len();
+                len as f64 / channels as f64 / sample_rate as f64
+            }
+            None => 0.0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        let len = self.audio_data.lock().unwrap().len();
+        *self.audio_position.lock().unwrap() >= len
     }
 
     pub fn ui(&mut self, ui: &mut Ui) {
@@ -209,5 +608,134 @@ impl Playback {
                 ui.label(format!("Now Playing: {}", path.display()));
             }
         }
+        if !self.status.is_empty() {
+            ui.colored_label(ui.visuals().warn_fg_color, &self.status);
+        }
+        if let Some(input) = &self.input {
+            if input.is_connected() {
+                ui.label("Live input");
+            } else {
+                ui.colored_label(ui.visuals().warn_fg_color, &input.status);
+            }
+        } else if self.current_track_index.is_some() {
+            ui.horizontal(|ui| {
+                if ui.button("⏮").clicked() {
+                    self.previous_track();
+                }
+                let label = if self.is_playing() { "⏸" } else { "⏵" };
+                if ui.button(label).clicked() {
+                    self.set_paused(self.is_playing());
+                }
+                if ui
+                    .add_enabled(self.has_next_track(), Button::new("⏭"))
+                    .clicked()
+                {
+                    self.next_track();
+                }
+            });
+            let analysis = self.track_analysis();
+            if let Some(time) = seek_bar(
+                ui,
+                analysis.as_deref(),
+                self.position_secs(),
+                self.duration_secs(),
+            ) {
+                self.seek(time);
+            }
+        }
+        self.meter.ui(ui);
+        if let Some(bpm) = self.bpm() {
+            ui.label(format!("Tempo: {:.0} BPM", bpm));
+        }
+        if let Some(analysis) = self.track_analysis() {
+            if let (Some(lufs), Some(peak)) = (analysis.integrated_lufs, analysis.true_peak_db) {
+                ui.label(format!("Loudness: {:.1} LUFS, peak {:.1} dBTP", lufs, peak));
+            }
+        } else if self.current_track().is_some() {
+            ui.weak("Analyzing...");
+        }
+        if let Some(track_gain) = self.track_gain {
+            let source = if track_gain.from_tags {
+                "ReplayGain"
+            } else {
+                "measured"
+            };
+            ui.label(format!("Normalization: {:+.1} dB ({})", track_gain.gain_db, source));
+        }
+    }
+}
+
+/// Decodes the whole file at `path`, or returns why it couldn't be read.
+pub fn decode_file(path: &Path) -> Result<DecodedAudio, Error> {
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    let source = Box::new(File::open(path)?);
+    let mss = MediaSourceStream::new(source, Default::default());
+    let format_opts = FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+    };
+    let metadata_opts: MetadataOptions = Default::default();
+    let decoder_opts: DecoderOptions = Default::default();
+    let mut probed =
+        symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts)?;
+
+    let track = probed
+        .format
+        .default_track()
+        .ok_or(Error::Unsupported("no audio track"))?;
+    let track_id = track.id;
+    let mut sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
+    let mut channels = track.codec_params.channels.map(|c| c.count()).unwrap_or(2);
+    let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &decoder_opts)?;
+
+    let mut samples_f32: Vec<f32> = Vec::new();
+
+    loop {
+        let packet = match probed.format.next_packet() {
+            Ok(packet) => packet,
+            // A new track list starts; chained streams are rare enough to stop at the first.
+            Err(Error::ResetRequired) => {
+                break;
+            }
+            Err(Error::IoError(_)) => {
+                break;
+            }
+            Err(err) => {
+                return Err(err);
+            }
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                sample_rate = decoded.spec().rate;
+                channels = decoded.spec().channels.count();
+                let mut sample_buf =
+                    SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+                sample_buf.copy_interleaved_ref(decoded);
+                samples_f32.extend_from_slice(sample_buf.samples());
+            }
+            Err(Error::IoError(_)) => {
+                break;
+            }
+            Err(Error::DecodeError(_)) => {
+                continue;
+            }
+            Err(err) => {
+                return Err(err);
+            }
+        }
     }
-}
\ No newline at end of file
+
+    Ok(DecodedAudio {
+        samples: samples_f32,
+        sample_rate,
+        channels,
+    })
+}
@@ -0,0 +1,237 @@
+use std::sync::{Arc, Mutex};
+
+use projectm::core::ProjectM;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::beat_detector::AudioEvent;
+
+/// How loud a passage is compared to the rest of the track, and the preset
+/// tag (`intensity:low` ...) used to match presets to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Intensity {
+    Low,
+    Medium,
+    High,
+}
+
+impl Intensity {
+    pub const ALL: [Intensity; 3] = [Intensity::Low, Intensity::Medium, Intensity::High];
+
+    pub fn tag(self) -> &'static str {
+        match self {
+            Intensity::Low => "intensity:low",
+            Intensity::Medium => "intensity:medium",
+            Intensity::High => "intensity:high",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Intensity::Low => "Low",
+            Intensity::Medium => "Medium",
+            Intensity::High => "High",
+        }
+    }
+
+    /// Classifies short-term loudness against the track's longer-term average.
+    fn from_energy(short_db: f32, long_db: f32) -> Self {
+        match short_db - long_db {
+            gap if gap < -3.0 => Intensity::Low,
+            gap if gap > 1.5 => Intensity::High,
+            _ => Intensity::Medium,
+        }
+    }
+}
+
+/// The preset projectM is showing, whoever loaded it. Every preset load goes
+/// through `load`, including the recorder's on the render thread.
+#[derive(Clone, Default)]
+pub struct CurrentPreset(Arc<Mutex<Option<String>>>);
+
+impl CurrentPreset {
+    pub fn get(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Loads `preset` into projectM and makes it current.
+    pub fn load(&self, projectm: &ProjectM, preset: &str, smooth_transition: bool) {
+        projectm.load_preset_file(preset, smooth_transition);
+        *self.0.lock().unwrap() = Some(preset.to_string());
+    }
+}
+
+/// Automatic preset switching outside of recordings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DirectorConfig {
+    /// Off leaves switching to projectM's timer, which steps through the
+    /// presets in order.
+    pub enabled: bool,
+    /// Wait for a downbeat once `Config::preset_duration` has passed,
+    /// instead of switching mid-bar.
+    pub downbeat_sync: bool,
+    /// No preset is replaced before it has been shown this many seconds.
+    pub min_hold: f64,
+    /// Switch early when the loudness jumps by at least `energy_jump_db`.
+    pub energy_switching: bool,
+    pub energy_jump_db: f32,
+    /// Seconds after an energy-triggered switch before another may happen.
+    pub energy_cooldown: f64,
+    /// Pick presets tagged with the current intensity, when there are any.
+    pub match_intensity: bool,
+    pub smooth_transitions: bool,
+    /// Length of smooth transitions in seconds.
+    pub transition_duration: f64,
+}
+
+impl Default for DirectorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            downbeat_sync: true,
+            min_hold: 4.0,
+            energy_switching: true,
+            energy_jump_db: 6.0,
+            energy_cooldown: 15.0,
+            match_intensity: false,
+            smooth_transitions: true,
+            transition_duration: 2.0,
+        }
+    }
+}
+
+/// Presets remembered for stepping back.
+const HISTORY_LEN: usize = 100;
+
+/// Decides when to switch presets from the beats and loudness of the music,
+/// and remembers what it has shown so the user can step back.
+pub struct PresetDirector {
+    history: Vec<String>,
+    /// Wall-clock seconds of the last switch.
+    last_switch: Option<f64>,
+    last_energy_switch: Option<f64>,
+    intensity: Intensity,
+    rng: StdRng,
+}
+
+impl PresetDirector {
+    pub fn new() -> Self {
+        Self {
+            history: Vec::new(),
+            last_switch: None,
+            last_energy_switch: None,
+            intensity: Intensity::Medium,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// The director's last pick, which may no longer be showing; see `CurrentPreset`.
+    fn current(&self) -> Option<&str> {
+        self.history.last().map(String::as_str)
+    }
+
+    /// The intensity of what is playing right now.
+    pub fn intensity(&self) -> Intensity {
+        self.intensity
+    }
+
+    /// Looks at the audio events since the last call and says whether it is
+    /// time for a new preset. `now` is wall-clock seconds, so presets keep
+    /// changing while nothing plays.
+    pub fn update(
+        &mut self,
+        now: f64,
+        events: &[AudioEvent],
+        config: &DirectorConfig,
+        preset_duration: f64,
+    ) -> bool {
+        let due = self.switch_due(now, events, config, preset_duration);
+        if due {
+            // Counted as a switch even if no preset can be picked, so an empty
+            // pool is tried again after a while rather than every frame.
+            self.last_switch = Some(now);
+        }
+        due
+    }
+
+    fn switch_due(
+        &mut self,
+        now: f64,
+        events: &[AudioEvent],
+        config: &DirectorConfig,
+        preset_duration: f64,
+    ) -> bool {
+        let Some(last_switch) = self.last_switch else {
+            return true;
+        };
+        let held = now - last_switch;
+
+        let mut downbeat = false;
+        let mut energy_jump = false;
+        for event in events {
+            match *event {
+                AudioEvent::Beat { downbeat: true, .. } => downbeat = true,
+                AudioEvent::Energy {
+                    short_db, long_db, ..
+                } => {
+                    self.intensity = Intensity::from_energy(short_db, long_db);
+                    energy_jump |= (short_db - long_db).abs() >= config.energy_jump_db;
+                }
+                _ => {}
+            }
+        }
+
+        if held < config.min_hold {
+            return false;
+        }
+        let cooled_down = self
+            .last_energy_switch
+            .is_none_or(|last| now - last >= config.energy_cooldown);
+        if config.energy_switching && energy_jump && cooled_down {
+            self.last_energy_switch = Some(now);
+            return true;
+        }
+        if !config.downbeat_sync {
+            return held >= preset_duration;
+        }
+        // Quiet or beatless passages never produce a downbeat; don't wait forever.
+        (held >= preset_duration && downbeat) || held >= preset_duration * 1.5
+    }
+
+    /// Picks a random preset from `pool` that isn't the current one and makes it current.
+    pub fn choose(&mut self, now: f64, pool: &[String]) -> Option<String> {
+        if pool.is_empty() {
+            return None;
+        }
+        let mut preset = &pool[self.rng.gen_range(0..pool.len())];
+        if pool.len() > 1 {
+            while Some(preset.as_str()) == self.current() {
+                preset = &pool[self.rng.gen_range(0..pool.len())];
+            }
+        }
+        let preset = preset.clone();
+        self.show(now, &preset);
+        Some(preset)
+    }
+
+    /// Makes `preset` current, as if the director had picked it.
+    pub fn show(&mut self, now: f64, preset: &str) {
+        if self.history.len() >= HISTORY_LEN {
+            self.history.remove(0);
+        }
+        self.history.push(preset.to_string());
+        self.last_switch = Some(now);
+    }
+
+    /// Goes back to the preset shown before the current one.
+    pub fn previous(&mut self, now: f64) -> Option<String> {
+        if self.history.len() < 2 {
+            return None;
+        }
+        self.history.pop();
+        self.last_switch = Some(now);
+        self.history.last().cloned()
+    }
+}
@@ -0,0 +1,217 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// Where the recorder draws its presets from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PresetSource {
+    /// Every preset under `Config::preset_path`.
+    All,
+    /// Only presets in `Favorites`; with none, the render is refused.
+    Favorites,
+    /// Only presets carrying the given tag in `PresetTags`; with none, the render is refused.
+    Tag(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SwitchMode {
+    /// Switch every `interval` seconds of track time.
+    Interval,
+    /// Wait at least `interval` seconds, then switch on the next detected beat.
+    Beat,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RotationConfig {
+    pub source: PresetSource,
+    pub switch_mode: SwitchMode,
+    /// Seconds between switches, or the minimum hold time in beat mode.
+    pub interval: f64,
+    pub smooth_transitions: bool,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        Self {
+            source: PresetSource::Favorites,
+            switch_mode: SwitchMode::Interval,
+            interval: 10.0,
+            smooth_transitions: true,
+        }
+    }
+}
+
+/// Preset switch requests raised by projectM's timer from inside `render_frame`.
+///
+/// projectM only has a single callback slot, so the app installs one handler
+/// that records requests here and decides afterwards who acts on them.
+#[derive(Default)]
+pub struct SwitchRequests {
+    pending: AtomicBool,
+}
+
+impl SwitchRequests {
+    pub fn raise(&self) {
+        self.pending.store(true, Ordering::Relaxed);
+    }
+
+    /// Consumes the pending request, if any.
+    pub fn take(&self) -> bool {
+        self.pending.swap(false, Ordering::Relaxed)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleEntry {
+    /// Track time in seconds at which the preset was loaded.
+    pub time: f64,
+    pub preset: String,
+}
+
+/// The exact preset timeline of one render, written next to the video.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PresetSchedule {
+    pub audio: PathBuf,
+    pub rotation: RotationConfig,
+    pub seed: u64,
+    pub entries: Vec<ScheduleEntry>,
+}
+
+impl PresetSchedule {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        serde_json::from_str(&contents).map_err(io::Error::other)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let contents = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+}
+
+/// Picks the preset for every moment of a render and records what it picked.
+pub struct PresetRotation {
+    pool: Vec<String>,
+    rng: StdRng,
+    /// Entries still to be played back when replaying a saved schedule.
+    replay: Option<std::vec::IntoIter<ScheduleEntry>>,
+    last_switch: Option<f64>,
+    schedule: PresetSchedule,
+}
+
+impl PresetRotation {
+    pub fn new(audio: PathBuf, rotation: RotationConfig, pool: Vec<String>) -> Self {
+        let seed = rand::random();
+        Self {
+            pool,
+            rng: StdRng::seed_from_u64(seed),
+            replay: None,
+            last_switch: None,
+            schedule: PresetSchedule {
+                audio,
+                rotation,
+                seed,
+                entries: Vec::new(),
+            },
+        }
+    }
+
+    /// Plays back a saved schedule instead of choosing presets.
+    pub fn replay(saved: PresetSchedule) -> Self {
+        let entries = saved.entries.clone();
+        Self {
+            pool: Vec::new(),
+            rng: StdRng::seed_from_u64(saved.seed),
+            replay: Some(entries.into_iter()),
+            last_switch: None,
+            schedule: PresetSchedule {
+                entries: Vec::new(),
+                ..saved
+            },
+        }
+    }
+
+    pub fn smooth_transitions(&self) -> bool {
+        self.schedule.rotation.smooth_transitions
+    }
+
+    pub fn schedule(&self) -> &PresetSchedule {
+        &self.schedule
+    }
+
+    /// Returns the preset to load at `media_time`, if it is time to switch.
+    /// `beat` says whether a beat was detected since the last call.
+    pub fn update(&mut self, media_time: f64, beat: bool) -> Option<String> {
+        if let Some(replay) = &mut self.replay {
+            let entry = replay.as_slice().first()?;
+            if entry.time > media_time {
+                return None;
+            }
+            let entry = replay.next()?;
+            return Some(self.record(entry.time, entry.preset));
+        }
+
+        let due = match self.last_switch {
+            None => true,
+            Some(last) => {
+                let held = media_time - last;
+                let interval = self.schedule.rotation.interval;
+                match self.schedule.rotation.switch_mode {
+                    SwitchMode::Interval => held >= interval,
+                    // Don't wait forever on tracks without a detectable beat.
+                    SwitchMode::Beat => (held >= interval && beat) || held >= interval * 2.0,
+                }
+            }
+        };
+        if !due || self.pool.is_empty() {
+            return None;
+        }
+
+        let current = self.schedule.entries.last().map(|entry| entry.preset.clone());
+        let mut preset = self.pool[self.rng.gen_range(0..self.pool.len())].clone();
+        if self.pool.len() > 1 {
+            while Some(&preset) == current.as_ref() {
+                preset = self.pool[self.rng.gen_range(0..self.pool.len())].clone();
+            }
+        }
+        Some(self.record(media_time, preset))
+    }
+
+    fn record(&mut self, time: f64, preset: String) -> String {
+        self.last_switch = Some(time);
+        self.schedule.entries.push(ScheduleEntry {
+            time,
+            preset: preset.clone(),
+        });
+        preset
+    }
+}
+
+/// Recursively lists every `.milk` preset below `dir`, sorted.
+pub fn scan_presets(dir: &Path) -> Vec<String> {
+    let mut presets = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "milk") {
+                presets.push(path.to_string_lossy().to_string());
+            }
+        }
+    }
+    presets.sort();
+    presets
+}
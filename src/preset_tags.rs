@@ -0,0 +1,78 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+const TAGS_FILE: &str = "preset_tags.txt";
+
+/// Free-form tags attached to presets, used to build named collections.
+///
+/// Stored one `tag<TAB>preset path` pair per line.
+pub struct PresetTags {
+    tags: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl PresetTags {
+    pub fn new() -> Self {
+        let mut tags: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        if Path::new(TAGS_FILE).exists() {
+            let file = File::open(TAGS_FILE).unwrap();
+            let reader = BufReader::new(file);
+            for line in reader.lines().map(|line| line.unwrap()) {
+                if let Some((tag, preset)) = line.split_once('\t') {
+                    tags.entry(tag.to_string())
+                        .or_default()
+                        .insert(preset.to_string());
+                }
+            }
+        }
+
+        Self { tags }
+    }
+
+    pub fn add(&mut self, preset_path: &str, tag: &str) {
+        if self
+            .tags
+            .entry(tag.to_string())
+            .or_default()
+            .insert(preset_path.to_string())
+        {
+            self.save();
+        }
+    }
+
+    pub fn remove(&mut self, preset_path: &str, tag: &str) {
+        if let Some(presets) = self.tags.get_mut(tag) {
+            if presets.remove(preset_path) {
+                if presets.is_empty() {
+                    self.tags.remove(tag);
+                }
+                self.save();
+            }
+        }
+    }
+
+    pub fn has_tag(&self, preset_path: &str, tag: &str) -> bool {
+        self.tags
+            .get(tag)
+            .is_some_and(|presets| presets.contains(preset_path))
+    }
+
+    /// All known tag names, sorted.
+    pub fn tags(&self) -> impl Iterator<Item = &String> {
+        self.tags.keys()
+    }
+
+    pub fn presets_with_tag(&self, tag: &str) -> impl Iterator<Item = &String> {
+        self.tags.get(tag).into_iter().flatten()
+    }
+
+    fn save(&self) {
+        let mut file = File::create(TAGS_FILE).unwrap();
+        for (tag, presets) in &self.tags {
+            for preset in presets {
+                writeln!(file, "{}\t{}", tag, preset).unwrap();
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use egui::{PaintCallback, Vec2};
 use egui_glow::{
@@ -9,20 +9,33 @@ use egui_glow::{
 };
 use projectm::core::ProjectM;
 
+use crate::audio_bridge::AudioBridge;
+use crate::overlay::Overlay;
+use crate::recorder::Recorder;
+
 pub struct ProjectMVisualizer {
     projectm: Arc<ProjectM>,
+    /// Audio for projectM, fed on the render thread before each frame.
+    bridge: Arc<Mutex<AudioBridge>>,
     texture: Option<glow::Texture>,
     fbo: Option<glow::Framebuffer>,
     size: Vec2,
+    /// Flash the picture white whenever a click reaches projectM.
+    pub calibrating: bool,
+    /// Show black instead of the picture. Recordings are unaffected.
+    pub blackout: bool,
 }
 
 impl ProjectMVisualizer {
-    pub fn new(projectm: Arc<ProjectM>) -> Self {
+    pub fn new(projectm: Arc<ProjectM>, bridge: AudioBridge) -> Self {
         Self {
             projectm,
+            bridge: Arc::new(Mutex::new(bridge)),
             texture: None,
             fbo: None,
             size: Vec2::ZERO,
+            calibrating: false,
+            blackout: false,
         }
     }
 
@@ -66,7 +79,16 @@ impl ProjectMVisualizer {
         }
     }
 
-    pub fn ui(&mut self, ui: &mut egui::Ui, painter: &mut Painter) {
+    /// Draws the visualizer. While a recording is running, the recorder renders
+    /// its fixed-resolution targets first and the first of them is shown instead.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        painter: &mut Painter,
+        recorder: &Arc<Mutex<Recorder>>,
+        overlay: &Arc<Mutex<Overlay>>,
+        media_time: f64,
+    ) {
         let (rect, _) = ui.allocate_exact_size(ui.available_size(), egui::Sense::drag());
         let size = rect.size();
 
@@ -77,25 +99,62 @@ impl ProjectMVisualizer {
         let fbo = self.fbo.unwrap();
         let texture = self.texture.unwrap();
         let projectm = self.projectm.clone();
+        let bridge = self.bridge.clone();
+        let recorder_clone = recorder.clone();
+        let overlay_clone = overlay.clone();
+        let preview = recorder.lock().unwrap().preview();
+        let calibrating = self.calibrating;
 
         let callback = PaintCallback {
             rect,
             callback: Arc::new(CallbackFn::new(move |_info, painter| {
                 unsafe {
                     let gl = painter.gl();
+                    let mut bridge = bridge.lock().unwrap();
+                    bridge.feed(&projectm);
+                    let mut recorder = recorder_clone.lock().unwrap();
+                    let mut overlay = overlay_clone.lock().unwrap();
+                    if recorder.render(gl, &projectm, &mut overlay, media_time) {
+                        projectm.set_window_size(size.x as usize, size.y as usize);
+                    }
+                    if preview.is_some() && recorder.is_recording() {
+                        return;
+                    }
                     // TODO: Save/restore viewport and other GL state if needed
                     gl.bind_framebuffer(FRAMEBUFFER, Some(fbo));
                     projectm.render_frame();
+                    if calibrating && bridge.flashing() {
+                        gl.clear_color(1.0, 1.0, 1.0, 1.0);
+                        gl.clear(glow::COLOR_BUFFER_BIT);
+                    }
+                    overlay.draw(gl, size.x as u32, size.y as u32, media_time);
                     gl.bind_framebuffer(FRAMEBUFFER, None);
                 }
             })),
         };
         ui.painter().add(callback);
 
-        let texture_id = painter.register_native_texture(texture);
-        ui.image(egui::ImageSource::Texture(egui::load::SizedTexture::new(
-            texture_id,
-            size,
-        )));
+        if let Some((preview_texture, width, height)) = preview {
+            // Letterbox the recording so its aspect ratio is visible on screen.
+            let scale = (size.x / width as f32).min(size.y / height as f32);
+            let preview_size = Vec2::new(width as f32, height as f32) * scale;
+            let texture_id = painter.register_native_texture(preview_texture);
+            ui.put(
+                egui::Rect::from_center_size(rect.center(), preview_size),
+                egui::Image::new(egui::load::SizedTexture::new(texture_id, preview_size))
+                    .uv(egui::Rect::from_min_max(egui::pos2(0.0, 1.0), egui::pos2(1.0, 0.0))),
+            );
+        } else {
+            // The FBO's first row is the bottom of the image, so flip it for egui.
+            let texture_id = painter.register_native_texture(texture);
+            ui.put(
+                rect,
+                egui::Image::new(egui::load::SizedTexture::new(texture_id, size))
+                    .uv(egui::Rect::from_min_max(egui::pos2(0.0, 1.0), egui::pos2(1.0, 0.0))),
+            );
+        }
+        if self.blackout {
+            ui.painter().rect_filled(rect, 0.0, egui::Color32::BLACK);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,653 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use egui_glow::glow::{self, HasContext};
+use projectm::core::ProjectM;
+use symphonia::core::errors::Error as DecodeError;
+use thiserror::Error;
+
+use crate::audio_bridge::feed_projectm;
+use crate::beat_detector::{AudioEvent, BeatDetector};
+use crate::config::RecordingConfig;
+use crate::metadata::read_metadata;
+use crate::normalization::{GainSource, NormalizationConfig};
+use crate::output_profile::OutputProfile;
+use crate::overlay::Overlay;
+use crate::playback::{decode_file, DecodedAudio};
+use crate::preset_director::CurrentPreset;
+use crate::preset_rotation::PresetRotation;
+use crate::render_target::RenderTarget;
+use crate::subtitles::{self, Cue, SubtitleConfig};
+use crate::track_analysis;
+
+/// How long a single paint callback may spend on offline frames before handing
+/// control back to the UI.
+const OFFLINE_FRAME_BUDGET: Duration = Duration::from_millis(30);
+
+/// Frames buffered between the render thread and each ffmpeg writer.
+const ENCODER_QUEUE_DEPTH: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum RecorderError {
+    #[error("no output profiles selected")]
+    NoProfiles,
+    #[error("a recording is already running")]
+    AlreadyRecording,
+    #[error("failed to start ffmpeg: {0}")]
+    Spawn(io::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderMode {
+    /// Capture while the track plays through the speakers, paced by the playback position.
+    Live,
+    /// Decode the track and render every frame as fast as the GPU allows, without playback.
+    ///
+    /// The audio, beats and preset switches follow the video's own clock, but
+    /// projectM animates presets by the wall clock and can't be told otherwise,
+    /// so their motion plays faster or slower than in a live render depending
+    /// on how quickly frames are rendered.
+    Offline,
+}
+
+pub struct RenderJob {
+    pub audio_path: PathBuf,
+    pub profiles: Vec<OutputProfile>,
+    pub mode: RenderMode,
+    pub rotation: PresetRotation,
+    /// Used to detect beats in offline renders, which get no live audio events.
+    pub beat_sensitivity: f32,
+    /// The overlay's text timeline, written out as subtitles.
+    pub cues: Vec<Cue>,
+    /// Offline renders scale projectM's input by the track's visual gain,
+    /// like playback does.
+    pub normalization: NormalizationConfig,
+}
+
+/// One ffmpeg process fed by one fixed-resolution render target.
+struct Encoder {
+    ffmpeg: PathBuf,
+    profile: OutputProfile,
+    target: Option<RenderTarget>,
+    frames_written: u64,
+    max_frames: Option<u64>,
+    sender: Option<SyncSender<Vec<u8>>>,
+    writer: Option<JoinHandle<io::Result<()>>>,
+    child: Child,
+    temp_path: PathBuf,
+    output_path: PathBuf,
+}
+
+impl Encoder {
+    fn spawn(
+        ffmpeg: &Path,
+        profile: OutputProfile,
+        audio_path: &Path,
+        temp_dir: &Path,
+        output_dir: &Path,
+    ) -> Result<Self, RecorderError> {
+        let stem = audio_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from("recording"));
+        let file_name = format!("{}_{}.{}", stem, profile.slug(), profile.container);
+        let temp_path = temp_dir.join(&file_name);
+        let output_path = output_dir.join(&file_name);
+
+        let mut child = Command::new(ffmpeg)
+            .args(profile.ffmpeg_args(audio_path, &temp_path))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(RecorderError::Spawn)?;
+
+        let stdin = child.stdin.take().unwrap();
+        let (sender, receiver) = sync_channel::<Vec<u8>>(ENCODER_QUEUE_DEPTH);
+        let writer = thread::spawn(move || Self::write_frames(stdin, receiver));
+
+        let max_frames = profile
+            .max_duration
+            .map(|seconds| (seconds * profile.fps as f64).ceil() as u64);
+
+        Ok(Self {
+            ffmpeg: ffmpeg.to_path_buf(),
+            profile,
+            target: None,
+            frames_written: 0,
+            max_frames,
+            sender: Some(sender),
+            writer: Some(writer),
+            child,
+            temp_path,
+            output_path,
+        })
+    }
+
+    fn write_frames(
+        mut stdin: ChildStdin,
+        receiver: std::sync::mpsc::Receiver<Vec<u8>>,
+    ) -> io::Result<()> {
+        for frame in receiver {
+            stdin.write_all(&frame)?;
+        }
+        stdin.flush()
+    }
+
+    fn is_done(&self) -> bool {
+        self.sender.is_none() || self.max_frames.is_some_and(|max| self.frames_written >= max)
+    }
+
+    /// Number of frames this encoder should have received by `media_time`.
+    fn frames_due(&self, media_time: f64) -> u64 {
+        let due = (media_time * self.profile.fps as f64).floor() as u64 + 1;
+        match self.max_frames {
+            Some(max) => due.min(max),
+            None => due,
+        }
+    }
+
+    fn send(&mut self, frame: Vec<u8>) {
+        if let Some(sender) = &self.sender {
+            if sender.send(frame).is_err() {
+                log::error!("ffmpeg for '{}' stopped accepting frames", self.profile.name);
+                self.sender = None;
+            } else {
+                self.frames_written += 1;
+            }
+        }
+    }
+
+    /// Closes stdin, waits for ffmpeg, adds `subtitles` as a subtitle track
+    /// if given and moves the file to the output directory.
+    fn finish(mut self, subtitles: Option<&[Cue]>) -> io::Result<PathBuf> {
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            if let Ok(Err(err)) = writer.join() {
+                log::warn!("Writing frames for '{}' failed: {}", self.profile.name, err);
+            }
+        }
+
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "ffmpeg exited with {} for '{}'",
+                status, self.profile.name
+            )));
+        }
+        if let Some(cues) = subtitles {
+            // The video is still worth keeping without them.
+            if let Err(err) = self.embed_subtitles(cues) {
+                log::warn!("Could not add subtitles to '{}': {}", self.profile.name, err);
+            }
+        }
+
+        if fs::rename(&self.temp_path, &self.output_path).is_err() {
+            // /tmp is often a different filesystem from the videos directory.
+            fs::copy(&self.temp_path, &self.output_path)?;
+            fs::remove_file(&self.temp_path)?;
+        }
+        Ok(self.output_path)
+    }
+
+    /// Remuxes the finished video with `cues` as a subtitle track.
+    fn embed_subtitles(&self, cues: &[Cue]) -> io::Result<()> {
+        let srt = self.temp_path.with_extension("srt");
+        let muxed = self
+            .temp_path
+            .with_extension(format!("subtitled.{}", self.profile.container));
+        let args = self
+            .profile
+            .subtitle_mux_args(&self.temp_path, &srt, &muxed)
+            .ok_or_else(|| {
+                io::Error::other(format!("{} files have no subtitle tracks", self.profile.container))
+            })?;
+
+        fs::write(&srt, subtitles::to_srt(cues))?;
+        let status = Command::new(&self.ffmpeg)
+            .args(args)
+            .stdout(Stdio::null())
+            .status();
+        fs::remove_file(&srt)?;
+        let status = status?;
+        if !status.success() {
+            let _ = fs::remove_file(&muxed);
+            return Err(io::Error::other(format!("ffmpeg exited with {}", status)));
+        }
+        fs::rename(&muxed, &self.temp_path)
+    }
+}
+
+struct Session {
+    mode: RenderMode,
+    encoders: Vec<Encoder>,
+    rotation: PresetRotation,
+    /// Decoded track for offline renders.
+    audio: Option<DecodedAudio>,
+    /// Offline clock, in ticks of the fastest profile's frame rate.
+    offline_tick: u64,
+    offline_tick_rate: u32,
+    /// Samples (per channel) already handed to projectM by the offline clock.
+    offline_fed: usize,
+    /// Beats of the offline audio, analyzed alongside it so renders repeat exactly.
+    offline_beats: Option<BeatDetector>,
+    /// Linear gain of the offline audio on its way to projectM and the beat detector.
+    visual_gain: f32,
+    cues: Vec<Cue>,
+    /// projectM's frame, rendered once per tick and copied into each profile's target.
+    frame: Option<RenderTarget>,
+    /// Large enough in both directions to crop any profile from.
+    frame_size: (u32, u32),
+}
+
+/// An offline job whose track is still being decoded.
+struct PendingStart {
+    job: RenderJob,
+    /// The decoded track and its visual gain.
+    decode: JoinHandle<Result<(DecodedAudio, f32), DecodeError>>,
+}
+
+pub struct Recorder {
+    ffmpeg_path: PathBuf,
+    output_dir: PathBuf,
+    temp_dir: PathBuf,
+    subtitles: SubtitleConfig,
+    session: Option<Session>,
+    pending: Option<PendingStart>,
+    pixels: Vec<u8>,
+    /// Render targets of a stopped session, deleted on the next GL callback.
+    stale_targets: Vec<RenderTarget>,
+    /// Events of the audio being played, which drive beat switching in live renders.
+    audio_events: Receiver<AudioEvent>,
+    current_preset: CurrentPreset,
+    pub status: String,
+}
+
+impl Recorder {
+    pub fn new(
+        config: &RecordingConfig,
+        audio_events: Receiver<AudioEvent>,
+        current_preset: CurrentPreset,
+    ) -> Self {
+        Self {
+            ffmpeg_path: config.ffmpeg_path.clone(),
+            output_dir: config.output_dir.clone(),
+            temp_dir: config.temp_dir.clone(),
+            subtitles: config.subtitles.clone(),
+            session: None,
+            pending: None,
+            pixels: Vec::new(),
+            stale_targets: Vec::new(),
+            audio_events,
+            current_preset,
+            status: String::new(),
+        }
+    }
+
+    pub fn apply_config(&mut self, config: &RecordingConfig) {
+        self.ffmpeg_path = config.ffmpeg_path.clone();
+        self.output_dir = config.output_dir.clone();
+        self.temp_dir = config.temp_dir.clone();
+        self.subtitles = config.subtitles.clone();
+    }
+
+    /// True from `start` on, including while an offline job's track is decoded.
+    pub fn is_recording(&self) -> bool {
+        self.session.is_some() || self.pending.is_some()
+    }
+
+    pub fn mode(&self) -> Option<RenderMode> {
+        match (&self.session, &self.pending) {
+            (Some(session), _) => Some(session.mode),
+            (None, Some(pending)) => Some(pending.job.mode),
+            (None, None) => None,
+        }
+    }
+
+    /// Starts a live recording right away. Offline renders decode their track
+    /// in the background first and start from `render` once it is ready.
+    pub fn start(&mut self, job: RenderJob) -> Result<(), RecorderError> {
+        if self.is_recording() {
+            return Err(RecorderError::AlreadyRecording);
+        }
+        if job.profiles.is_empty() {
+            return Err(RecorderError::NoProfiles);
+        }
+
+        fs::create_dir_all(&self.temp_dir)?;
+        fs::create_dir_all(&self.output_dir)?;
+
+        match job.mode {
+            RenderMode::Live => self.begin(job, None, 1.0),
+            RenderMode::Offline => {
+                let path = job.audio_path.clone();
+                let normalization = job.normalization.clone();
+                self.status = format!("Decoding {}...", path.display());
+                self.pending = Some(PendingStart {
+                    job,
+                    decode: thread::spawn(move || {
+                        let audio = decode_file(&path)?;
+                        let visual_gain = visual_gain(&normalization, &path, &audio);
+                        Ok((audio, visual_gain))
+                    }),
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Starts the offline session once its track has been decoded.
+    fn poll_decode(&mut self) {
+        if !self
+            .pending
+            .as_ref()
+            .is_some_and(|pending| pending.decode.is_finished())
+        {
+            return;
+        }
+        let PendingStart { job, decode } = self.pending.take().unwrap();
+        match decode.join().unwrap() {
+            Ok((audio, visual_gain)) => {
+                if let Err(err) = self.begin(job, Some(audio), visual_gain) {
+                    self.status = format!("Recording failed: {}", err);
+                }
+            }
+            Err(err) => {
+                log::error!("Could not decode {}: {}", job.audio_path.display(), err);
+                self.status = format!("Could not decode {}: {}", job.audio_path.display(), err);
+            }
+        }
+    }
+
+    fn begin(
+        &mut self,
+        job: RenderJob,
+        audio: Option<DecodedAudio>,
+        visual_gain: f32,
+    ) -> Result<(), RecorderError> {
+        let offline_tick_rate = job.profiles.iter().map(|p| p.fps).max().unwrap_or(60);
+        let frame_size = (
+            job.profiles.iter().map(|p| p.width).max().unwrap_or(1),
+            job.profiles.iter().map(|p| p.height).max().unwrap_or(1),
+        );
+
+        let mut encoders = Vec::new();
+        for profile in job.profiles {
+            let name = profile.name.clone();
+            match Encoder::spawn(
+                &self.ffmpeg_path,
+                profile,
+                &job.audio_path,
+                &self.temp_dir,
+                &self.output_dir,
+            ) {
+                Ok(encoder) => encoders.push(encoder),
+                Err(err) => {
+                    log::error!("Could not start encoder for '{}': {}", name, err);
+                    for encoder in encoders {
+                        let _ = encoder.finish(None);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        let offline_beats = audio
+            .as_ref()
+            .map(|audio| BeatDetector::new(audio.sample_rate, job.beat_sensitivity));
+
+        self.status = format!("Recording {} profile(s)", encoders.len());
+        self.session = Some(Session {
+            mode: job.mode,
+            encoders,
+            rotation: job.rotation,
+            audio,
+            offline_tick: 0,
+            offline_tick_rate,
+            offline_fed: 0,
+            offline_beats,
+            visual_gain,
+            cues: job.cues,
+            frame: None,
+            frame_size,
+        });
+        Ok(())
+    }
+
+    /// Ends the session and finalizes every output in the background.
+    pub fn stop(&mut self) -> Option<JoinHandle<()>> {
+        if self.pending.take().is_some() {
+            // The decode finishes in the background and is dropped.
+            self.status = String::from("Recording cancelled");
+            return None;
+        }
+        let session = self.session.take()?;
+        self.stale_targets.extend(session.frame);
+
+        let mut encoders = session.encoders;
+        for encoder in &mut encoders {
+            if let Some(target) = encoder.target.take() {
+                self.stale_targets.push(target);
+            }
+        }
+
+        let schedule = session.rotation.schedule().clone();
+        let (all_cues, subtitle_config) = (session.cues, self.subtitles.clone());
+        self.status = String::from("Finalizing recording...");
+        Some(thread::spawn(move || {
+            for encoder in encoders {
+                let name = encoder.profile.name.clone();
+                let length = encoder.frames_written as f64 / encoder.profile.fps as f64;
+                let cues = subtitles::clip(&all_cues, length);
+                let embed = (subtitle_config.embed && !cues.is_empty()).then_some(&cues[..]);
+                match encoder.finish(embed) {
+                    Ok(path) => {
+                        log::info!("Saved '{}' recording to {}", name, path.display());
+                        let sidecar = path.with_extension("presets.json");
+                        if let Err(err) = schedule.save(&sidecar) {
+                            log::error!("Failed to write {}: {}", sidecar.display(), err);
+                        }
+                        if !cues.is_empty() {
+                            if let Err(err) = subtitles::write_sidecars(&path, &cues, &subtitle_config) {
+                                log::error!("Failed to write subtitles for {}: {}", path.display(), err);
+                            }
+                        }
+                    }
+                    Err(err) => log::error!("Recording '{}' failed: {}", name, err),
+                }
+            }
+        }))
+    }
+
+    /// Texture and size of the first profile, shown on screen while recording.
+    pub fn preview(&self) -> Option<(glow::Texture, u32, u32)> {
+        let target = self.session.as_ref()?.encoders.first()?.target.as_ref()?;
+        Some((target.texture, target.width, target.height))
+    }
+
+    /// Renders every frame that has come due. Must be called on the GL thread.
+    ///
+    /// Returns true if projectM was rendered at a different size, in which case
+    /// the caller has to restore the window size before drawing to screen.
+    pub fn render(
+        &mut self,
+        gl: &glow::Context,
+        projectm: &ProjectM,
+        overlay: &mut Overlay,
+        media_time: f64,
+    ) -> bool {
+        for target in self.stale_targets.drain(..) {
+            target.destroy(gl);
+        }
+        self.poll_decode();
+
+        // Drained even when idle so the channel doesn't grow between recordings.
+        let live_beat = self
+            .audio_events
+            .try_iter()
+            .any(|event| matches!(event, AudioEvent::Beat { .. }));
+
+        let Some(session) = self.session.as_mut() else {
+            return false;
+        };
+
+        let rendered = match session.mode {
+            RenderMode::Live => {
+                Self::switch_preset(session, &self.current_preset, projectm, media_time, live_beat);
+                Self::render_due(session, &mut self.pixels, gl, projectm, overlay, media_time)
+            }
+            RenderMode::Offline => Self::render_offline(
+                session,
+                &self.current_preset,
+                &mut self.pixels,
+                gl,
+                projectm,
+                overlay,
+            ),
+        };
+
+        let finished = session.encoders.iter().all(Encoder::is_done)
+            || session.audio.as_ref().is_some_and(|audio| {
+                session.offline_fed * audio.channels >= audio.samples.len()
+            });
+        if finished {
+            self.stop();
+            self.status = String::from("Recording finished");
+        }
+
+        rendered
+    }
+
+    fn render_offline(
+        session: &mut Session,
+        current_preset: &CurrentPreset,
+        pixels: &mut Vec<u8>,
+        gl: &glow::Context,
+        projectm: &ProjectM,
+        overlay: &mut Overlay,
+    ) -> bool {
+        let Some(audio) = session.audio.take() else {
+            return false;
+        };
+
+        let started = Instant::now();
+        let mut rendered = false;
+        while started.elapsed() < OFFLINE_FRAME_BUDGET {
+            let media_time = session.offline_tick as f64 / session.offline_tick_rate as f64;
+
+            // Hand projectM exactly the audio that belongs to this frame.
+            let end = ((media_time * audio.sample_rate as f64) as usize)
+                .min(audio.samples.len() / audio.channels);
+            let mut beat = false;
+            if end > session.offline_fed {
+                let chunk: Vec<f32> = audio.samples
+                    [session.offline_fed * audio.channels..end * audio.channels]
+                    .iter()
+                    .map(|sample| sample * session.visual_gain)
+                    .collect();
+                feed_projectm(projectm, &chunk, audio.channels);
+                if let Some(detector) = &mut session.offline_beats {
+                    let chunk_time = session.offline_fed as f64 / audio.sample_rate as f64;
+                    for event in detector.process(&chunk, audio.channels, chunk_time) {
+                        if let AudioEvent::Beat { time, .. } = event {
+                            overlay.beat(time);
+                            beat = true;
+                        }
+                    }
+                }
+                session.offline_fed = end;
+            }
+
+            Self::switch_preset(session, current_preset, projectm, media_time, beat);
+            rendered |= Self::render_due(session, pixels, gl, projectm, overlay, media_time);
+            session.offline_tick += 1;
+
+            if session.offline_fed * audio.channels >= audio.samples.len()
+                || session.encoders.iter().all(Encoder::is_done)
+            {
+                break;
+            }
+        }
+
+        session.audio = Some(audio);
+        rendered
+    }
+
+    fn switch_preset(
+        session: &mut Session,
+        current_preset: &CurrentPreset,
+        projectm: &ProjectM,
+        media_time: f64,
+        beat: bool,
+    ) {
+        if let Some(preset) = session.rotation.update(media_time, beat) {
+            current_preset.load(projectm, &preset, session.rotation.smooth_transitions());
+        }
+    }
+
+    fn render_due(
+        session: &mut Session,
+        pixels: &mut Vec<u8>,
+        gl: &glow::Context,
+        projectm: &ProjectM,
+        overlay: &mut Overlay,
+        media_time: f64,
+    ) -> bool {
+        let due = |encoder: &Encoder| {
+            !encoder.is_done() && encoder.frames_written < encoder.frames_due(media_time)
+        };
+        if !session.encoders.iter().any(due) {
+            return false;
+        }
+
+        // Every rendered frame moves projectM's animation on, so it renders
+        // once per tick and each profile gets its own crop of that frame.
+        let (width, height) = session.frame_size;
+        let frame = session
+            .frame
+            .get_or_insert_with(|| RenderTarget::new(gl, width, height));
+        projectm.set_window_size(width as usize, height as usize);
+        frame.bind(gl);
+        projectm.render_frame();
+
+        for encoder in session.encoders.iter_mut().filter(|encoder| due(encoder)) {
+            let profile = &encoder.profile;
+            let target = encoder
+                .target
+                .get_or_insert_with(|| RenderTarget::new(gl, profile.width, profile.height));
+            target.copy_from(gl, frame);
+            target.bind(gl);
+            overlay.draw(gl, target.width, target.height, media_time);
+            target.read_pixels(gl, pixels);
+
+            // If the UI fell behind, repeat the frame so the video stays in sync with the audio.
+            let due = encoder.frames_due(media_time);
+            while encoder.frames_written < due && !encoder.is_done() {
+                encoder.send(pixels.clone());
+            }
+        }
+
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        }
+        true
+    }
+}
+
+/// The visual gain playback would use for `audio`, measuring its loudness only
+/// when the tags don't settle it.
+fn visual_gain(normalization: &NormalizationConfig, path: &Path, audio: &DecodedAudio) -> f32 {
+    let replay_gain = read_metadata(path).replay_gain;
+    let mut track_gain = normalization.track_gain(&replay_gain, None);
+    if track_gain.is_none() && normalization.source != GainSource::Off {
+        let analysis = track_analysis::analyze(audio);
+        track_gain = normalization.track_gain(&replay_gain, Some(&analysis));
+    }
+    normalization.gains(track_gain).visual
+}
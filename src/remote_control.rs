@@ -0,0 +1,540 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use music_visualizer::paths;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::Message;
+/// How often a WebSocket connection looks for events to push.
+const WEBSOCKET_POLL: Duration = Duration::from_millis(50);
+/// Largest HTTP request body accepted.
+const MAX_BODY: usize = 1 << 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// Listen on the Unix socket.
+    pub enabled: bool,
+    /// Socket path, or empty for `control.sock` in the runtime directory.
+    pub socket_path: PathBuf,
+    /// Also listen for HTTP and WebSocket connections on 127.0.0.1.
+    pub http: bool,
+    pub http_port: u16,
+    /// Bearer token HTTP and WebSocket clients must send, or empty for none.
+    pub token: String,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            socket_path: PathBuf::new(),
+            http: false,
+            http_port: 7878,
+            token: String::new(),
+        }
+    }
+}
+
+impl RemoteConfig {
+    pub fn socket_path(&self) -> PathBuf {
+        paths::control_socket(&self.socket_path)
+    }
+}
+
+/// Overlay element a `set_overlay_text` command writes to.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlaySlot {
+    #[default]
+    Title,
+    Artist,
+    Url,
+}
+
+/// A JSON command, tagged by its `command` field, e.g.
+/// `{"command": "load_preset", "preset": "/path/to/preset.milk"}`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum RemoteCommand {
+    /// Does nothing; the reply carries the current state.
+    Status,
+    NextPreset,
+    PreviousPreset,
+    /// Takes a preset path, or the file name of one of the scanned presets.
+    LoadPreset {
+        preset: String,
+    },
+    /// Marks the current preset as a favorite, or unmarks it with `"favorite": false`.
+    Favorite {
+        #[serde(default = "default_true")]
+        favorite: bool,
+    },
+    /// Blocks the current preset and moves on to the next.
+    Block,
+    /// A tap on the beat, from a button or a controller.
+    Tap,
+    QueueAdd {
+        files: Vec<PathBuf>,
+    },
+    /// Plays the queue entry at `index`, or resumes without one.
+    Play {
+        #[serde(default)]
+        index: Option<usize>,
+    },
+    Pause,
+    NextTrack,
+    PreviousTrack,
+    StartRecording {
+        #[serde(default)]
+        offline: bool,
+    },
+    StopRecording,
+    /// Shows `text` in an overlay element, which then displays custom text.
+    SetOverlayText {
+        text: String,
+        #[serde(default)]
+        element: OverlaySlot,
+    },
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// What clients are told after each command and whenever it changes.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct RemoteState {
+    pub preset: Option<String>,
+    pub favorite: bool,
+    pub queue: Vec<PathBuf>,
+    pub track_index: Option<usize>,
+    pub playing: bool,
+    pub recording: bool,
+    pub overlay_enabled: bool,
+    pub volume: f32,
+}
+
+/// A command waiting for the UI thread, with the way back to its client.
+pub struct RemoteRequest {
+    pub command: RemoteCommand,
+    id: Value,
+    reply: Sender<String>,
+}
+
+impl RemoteRequest {
+    pub fn reply(self, result: Result<(), String>, state: &RemoteState) {
+        let mut response = match result {
+            Ok(()) => json!({ "ok": true, "state": state }),
+            Err(err) => json!({ "ok": false, "error": err, "state": state }),
+        };
+        if !self.id.is_null() {
+            response["id"] = self.id;
+        }
+        let _ = self.reply.send(response.to_string());
+    }
+}
+
+/// Everything a connection needs to talk to the app.
+#[derive(Clone)]
+struct Hub {
+    requests: Sender<RemoteRequest>,
+    /// Event queues of the connections that want state changes pushed.
+    subscribers: Arc<Mutex<Vec<Sender<String>>>>,
+    /// `RemoteConfig::token`, checked on HTTP and WebSocket requests.
+    token: Arc<str>,
+}
+
+impl Hub {
+    /// Parses one JSON command and queues it, or answers right away if it is invalid.
+    fn submit(&self, line: &str, reply: &Sender<String>) {
+        let value: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(err) => {
+                let _ = reply.send(json!({ "ok": false, "error": err.to_string() }).to_string());
+                return;
+            }
+        };
+        let id = value.get("id").cloned().unwrap_or(Value::Null);
+        match serde_json::from_value::<RemoteCommand>(value) {
+            Ok(command) => {
+                let _ = self.requests.send(RemoteRequest {
+                    command,
+                    id,
+                    reply: reply.clone(),
+                });
+            }
+            Err(err) => {
+                let mut response = json!({ "ok": false, "error": err.to_string() });
+                if !id.is_null() {
+                    response["id"] = id;
+                }
+                let _ = reply.send(response.to_string());
+            }
+        }
+    }
+
+    fn subscribe(&self) -> (Sender<String>, Receiver<String>) {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender.clone());
+        (sender, receiver)
+    }
+}
+
+/// Accepts JSON commands from scripts and show-control tools and pushes state
+/// changes back. Commands are newline-delimited JSON on the Unix socket and
+/// on WebSocket connections; plain HTTP takes one command per
+/// `POST /command`, and `GET /state` returns the state.
+pub struct RemoteServer {
+    requests: Receiver<RemoteRequest>,
+    subscribers: Arc<Mutex<Vec<Sender<String>>>>,
+    last_state: Option<RemoteState>,
+    /// Removed again when the server goes away.
+    socket_path: Option<PathBuf>,
+}
+
+impl RemoteServer {
+    pub fn start(config: &RemoteConfig) -> Self {
+        let (sender, requests) = channel();
+        let hub = Hub {
+            requests: sender,
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            token: config.token.as_str().into(),
+        };
+        let mut socket_path = None;
+
+        #[cfg(unix)]
+        if config.enabled {
+            let path = config.socket_path();
+            match listen_unix(&path, hub.clone()) {
+                Ok(()) => {
+                    log::info!("Remote control listening on {}", path.display());
+                    socket_path = Some(path);
+                }
+                Err(err) => log::warn!("Remote control socket {}: {}", path.display(), err),
+            }
+        }
+        if config.http {
+            match TcpListener::bind(("127.0.0.1", config.http_port)) {
+                Ok(listener) => {
+                    log::info!("Remote control listening on http://127.0.0.1:{}", config.http_port);
+                    let hub = hub.clone();
+                    thread::spawn(move || {
+                        for stream in listener.incoming().flatten() {
+                            let hub = hub.clone();
+                            thread::spawn(move || serve_tcp(stream, hub));
+                        }
+                    });
+                }
+                Err(err) => log::warn!("Remote control port {}: {}", config.http_port, err),
+            }
+        }
+
+        Self {
+            requests,
+            subscribers: hub.subscribers,
+            last_state: None,
+            socket_path,
+        }
+    }
+
+    /// Commands received since the last call.
+    pub fn requests(&self) -> Vec<RemoteRequest> {
+        self.requests.try_iter().collect()
+    }
+
+    /// Pushes `state` to every subscribed client if it differs from the last one.
+    pub fn publish(&mut self, state: &RemoteState) {
+        if self.last_state.as_ref() == Some(state) {
+            return;
+        }
+        self.last_state = Some(state.clone());
+        let event = json!({ "event": "state", "state": state }).to_string();
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+impl Drop for RemoteServer {
+    fn drop(&mut self) {
+        if let Some(path) = &self.socket_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Binds the socket, replacing a stale one left by a crashed instance but
+/// not one that another running instance still answers on.
+#[cfg(unix)]
+fn listen_unix(path: &std::path::Path, hub: Hub) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(std::io::Error::new(
+                ErrorKind::AddrInUse,
+                "another instance is listening",
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    if let Some(dir) = path.parent() {
+        if dir == paths::runtime_dir() {
+            private_dir(dir)?;
+        } else {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let hub = hub.clone();
+            thread::spawn(move || {
+                let Ok(writer) = stream.try_clone() else {
+                    return;
+                };
+                serve_lines(stream, writer, hub);
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Creates `dir` for this user only, or makes sure an existing one is. The
+/// runtime directory may fall back to the shared temp directory, where anyone
+/// could have created it first.
+#[cfg(unix)]
+fn private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    match std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir) {
+        Err(err) if err.kind() != ErrorKind::AlreadyExists => return Err(err),
+        _ => {}
+    }
+    let metadata = std::fs::symlink_metadata(dir)?;
+    // SAFETY: getuid has no preconditions and can't fail.
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(std::io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{} is not a private directory of this user", dir.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Newline-delimited JSON both ways: commands in, replies and events out.
+fn serve_lines(reader: impl Read, mut writer: impl Write + Send + 'static, hub: Hub) {
+    let (outgoing, events) = hub.subscribe();
+    thread::spawn(move || {
+        for line in events {
+            if writeln!(writer, "{}", line).is_err() {
+                break;
+            }
+        }
+    });
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if !line.trim().is_empty() {
+            hub.submit(&line, &outgoing);
+        }
+    }
+}
+
+/// HTTP and WebSocket on the same port, told apart by the upgrade header.
+fn serve_tcp(stream: TcpStream, hub: Hub) {
+    let mut head = [0u8; 4096];
+    let Ok(len) = stream.peek(&mut head) else {
+        return;
+    };
+    let head = String::from_utf8_lossy(&head[..len]).to_ascii_lowercase();
+    if head.contains("upgrade: websocket") {
+        serve_websocket(stream, hub);
+    } else {
+        serve_http(stream, hub);
+    }
+}
+
+/// Whether a TCP request may talk to the app, going by its headers. Browsers
+/// can reach localhost too, so requests from web pages are refused by their
+/// `Origin` and DNS rebinding by `Host`; scripts send neither.
+fn authorize(header: impl Fn(&str) -> Option<String>, token: &str) -> Result<(), StatusCode> {
+    if header("origin").is_some() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if let Some(host) = header("host") {
+        let name = host.rsplit_once(':').map_or(host.as_str(), |(name, _)| name);
+        if !matches!(name, "127.0.0.1" | "localhost" | "[::1]") {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+    if !token.is_empty() && header("authorization") != Some(format!("Bearer {}", token)) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(())
+}
+
+fn serve_websocket(stream: TcpStream, hub: Hub) {
+    // The error type is tungstenite's.
+    #[allow(clippy::result_large_err)]
+    let check = |request: &Request, response: Response| {
+        let header = |name: &str| {
+            let value = request.headers().get(name)?;
+            value.to_str().ok().map(str::to_string)
+        };
+        authorize(header, &hub.token).map(|()| response).map_err(|status| {
+            let mut error = ErrorResponse::new(None);
+            *error.status_mut() = status;
+            error
+        })
+    };
+    let Ok(mut socket) = tungstenite::accept_hdr(stream, check) else {
+        return;
+    };
+    let _ = socket.get_ref().set_read_timeout(Some(WEBSOCKET_POLL));
+    let (outgoing, events) = hub.subscribe();
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => hub.submit(&text, &outgoing),
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => break,
+        }
+        for event in events.try_iter() {
+            if socket.send(Message::Text(event)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn serve_http(mut stream: TcpStream, hub: Hub) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut headers = Vec::new();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).unwrap_or(0) == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let header = |name: &str| {
+        let (_, value) = headers.iter().find(|(header, _)| header == name)?;
+        Some(value.clone())
+    };
+    if let Err(status) = authorize(header, &hub.token) {
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            status
+        );
+        return;
+    }
+    let content_length = header("content-length")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let body = match (method, path) {
+        ("POST", "/command") if content_length <= MAX_BODY => {
+            let mut body = vec![0; content_length];
+            if reader.read_exact(&mut body).is_err() {
+                return;
+            }
+            String::from_utf8_lossy(&body).to_string()
+        }
+        ("GET", "/state") => json!({ "command": "status" }).to_string(),
+        _ => {
+            let _ = write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            );
+            return;
+        }
+    };
+
+    let (reply, response) = channel();
+    hub.submit(&body, &reply);
+    let Ok(response) = response.recv() else {
+        return;
+    };
+    let _ = write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.len(),
+        response
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(headers: &[(&str, &str)], token: &str) -> Result<(), StatusCode> {
+        let header = |name: &str| {
+            let (_, value) = headers.iter().find(|(header, _)| *header == name)?;
+            Some(value.to_string())
+        };
+        authorize(header, token)
+    }
+
+    #[test]
+    fn refuses_web_pages() {
+        assert_eq!(check(&[("host", "127.0.0.1:7878")], ""), Ok(()));
+        assert_eq!(check(&[("host", "localhost")], ""), Ok(()));
+        assert_eq!(check(&[], ""), Ok(()));
+        assert_eq!(
+            check(&[("host", "127.0.0.1:7878"), ("origin", "https://example.com")], ""),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(check(&[("host", "example.com:7878")], ""), Err(StatusCode::FORBIDDEN));
+    }
+
+    #[test]
+    fn requires_the_token() {
+        assert_eq!(check(&[], "secret"), Err(StatusCode::UNAUTHORIZED));
+        assert_eq!(
+            check(&[("authorization", "Bearer wrong")], "secret"),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(check(&[("authorization", "Bearer secret")], "secret"), Ok(()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runtime_dir_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("aurora-remote-test-{}", std::process::id()));
+        private_dir(&dir).unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+        assert_eq!(private_dir(&dir).unwrap_err().kind(), ErrorKind::PermissionDenied);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+}
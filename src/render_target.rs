@@ -0,0 +1,116 @@
+use egui_glow::glow::{self, HasContext, FRAMEBUFFER, TEXTURE_2D};
+
+/// An offscreen framebuffer with a fixed pixel size, independent of the window.
+///
+/// The recorder renders projectM into one of these and copies it into another
+/// per output profile, so the encoded video always has the profile's exact
+/// resolution.
+pub struct RenderTarget {
+    pub texture: glow::Texture,
+    pub fbo: glow::Framebuffer,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderTarget {
+    pub fn new(gl: &glow::Context, width: u32, height: u32) -> Self {
+        unsafe {
+            let texture = gl.create_texture().unwrap();
+            gl.bind_texture(TEXTURE_2D, Some(texture));
+            gl.tex_image_2d(
+                TEXTURE_2D,
+                0,
+                glow::RGBA as i32,
+                width as i32,
+                height as i32,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                None,
+            );
+            gl.tex_parameter_i32(TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
+            gl.tex_parameter_i32(TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::LINEAR as i32);
+            gl.bind_texture(TEXTURE_2D, None);
+
+            let fbo = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(FRAMEBUFFER, Some(fbo));
+            gl.framebuffer_texture_2d(
+                FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                TEXTURE_2D,
+                Some(texture),
+                0,
+            );
+            gl.bind_framebuffer(FRAMEBUFFER, None);
+
+            Self {
+                texture,
+                fbo,
+                width,
+                height,
+            }
+        }
+    }
+
+    pub fn bind(&self, gl: &glow::Context) {
+        unsafe {
+            gl.bind_framebuffer(FRAMEBUFFER, Some(self.fbo));
+            gl.viewport(0, 0, self.width as i32, self.height as i32);
+        }
+    }
+
+    /// Fills this target with the middle of `source`, cropped to this target's
+    /// aspect ratio and scaled to its size.
+    pub fn copy_from(&self, gl: &glow::Context, source: &RenderTarget) {
+        let scale = (source.width as f32 / self.width as f32)
+            .min(source.height as f32 / self.height as f32);
+        let width = (self.width as f32 * scale).round() as i32;
+        let height = (self.height as f32 * scale).round() as i32;
+        let x = (source.width as i32 - width) / 2;
+        let y = (source.height as i32 - height) / 2;
+        unsafe {
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(source.fbo));
+            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, Some(self.fbo));
+            gl.blit_framebuffer(
+                x,
+                y,
+                x + width,
+                y + height,
+                0,
+                0,
+                self.width as i32,
+                self.height as i32,
+                glow::COLOR_BUFFER_BIT,
+                glow::LINEAR,
+            );
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, None);
+            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, None);
+        }
+    }
+
+    /// Reads the framebuffer back as tightly packed RGBA, bottom row first.
+    pub fn read_pixels(&self, gl: &glow::Context, pixels: &mut Vec<u8>) {
+        pixels.resize(self.width as usize * self.height as usize * 4, 0);
+        unsafe {
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(self.fbo));
+            gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+            gl.read_pixels(
+                0,
+                0,
+                self.width as i32,
+                self.height as i32,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(pixels),
+            );
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, None);
+        }
+    }
+
+    pub fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.fbo);
+            gl.delete_texture(self.texture);
+        }
+    }
+}
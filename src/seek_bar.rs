@@ -0,0 +1,108 @@
+use egui::{pos2, vec2, Color32, Rect, Sense, Stroke, Ui};
+
+use crate::track_analysis::TrackAnalysis;
+
+/// Beat ticks closer together than this many pixels are left out.
+const MIN_TICK_SPACING: f32 = 4.0;
+
+/// Whole-track waveform with the played part highlighted, beat-grid ticks,
+/// section lines and the playhead. Returns the time clicked or dragged to.
+pub fn seek_bar(
+    ui: &mut Ui,
+    analysis: Option<&TrackAnalysis>,
+    position: f64,
+    duration: f64,
+) -> Option<f64> {
+    let (rect, response) =
+        ui.allocate_exact_size(vec2(ui.available_width(), 56.0), Sense::click_and_drag());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    if duration <= 0.0 {
+        return None;
+    }
+
+    let x_at = |time: f64| rect.left() + rect.width() * (time / duration).clamp(0.0, 1.0) as f32;
+    let played_x = x_at(position);
+    let played_color = ui.visuals().selection.bg_fill;
+    let unplayed_color = ui.visuals().widgets.inactive.bg_fill;
+
+    match analysis.filter(|analysis| !analysis.waveform.is_empty()) {
+        Some(analysis) => {
+            let points = analysis.waveform.len();
+            let columns = rect.width().max(1.0) as usize;
+            let half_height = rect.height() * 0.45;
+            for column in 0..columns {
+                let first = column * points / columns;
+                let last = ((column + 1) * points / columns).max(first + 1).min(points);
+                let [min, max] = analysis.waveform[first..last]
+                    .iter()
+                    .fold([0.0f32, 0.0f32], |[min, max], [low, high]| {
+                        [min.min(*low), max.max(*high)]
+                    });
+                let x = rect.left() + column as f32 + 0.5;
+                let color = if x <= played_x {
+                    played_color
+                } else {
+                    unplayed_color
+                };
+                painter.line_segment(
+                    [
+                        pos2(x, rect.center().y - max.min(1.0) * half_height),
+                        pos2(x, rect.center().y - min.max(-1.0) * half_height - 1.0),
+                    ],
+                    Stroke::new(1.0, color),
+                );
+            }
+
+            let section_stroke = Stroke::new(1.0, ui.visuals().weak_text_color());
+            for section in &analysis.sections {
+                let x = x_at(*section);
+                painter.line_segment([pos2(x, rect.top()), pos2(x, rect.bottom())], section_stroke);
+            }
+
+            let tick_stroke = Stroke::new(1.0, ui.visuals().text_color());
+            let spacing = |count: usize| rect.width() / count.max(1) as f32;
+            if spacing(analysis.beats.len()) >= MIN_TICK_SPACING {
+                for beat in &analysis.beats {
+                    let x = x_at(*beat);
+                    painter.line_segment([pos2(x, rect.bottom() - 3.0), pos2(x, rect.bottom())], tick_stroke);
+                }
+            }
+            if spacing(analysis.downbeats.len()) >= MIN_TICK_SPACING {
+                for downbeat in &analysis.downbeats {
+                    let x = x_at(*downbeat);
+                    painter.line_segment([pos2(x, rect.bottom() - 7.0), pos2(x, rect.bottom())], tick_stroke);
+                }
+            }
+        }
+        // No waveform until the background analysis finishes; show plain progress.
+        None => {
+            painter.rect_filled(
+                Rect::from_min_max(rect.min, pos2(played_x, rect.bottom())),
+                2.0,
+                played_color,
+            );
+        }
+    }
+
+    painter.line_segment(
+        [pos2(played_x, rect.top()), pos2(played_x, rect.bottom())],
+        Stroke::new(2.0, Color32::WHITE),
+    );
+
+    ui.label(format!("{} / {}", format_time(position), format_time(duration)));
+
+    if response.clicked() || response.dragged() {
+        response.interact_pointer_pos().map(|pointer| {
+            ((pointer.x - rect.left()) / rect.width()).clamp(0.0, 1.0) as f64 * duration
+        })
+    } else {
+        None
+    }
+}
+
+/// `m:ss`.
+pub fn format_time(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
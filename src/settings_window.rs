@@ -0,0 +1,1609 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use ab_glyph::FontArc;
+use eframe::egui;
+use music_visualizer::paths;
+
+use crate::config::{self, Config, RecordingConfig};
+use crate::hotkeys::{HotkeyAction, HotkeyConfig};
+use crate::main_app::MusicVisualizerApp;
+use crate::midi::{self, MidiConfig, MidiTarget, VIRTUAL_PORT};
+use crate::normalization::GainSource;
+use crate::osc::{OscAction, OscConfig, OscMapping};
+use crate::output_profile::RateControl;
+use crate::overlay::{Anchor, OverlayConfig, TextElementConfig, TextSource};
+use crate::overlay_animation::{AnimationConfig, Motion};
+use crate::overlay_image::{ImageElementConfig, ImageSource};
+use crate::overlay_widgets::{CardConfig, ProgressBarConfig};
+use crate::remote_control::RemoteConfig;
+use crate::sprite_renderer::BlendMode;
+use crate::text_raster::{self, HAlign, TextStyle};
+#[cfg(feature = "transcription")]
+use crate::transcription::TranscriptionConfig;
+use crate::ui::rotation_ui;
+
+const PREVIEW_TEXT: &str = "The quick brown fox 0123";
+const PREVIEW_PX: f32 = 32.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tab {
+    Audio,
+    Presets,
+    Recording,
+    Overlay,
+    Hotkeys,
+    Midi,
+    Remote,
+}
+
+impl Tab {
+    const ALL: [Tab; 7] = [
+        Tab::Audio,
+        Tab::Presets,
+        Tab::Recording,
+        Tab::Overlay,
+        Tab::Hotkeys,
+        Tab::Midi,
+        Tab::Remote,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Tab::Audio => "Audio",
+            Tab::Presets => "Presets",
+            Tab::Recording => "Recording",
+            Tab::Overlay => "Overlay",
+            Tab::Hotkeys => "Hotkeys",
+            Tab::Midi => "MIDI",
+            Tab::Remote => "Remote",
+        }
+    }
+}
+
+/// Runs `$merge!(field)` for every part of `Config` the window edits.
+macro_rules! edited_sections {
+    ($merge:ident) => {
+        $merge!(beat_sensitivity);
+        $merge!(normalization);
+        $merge!(output);
+        $merge!(preset_path);
+        $merge!(texture_path);
+        $merge!(frame_rate);
+        $merge!(preset_duration);
+        $merge!(director);
+        $merge!(recording);
+        $merge!(overlay);
+        $merge!(hotkeys);
+        $merge!(midi);
+        $merge!(remote);
+        $merge!(osc);
+        #[cfg(feature = "transcription")]
+        $merge!(transcription);
+    };
+}
+
+/// The window's edits, kept apart from `app.config` until Save so that other
+/// saves never write them and Cancel has nothing to undo.
+struct Draft {
+    /// What the window shows and edits.
+    edited: Config,
+    /// `app.config` as the window last took it over; sections of `edited`
+    /// still equal to it haven't been touched.
+    base: Config,
+}
+
+impl Draft {
+    fn new(config: &Config) -> Self {
+        Self {
+            edited: config.clone(),
+            base: config.clone(),
+        }
+    }
+
+    /// Picks up changes made elsewhere meanwhile, such as the volume from
+    /// media keys, in every section the user hasn't touched.
+    fn rebase(&mut self, config: &Config) {
+        macro_rules! rebase {
+            ($field:ident) => {
+                if self.edited.$field == self.base.$field && self.base.$field != config.$field {
+                    self.edited.$field = Clone::clone(&config.$field);
+                    self.base.$field = Clone::clone(&config.$field);
+                }
+            };
+        }
+        edited_sections!(rebase);
+    }
+
+    /// Writes the sections the user changed into `config`.
+    fn apply_to(&self, config: &mut Config) {
+        macro_rules! apply {
+            ($field:ident) => {
+                if self.edited.$field != self.base.$field {
+                    config.$field = Clone::clone(&self.edited.$field);
+                }
+            };
+        }
+        edited_sections!(apply);
+    }
+}
+
+/// Options → Advanced Settings. Edits apply to the running app immediately,
+/// but only Save makes them part of `app.config` and the config file; Cancel
+/// drops them.
+pub struct SettingsWindow {
+    is_open: bool,
+    tab: Tab,
+    /// Set while the window is open.
+    draft: Option<Draft>,
+    /// Action waiting for a key press in the Hotkeys tab.
+    capturing: Option<HotkeyAction>,
+    /// Target waiting for a control to be touched in the MIDI tab.
+    midi_learning: Option<MidiTarget>,
+    /// Input ports found when the MIDI tab was last shown, or why there are none.
+    midi_ports: Option<Result<Vec<String>, String>>,
+    /// Name typed for a new MIDI profile.
+    new_profile: String,
+    fonts: HashMap<PathBuf, FontArc>,
+    /// Font preview per overlay element, with the settings it was drawn from.
+    previews: HashMap<&'static str, (TextElementConfig, egui::TextureHandle)>,
+}
+
+impl SettingsWindow {
+    pub fn new() -> Self {
+        Self {
+            is_open: false,
+            tab: Tab::Audio,
+            draft: None,
+            capturing: None,
+            midi_learning: None,
+            midi_ports: None,
+            new_profile: String::new(),
+            fonts: HashMap::new(),
+            previews: HashMap::new(),
+        }
+    }
+
+    pub fn open(&mut self, config: &Config) {
+        if !self.is_open {
+            self.is_open = true;
+            self.draft = Some(Draft::new(config));
+        }
+    }
+
+    /// `config` as the running app should use it, with the window's unsaved edits.
+    pub fn preview(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        if let Some(draft) = &self.draft {
+            draft.apply_to(&mut config);
+        }
+        config
+    }
+
+    /// True while the Hotkeys tab waits for a key, so that key doesn't also trigger its action.
+    pub fn is_capturing(&self) -> bool {
+        self.capturing.is_some()
+    }
+
+    pub fn midi_learning(&self) -> Option<MidiTarget> {
+        self.midi_learning
+    }
+
+    pub fn stop_midi_learning(&mut self) {
+        self.midi_learning = None;
+    }
+
+    fn close(&mut self) {
+        self.is_open = false;
+        self.draft = None;
+        self.capturing = None;
+        self.midi_learning = None;
+        self.midi_ports = None;
+    }
+
+    fn font(&mut self, path: &Path) -> FontArc {
+        self.fonts
+            .entry(path.to_path_buf())
+            .or_insert_with(|| {
+                if path.as_os_str().is_empty() {
+                    text_raster::default_font()
+                } else {
+                    text_raster::load_font(path).unwrap_or_else(text_raster::default_font)
+                }
+            })
+            .clone()
+    }
+}
+
+pub fn show(ctx: &egui::Context, app: &mut MusicVisualizerApp) {
+    if !app.settings.is_open {
+        return;
+    }
+    let Some(mut draft) = app.settings.draft.take() else {
+        return;
+    };
+    draft.rebase(&app.config);
+    let config = &mut draft.edited;
+
+    let mut changed = false;
+    if let Some(action) = app.settings.capturing {
+        let pressed = ctx.input(|input| {
+            input.events.iter().find_map(|event| match event {
+                egui::Event::Key { key, pressed: true, .. } => Some(*key),
+                _ => None,
+            })
+        });
+        if let Some(key) = pressed {
+            let binding = config.hotkeys.binding_mut(action);
+            *binding = if key == egui::Key::Escape {
+                String::new()
+            } else {
+                key.name().to_string()
+            };
+            app.settings.capturing = None;
+            changed = true;
+        }
+    }
+
+    let recording = app.recorder.lock().unwrap().is_recording();
+    let tags: Vec<String> = app.preset_tags.tags().cloned().collect();
+    let osc_status = app.osc.status.clone();
+    let midi_status = app.midi.status.clone();
+    let settings = &mut app.settings;
+
+    let mut open = true;
+    let mut save = false;
+    let mut cancel = false;
+    egui::Window::new("Advanced Settings")
+        .open(&mut open)
+        .default_width(460.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for tab in Tab::ALL {
+                    ui.selectable_value(&mut settings.tab, tab, tab.label());
+                }
+            });
+            ui.separator();
+
+            let tab = settings.tab;
+            egui::ScrollArea::vertical().max_height(480.0).show(ui, |ui| {
+                changed |= match tab {
+                    Tab::Audio => audio_tab(ui, config),
+                    Tab::Presets => presets_tab(ui, config),
+                    Tab::Recording => {
+                        ui.add_enabled_ui(!recording, |ui| {
+                            recording_tab(ui, &mut config.recording, &tags)
+                        })
+                        .inner
+                    }
+                    Tab::Overlay => overlay_tab(ui, config, settings),
+                    Tab::Hotkeys => hotkeys_tab(ui, &config.hotkeys, &mut settings.capturing),
+                    Tab::Midi => midi_tab(ui, &mut config.midi, settings, &midi_status),
+                    Tab::Remote => remote_tab(ui, config, &osc_status),
+                };
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Reset Section to Defaults").clicked() {
+                    reset_section(config, tab);
+                    changed = true;
+                }
+                if ui.button("Save").clicked() {
+                    save = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    cancel = true;
+                }
+            });
+        });
+
+    if save {
+        draft.apply_to(&mut app.config);
+        config::save_config(&app.config);
+        app.settings.close();
+    } else if cancel || !open {
+        app.settings.close();
+    } else {
+        app.settings.draft = Some(draft);
+        if !changed {
+            return;
+        }
+    }
+    app.apply_config();
+}
+
+fn reset_section(config: &mut Config, tab: Tab) {
+    let defaults = Config::default();
+    match tab {
+        Tab::Audio => {
+            config.beat_sensitivity = defaults.beat_sensitivity;
+            config.normalization = defaults.normalization;
+            config.output = defaults.output;
+        }
+        Tab::Presets => {
+            config.preset_path = defaults.preset_path;
+            config.texture_path = defaults.texture_path;
+            config.frame_rate = defaults.frame_rate;
+            config.preset_duration = defaults.preset_duration;
+            config.director = defaults.director;
+        }
+        Tab::Recording => config.recording = RecordingConfig::default(),
+        Tab::Overlay => {
+            config.overlay = OverlayConfig::default();
+            #[cfg(feature = "transcription")]
+            {
+                config.transcription = TranscriptionConfig::default();
+            }
+        }
+        Tab::Hotkeys => config.hotkeys = HotkeyConfig::default(),
+        Tab::Midi => config.midi = MidiConfig::default(),
+        Tab::Remote => {
+            config.remote = defaults.remote;
+            config.osc = defaults.osc;
+        }
+    }
+}
+
+/// A path text field with a Browse button. Returns true if the path changed.
+fn path_row(
+    ui: &mut egui::Ui,
+    label: &str,
+    path: &mut PathBuf,
+    pick: impl FnOnce() -> Option<PathBuf>,
+) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut changed = false;
+        let mut text = path.to_string_lossy().to_string();
+        if ui.text_edit_singleline(&mut text).changed() {
+            *path = PathBuf::from(text);
+            changed = true;
+        }
+        if ui.button("Browse...").clicked() {
+            if let Some(picked) = pick() {
+                *path = picked;
+                changed = true;
+            }
+        }
+        changed
+    })
+    .inner
+}
+
+fn audio_tab(ui: &mut egui::Ui, config: &mut Config) -> bool {
+    let mut changed = ui
+        .add(egui::Slider::new(&mut config.beat_sensitivity, 0.0..=5.0).text("Beat sensitivity"))
+        .changed();
+
+    ui.separator();
+    ui.strong("Loudness normalization");
+    let normalization = &mut config.normalization;
+    egui::ComboBox::from_label("Gain from")
+        .selected_text(normalization.source.label())
+        .show_ui(ui, |ui| {
+            for source in GainSource::ALL {
+                changed |= ui
+                    .selectable_value(&mut normalization.source, source, source.label())
+                    .changed();
+            }
+        });
+    ui.add_enabled_ui(normalization.source != GainSource::Off, |ui| {
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut normalization.target_lufs, -30.0..=-8.0)
+                    .text("Target loudness")
+                    .suffix(" LUFS"),
+            )
+            .changed();
+        changed |= ui
+            .checkbox(&mut normalization.normalize_output, "Normalize what you hear")
+            .changed();
+        changed |= ui
+            .checkbox(&mut normalization.prevent_clipping, "Prevent clipping")
+            .changed();
+        changed |= ui
+            .checkbox(&mut normalization.normalize_visuals, "Normalize what the visualizer hears")
+            .changed();
+    });
+    changed |= ui
+        .add(
+            egui::Slider::new(&mut normalization.output_trim_db, -24.0..=12.0)
+                .text("Output gain")
+                .suffix(" dB"),
+        )
+        .changed();
+    changed |= ui
+        .add(
+            egui::Slider::new(&mut normalization.visual_trim_db, -24.0..=24.0)
+                .text("Visualizer gain")
+                .suffix(" dB"),
+        )
+        .changed();
+
+    ui.separator();
+    ui.strong("Output");
+    let output = &mut config.output;
+    changed |= ui
+        .add(
+            egui::Slider::new(&mut output.volume, 0.0..=1.0)
+                .custom_formatter(|value, _| format!("{:.0}%", value * 100.0))
+                .text("Volume"),
+        )
+        .changed();
+    changed |= ui.checkbox(&mut output.limiter, "Limiter").changed();
+    ui.add_enabled_ui(output.limiter, |ui| {
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut output.limiter_ceiling_db, -12.0..=0.0)
+                    .text("Ceiling")
+                    .suffix(" dB"),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut output.limiter_lookahead_ms, 0.0..=20.0)
+                    .text("Look-ahead")
+                    .suffix(" ms"),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut output.limiter_release_ms, 10.0..=1000.0)
+                    .logarithmic(true)
+                    .text("Release")
+                    .suffix(" ms"),
+            )
+            .changed();
+    });
+    changed
+}
+
+fn presets_tab(ui: &mut egui::Ui, config: &mut Config) -> bool {
+    let mut changed = path_row(ui, "Preset folder", &mut config.preset_path, || {
+        rfd::FileDialog::new().pick_folder()
+    });
+    ui.weak("A new preset folder is loaded the next time the app starts.");
+    changed |= path_row(ui, "Texture folder", &mut config.texture_path, || {
+        rfd::FileDialog::new().pick_folder()
+    });
+    changed |= ui
+        .add(egui::Slider::new(&mut config.frame_rate, 15..=144).text("projectM frame rate"))
+        .changed();
+    changed |= ui
+        .add(
+            egui::Slider::new(&mut config.preset_duration, 5.0..=300.0)
+                .text("Preset duration")
+                .suffix(" s"),
+        )
+        .changed();
+
+    ui.separator();
+    ui.strong("Automatic switching");
+    let director = &mut config.director;
+    changed |= ui
+        .checkbox(&mut director.enabled, "Switch with the music")
+        .on_hover_text("Off leaves switching to projectM's timer")
+        .changed();
+    ui.add_enabled_ui(director.enabled, |ui| {
+        changed |= ui
+            .checkbox(&mut director.downbeat_sync, "Wait for a downbeat")
+            .changed();
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut director.min_hold, 0.0..=60.0)
+                    .text("Minimum hold")
+                    .suffix(" s"),
+            )
+            .changed();
+        changed |= ui
+            .checkbox(&mut director.energy_switching, "Switch early on drops and breakdowns")
+            .changed();
+        ui.add_enabled_ui(director.energy_switching, |ui| {
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut director.energy_jump_db, 1.0..=20.0)
+                        .text("Loudness jump")
+                        .suffix(" dB"),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut director.energy_cooldown, 0.0..=120.0)
+                        .text("Cooldown")
+                        .suffix(" s"),
+                )
+                .changed();
+        });
+        changed |= ui
+            .checkbox(&mut director.match_intensity, "Match preset intensity tags")
+            .changed();
+        changed |= ui
+            .checkbox(&mut director.smooth_transitions, "Smooth transitions")
+            .changed();
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut director.transition_duration, 0.0..=10.0)
+                    .text("Transition duration")
+                    .suffix(" s"),
+            )
+            .changed();
+    });
+    changed
+}
+
+fn recording_tab(ui: &mut egui::Ui, recording: &mut RecordingConfig, tags: &[String]) -> bool {
+    let mut changed = path_row(ui, "Output folder", &mut recording.output_dir, || {
+        rfd::FileDialog::new().pick_folder()
+    });
+    changed |= path_row(ui, "Temp folder", &mut recording.temp_dir, || {
+        rfd::FileDialog::new().pick_folder()
+    });
+    changed |= path_row(ui, "ffmpeg", &mut recording.ffmpeg_path, || {
+        rfd::FileDialog::new().pick_file()
+    });
+
+    ui.separator();
+    ui.strong("Subtitles");
+    ui.label("The overlay's title, artist, URL and lyric lines, timed as they are drawn.");
+    let subtitles = &mut recording.subtitles;
+    ui.horizontal(|ui| {
+        changed |= ui.checkbox(&mut subtitles.srt, "SRT file").changed();
+        changed |= ui.checkbox(&mut subtitles.vtt, "WebVTT file").changed();
+    });
+    changed |= ui
+        .checkbox(&mut subtitles.embed, "Subtitle track in the video")
+        .on_hover_text("MP4, MKV and WebM; players can turn it on and off")
+        .changed();
+
+    ui.separator();
+    ui.strong("Output profiles");
+    for profile in &mut recording.profiles {
+        let mut active = recording.active_profiles.contains(&profile.name);
+        egui::CollapsingHeader::new(&profile.name).show(ui, |ui| {
+            if ui.checkbox(&mut active, "Render this profile").changed() {
+                if active {
+                    recording.active_profiles.push(profile.name.clone());
+                } else {
+                    recording.active_profiles.retain(|name| name != &profile.name);
+                }
+                changed = true;
+            }
+
+            egui::Grid::new(("profile", &profile.name))
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Size");
+                    ui.horizontal(|ui| {
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut profile.width).clamp_range(16..=7680))
+                            .changed();
+                        ui.label("x");
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut profile.height).clamp_range(16..=7680))
+                            .changed();
+                    });
+                    ui.end_row();
+
+                    ui.label("Frame rate");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut profile.fps)
+                                .clamp_range(1..=240)
+                                .suffix(" fps"),
+                        )
+                        .changed();
+                    ui.end_row();
+
+                    ui.label("Video codec");
+                    changed |= ui.text_edit_singleline(&mut profile.codec).changed();
+                    ui.end_row();
+
+                    ui.label("Encoder preset");
+                    changed |= ui.text_edit_singleline(&mut profile.codec_preset).changed();
+                    ui.end_row();
+
+                    ui.label("Quality");
+                    ui.horizontal(|ui| {
+                        let mut use_crf = matches!(profile.rate_control, RateControl::Crf(_));
+                        if ui.radio_value(&mut use_crf, true, "CRF").changed()
+                            | ui.radio_value(&mut use_crf, false, "Bitrate").changed()
+                        {
+                            profile.rate_control = if use_crf {
+                                RateControl::Crf(18)
+                            } else {
+                                RateControl::Bitrate(8_000)
+                            };
+                            changed = true;
+                        }
+                        changed |= match &mut profile.rate_control {
+                            RateControl::Crf(crf) => ui
+                                .add(egui::DragValue::new(crf).clamp_range(0..=51))
+                                .changed(),
+                            RateControl::Bitrate(kbps) => ui
+                                .add(
+                                    egui::DragValue::new(kbps)
+                                        .clamp_range(100..=200_000)
+                                        .suffix(" kbit/s"),
+                                )
+                                .changed(),
+                        };
+                    });
+                    ui.end_row();
+
+                    ui.label("Pixel format");
+                    changed |= ui.text_edit_singleline(&mut profile.pixel_format).changed();
+                    ui.end_row();
+
+                    ui.label("Length limit");
+                    ui.horizontal(|ui| {
+                        let mut limited = profile.max_duration.is_some();
+                        if ui.checkbox(&mut limited, "").changed() {
+                            profile.max_duration = limited.then_some(60.0);
+                            changed = true;
+                        }
+                        if let Some(max_duration) = &mut profile.max_duration {
+                            changed |= ui
+                                .add(
+                                    egui::DragValue::new(max_duration)
+                                        .clamp_range(1.0..=36_000.0)
+                                        .suffix(" s"),
+                                )
+                                .changed();
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Audio");
+                    ui.horizontal(|ui| {
+                        changed |= ui
+                            .add(egui::TextEdit::singleline(&mut profile.audio_codec).desired_width(80.0))
+                            .changed();
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(&mut profile.audio_bitrate)
+                                    .clamp_range(32..=1_024)
+                                    .suffix(" kbit/s"),
+                            )
+                            .changed();
+                    });
+                    ui.end_row();
+
+                    ui.label("Container");
+                    changed |= ui.text_edit_singleline(&mut profile.container).changed();
+                    ui.end_row();
+                });
+        });
+    }
+
+    ui.separator();
+    ui.strong("Preset rotation");
+    changed |= rotation_ui(ui, &mut recording.rotation, tags);
+    changed
+}
+
+fn overlay_tab(ui: &mut egui::Ui, config: &mut Config, settings: &mut SettingsWindow) -> bool {
+    let overlay = &mut config.overlay;
+    let mut changed = ui.checkbox(&mut overlay.enabled, "Show overlay").changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut overlay.opacity, 0.0..=1.0).text("Opacity"))
+        .changed();
+    changed |= ui
+        .add(
+            egui::Slider::new(&mut overlay.max_line_width, 0.1..=1.0)
+                .text("Max line width (fraction of frame)"),
+        )
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut overlay.max_lines, 1..=6).text("Max lines"))
+        .changed();
+
+    egui::CollapsingHeader::new("File name cleanup").show(ui, |ui| {
+        let cleanup = &mut overlay.title_cleanup;
+        changed |= ui.checkbox(&mut cleanup.enabled, "Clean up file names").changed();
+        ui.add_enabled_ui(cleanup.enabled, |ui| {
+            changed |= ui
+                .checkbox(&mut cleanup.replace_separators, "Underscores and asterisks to spaces")
+                .changed();
+            changed |= ui
+                .checkbox(&mut cleanup.strip_brackets, "Remove [square] and {curly} brackets")
+                .changed();
+            changed |= ui
+                .checkbox(&mut cleanup.strip_parentheses, "Remove (parentheses)")
+                .changed();
+            changed |= ui
+                .checkbox(&mut cleanup.strip_version_suffix, "Remove v2, final, wip... at the end")
+                .changed();
+            changed |= ui
+                .checkbox(&mut cleanup.strip_track_number, "Remove leading track numbers")
+                .changed();
+            changed |= ui
+                .checkbox(&mut cleanup.capitalize_words, "Capitalize words")
+                .changed();
+            ui.weak(format!(
+                "{} custom regex rules (edit them in config.toml)",
+                cleanup.regex_rules.len()
+            ));
+        });
+    });
+
+    let elements = [
+        ("Title", &mut overlay.title),
+        ("Artist", &mut overlay.artist),
+        ("URL", &mut overlay.url),
+        ("Timer", &mut overlay.timer),
+    ];
+    for (name, element) in elements {
+        egui::CollapsingHeader::new(name).show(ui, |ui| {
+            changed |= element_ui(ui, name, element, true, true, settings);
+        });
+    }
+
+    egui::CollapsingHeader::new("Progress bar").show(ui, |ui| {
+        changed |= progress_ui(ui, &mut overlay.progress);
+    });
+
+    let cards = [
+        ("Now playing card", &mut overlay.now_playing, "after the track starts"),
+        ("Up next card", &mut overlay.up_next, "before the track ends"),
+    ];
+    for (name, card, at) in cards {
+        egui::CollapsingHeader::new(name).show(ui, |ui| {
+            changed |= card_ui(ui, name, card, at, settings);
+        });
+    }
+
+    egui::CollapsingHeader::new("Lyrics").show(ui, |ui| {
+        let lyrics = &mut overlay.lyrics;
+        ui.label("Read from an .lrc file next to the track, or from SYLT/USLT tags.");
+        changed |= ui.checkbox(&mut lyrics.enabled, "Show synced lyrics").changed();
+        ui.horizontal(|ui| {
+            ui.label("Sung words");
+            changed |= ui
+                .color_edit_button_srgba_unmultiplied(&mut lyrics.highlight_color)
+                .changed();
+        });
+        egui::CollapsingHeader::new("Current line").show(ui, |ui| {
+            changed |= element_ui(ui, "Current line", &mut lyrics.current, false, true, settings);
+        });
+        changed |= ui
+            .checkbox(&mut lyrics.show_next_line, "Preview the next line")
+            .changed();
+        egui::CollapsingHeader::new("Next line").show(ui, |ui| {
+            changed |= element_ui(ui, "Next line", &mut lyrics.next, false, true, settings);
+        });
+    });
+
+    egui::CollapsingHeader::new("Images").show(ui, |ui| {
+        ui.label("Logos and cover art, drawn under the text.");
+        let mut remove = None;
+        for (index, image) in overlay.images.iter_mut().enumerate() {
+            let name = format!("Image {}", index + 1);
+            egui::CollapsingHeader::new(format!("{} ({})", name, image.source.label()))
+                .id_source(&name)
+                .show(ui, |ui| {
+                    changed |= image_ui(ui, &name, image);
+                    if ui.button("Remove").clicked() {
+                        remove = Some(index);
+                    }
+                });
+        }
+        if let Some(index) = remove {
+            overlay.images.remove(index);
+            changed = true;
+        }
+        if ui.button("Add Image").clicked() {
+            overlay.images.push(ImageElementConfig::default());
+            changed = true;
+        }
+    });
+    #[cfg(feature = "transcription")]
+    {
+        changed |= transcription_section(ui, &mut config.transcription);
+    }
+    changed
+}
+
+/// Model and options for writing lyrics with whisper.
+#[cfg(feature = "transcription")]
+fn transcription_section(ui: &mut egui::Ui, transcription: &mut TranscriptionConfig) -> bool {
+    let mut changed = false;
+    egui::CollapsingHeader::new("Lyrics transcription").show(ui, |ui| {
+        ui.label("Writes an .lrc file next to a track from its vocals, on the CPU.");
+        changed |= path_row(ui, "Model", &mut transcription.model_path, || {
+            rfd::FileDialog::new()
+                .add_filter("whisper.cpp model", &["bin"])
+                .pick_file()
+        });
+        ui.horizontal(|ui| {
+            ui.label("Language");
+            changed |= ui
+                .add(egui::TextEdit::singleline(&mut transcription.language).desired_width(60.0))
+                .on_hover_text("Two-letter code such as en, or auto to detect it")
+                .changed();
+        });
+        changed |= ui
+            .checkbox(&mut transcription.isolate_vocals, "Isolate vocals first")
+            .on_hover_text("Keeps what is panned to the center and in the vocal range")
+            .changed();
+        ui.horizontal(|ui| {
+            ui.label("Threads");
+            changed |= ui
+                .add(egui::DragValue::new(&mut transcription.threads).clamp_range(0..=64))
+                .on_hover_text("0 uses one per core")
+                .changed();
+        });
+    });
+    changed
+}
+
+/// Style of one overlay element. `text_source` shows where its text comes
+/// from; lyric lines and cards have their own. `timing` is passed on to
+/// `animation_ui`.
+fn element_ui(
+    ui: &mut egui::Ui,
+    name: &'static str,
+    element: &mut TextElementConfig,
+    text_source: bool,
+    timing: bool,
+    settings: &mut SettingsWindow,
+) -> bool {
+    let mut changed = ui.checkbox(&mut element.enabled, "Enabled").changed();
+
+    egui::Grid::new(("element", name)).num_columns(2).show(ui, |ui| {
+        if text_source {
+            ui.label("Text");
+            egui::ComboBox::from_id_source(("source", name))
+                .selected_text(element.source.label())
+                .show_ui(ui, |ui| {
+                    for source in TextSource::ALL {
+                        changed |= ui
+                            .selectable_value(&mut element.source, source, source.label())
+                            .changed();
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Custom text");
+            changed |= ui.text_edit_singleline(&mut element.custom_text).changed();
+            ui.end_row();
+        }
+
+        ui.label("Font");
+        ui.horizontal(|ui| {
+            let font_name = element
+                .font_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| String::from("Built-in"));
+            ui.label(font_name);
+            if ui.button("Choose...").clicked() {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("Fonts", &["ttf", "otf"])
+                    .pick_file()
+                {
+                    element.font_path = path;
+                    changed = true;
+                }
+            }
+            if ui.button("Built-in").clicked() {
+                element.font_path = PathBuf::new();
+                changed = true;
+            }
+        });
+        ui.end_row();
+
+        ui.label("Size");
+        changed |= ui
+            .add(egui::Slider::new(&mut element.size, 8.0..=300.0).suffix(" px"))
+            .changed();
+        ui.end_row();
+
+        ui.label("Color");
+        changed |= ui.color_edit_button_srgba_unmultiplied(&mut element.color).changed();
+        ui.end_row();
+
+        ui.label("Outline");
+        ui.horizontal(|ui| {
+            changed |= ui
+                .add(egui::Slider::new(&mut element.outline_width, 0.0..=16.0).suffix(" px"))
+                .changed();
+            changed |= ui
+                .color_edit_button_srgba_unmultiplied(&mut element.outline_color)
+                .changed();
+        });
+        ui.end_row();
+
+        ui.label("Shadow");
+        ui.horizontal(|ui| {
+            for offset in &mut element.shadow_offset {
+                changed |= ui
+                    .add(egui::DragValue::new(offset).clamp_range(-64.0..=64.0))
+                    .changed();
+            }
+            changed |= ui
+                .color_edit_button_srgba_unmultiplied(&mut element.shadow_color)
+                .changed();
+        });
+        ui.end_row();
+
+        ui.label("Position");
+        egui::ComboBox::from_id_source(("anchor", name))
+            .selected_text(element.anchor.label())
+            .show_ui(ui, |ui| {
+                for anchor in Anchor::ALL {
+                    changed |= ui
+                        .selectable_value(&mut element.anchor, anchor, anchor.label())
+                        .changed();
+                }
+            });
+        ui.end_row();
+
+        ui.label("Margin");
+        ui.horizontal(|ui| {
+            for margin in &mut element.margin {
+                changed |= ui
+                    .add(egui::DragValue::new(margin).clamp_range(0.0..=1080.0))
+                    .changed();
+            }
+        });
+        ui.end_row();
+
+        ui.label("Wrap long lines");
+        changed |= ui.checkbox(&mut element.wrap, "").changed();
+        ui.end_row();
+
+        ui.label("Panel");
+        ui.horizontal(|ui| {
+            changed |= ui
+                .color_edit_button_srgba_unmultiplied(&mut element.background)
+                .on_hover_text("Transparent leaves the panel out")
+                .changed();
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut element.padding)
+                        .clamp_range(0.0..=200.0)
+                        .prefix("padding "),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut element.corner_radius)
+                        .clamp_range(0.0..=200.0)
+                        .prefix("corners "),
+                )
+                .changed();
+        });
+        ui.end_row();
+    });
+
+    font_preview(ui, name, element, settings);
+
+    egui::CollapsingHeader::new("Animation")
+        .id_source(("animation", name))
+        .show(ui, |ui| {
+            changed |= animation_ui(ui, name, &mut element.animation, timing);
+        });
+
+    changed
+}
+
+fn progress_ui(ui: &mut egui::Ui, bar: &mut ProgressBarConfig) -> bool {
+    let mut changed = ui.checkbox(&mut bar.enabled, "Enabled").changed();
+
+    egui::Grid::new("progress").num_columns(2).show(ui, |ui| {
+        ui.label("Length");
+        changed |= ui
+            .add(egui::Slider::new(&mut bar.length, 0.05..=1.0).text("of the frame width"))
+            .changed();
+        ui.end_row();
+
+        ui.label("Thickness");
+        changed |= ui
+            .add(egui::Slider::new(&mut bar.thickness, 1.0..=64.0).suffix(" px"))
+            .changed();
+        ui.end_row();
+
+        ui.label("Colors");
+        ui.horizontal(|ui| {
+            changed |= ui
+                .color_edit_button_srgba_unmultiplied(&mut bar.color)
+                .on_hover_text("Played")
+                .changed();
+            changed |= ui
+                .color_edit_button_srgba_unmultiplied(&mut bar.background)
+                .on_hover_text("Still to play")
+                .changed();
+        });
+        ui.end_row();
+
+        ui.label("Position");
+        egui::ComboBox::from_id_source("progress-anchor")
+            .selected_text(bar.anchor.label())
+            .show_ui(ui, |ui| {
+                for anchor in Anchor::ALL {
+                    changed |= ui
+                        .selectable_value(&mut bar.anchor, anchor, anchor.label())
+                        .changed();
+                }
+            });
+        ui.end_row();
+
+        ui.label("Margin");
+        ui.horizontal(|ui| {
+            for margin in &mut bar.margin {
+                changed |= ui
+                    .add(egui::DragValue::new(margin).clamp_range(0.0..=1080.0))
+                    .changed();
+            }
+        });
+        ui.end_row();
+    });
+
+    egui::CollapsingHeader::new("Animation")
+        .id_source(("animation", "progress"))
+        .show(ui, |ui| {
+            changed |= animation_ui(ui, "progress", &mut bar.animation, true);
+        });
+
+    changed
+}
+
+/// Timing and style of a card; `at` says what `CardConfig::at` counts from.
+fn card_ui(
+    ui: &mut egui::Ui,
+    name: &'static str,
+    card: &mut CardConfig,
+    at: &str,
+    settings: &mut SettingsWindow,
+) -> bool {
+    let mut changed = false;
+    egui::Grid::new(("card", name)).num_columns(2).show(ui, |ui| {
+        ui.label("Heading");
+        changed |= ui.text_edit_singleline(&mut card.heading).changed();
+        ui.end_row();
+
+        ui.label("Slide in");
+        changed |= ui
+            .add(
+                egui::DragValue::new(&mut card.at)
+                    .clamp_range(0.0..=36_000.0)
+                    .suffix(format!(" s {}", at)),
+            )
+            .changed();
+        ui.end_row();
+
+        ui.label("Show for");
+        changed |= ui
+            .add(
+                egui::DragValue::new(&mut card.show_for)
+                    .clamp_range(0.5..=600.0)
+                    .suffix(" s"),
+            )
+            .changed();
+        ui.end_row();
+
+        ui.label("Slide time");
+        changed |= ui
+            .add(egui::Slider::new(&mut card.slide_time, 0.0..=3.0).suffix(" s"))
+            .changed();
+        ui.end_row();
+
+        ui.label("Slide distance");
+        changed |= ui
+            .add(egui::Slider::new(&mut card.slide_distance, 0.0..=1920.0).suffix(" px"))
+            .changed();
+        ui.end_row();
+    });
+    changed |= element_ui(ui, name, &mut card.style, false, false, settings);
+    changed
+}
+
+/// Source, placement and look of one image element.
+fn image_ui(ui: &mut egui::Ui, name: &str, image: &mut ImageElementConfig) -> bool {
+    let mut changed = ui.checkbox(&mut image.enabled, "Enabled").changed();
+
+    egui::Grid::new(("image", name)).num_columns(2).show(ui, |ui| {
+        ui.label("Source");
+        egui::ComboBox::from_id_source(("image-source", name))
+            .selected_text(image.source.label())
+            .show_ui(ui, |ui| {
+                for source in ImageSource::ALL {
+                    changed |= ui
+                        .selectable_value(&mut image.source, source, source.label())
+                        .changed();
+                }
+            });
+        ui.end_row();
+
+        ui.label(match image.source {
+            ImageSource::File => "File",
+            ImageSource::CoverArt => "Fallback",
+        });
+        changed |= path_row(ui, "", &mut image.path, || {
+            rfd::FileDialog::new()
+                .add_filter("Images", &["png", "jpg", "jpeg", "svg", "svgz"])
+                .pick_file()
+        });
+        ui.end_row();
+
+        ui.label("Height");
+        changed |= ui
+            .add(egui::Slider::new(&mut image.size, 8.0..=1080.0).suffix(" px"))
+            .changed();
+        ui.end_row();
+
+        ui.label("Opacity");
+        changed |= ui.add(egui::Slider::new(&mut image.opacity, 0.0..=1.0)).changed();
+        ui.end_row();
+
+        ui.label("Blend");
+        egui::ComboBox::from_id_source(("image-blend", name))
+            .selected_text(image.blend.label())
+            .show_ui(ui, |ui| {
+                for blend in BlendMode::ALL {
+                    changed |= ui
+                        .selectable_value(&mut image.blend, blend, blend.label())
+                        .changed();
+                }
+            });
+        ui.end_row();
+
+        ui.label("Position");
+        egui::ComboBox::from_id_source(("image-anchor", name))
+            .selected_text(image.anchor.label())
+            .show_ui(ui, |ui| {
+                for anchor in Anchor::ALL {
+                    changed |= ui
+                        .selectable_value(&mut image.anchor, anchor, anchor.label())
+                        .changed();
+                }
+            });
+        ui.end_row();
+
+        ui.label("Margin");
+        ui.horizontal(|ui| {
+            for margin in &mut image.margin {
+                changed |= ui
+                    .add(egui::DragValue::new(margin).clamp_range(0.0..=1080.0))
+                    .changed();
+            }
+        });
+        ui.end_row();
+    });
+
+    egui::CollapsingHeader::new("Animation")
+        .id_source(("animation", name))
+        .show(ui, |ui| {
+            changed |= animation_ui(ui, name, &mut image.animation, true);
+        });
+
+    changed
+}
+
+/// Sample text in the element's font, colors and outline, at a fixed size.
+fn font_preview(
+    ui: &mut egui::Ui,
+    name: &'static str,
+    element: &TextElementConfig,
+    settings: &mut SettingsWindow,
+) {
+    let stale = settings
+        .previews
+        .get(name)
+        .is_none_or(|(drawn, _)| drawn != element);
+    if stale {
+        let font = settings.font(&element.font_path);
+        let scale = PREVIEW_PX / element.size.max(1.0);
+        let bitmap = text_raster::rasterize(
+            PREVIEW_TEXT,
+            &TextStyle {
+                font: &font,
+                px: PREVIEW_PX,
+                color: element.color,
+                outline_width: element.outline_width * scale,
+                outline_color: element.outline_color,
+                shadow_offset: [
+                    element.shadow_offset[0] * scale,
+                    element.shadow_offset[1] * scale,
+                ],
+                shadow_color: element.shadow_color,
+                align: HAlign::Left,
+                visible_chars: None,
+                highlight: None,
+            },
+        );
+        let bitmap = if element.background[3] > 0 {
+            text_raster::add_panel(
+                bitmap,
+                element.background,
+                element.padding * scale,
+                element.corner_radius * scale,
+            )
+        } else {
+            bitmap
+        };
+        let image = egui::ColorImage::from_rgba_unmultiplied(
+            [bitmap.width as usize, bitmap.height as usize],
+            &bitmap.pixels,
+        );
+        let texture = ui.ctx().load_texture(
+            format!("font-preview-{}", name),
+            image,
+            egui::TextureOptions::LINEAR,
+        );
+        settings.previews.insert(name, (element.clone(), texture));
+    }
+
+    let (_, texture) = &settings.previews[name];
+    egui::Frame::none()
+        .fill(egui::Color32::from_gray(40))
+        .inner_margin(8.0)
+        .show(ui, |ui| {
+            ui.image((texture.id(), texture.size_vec2()));
+        });
+}
+
+/// `timing` shows when the element appears and disappears, its fades and
+/// keyframes; cards time themselves.
+fn animation_ui(ui: &mut egui::Ui, name: &str, animation: &mut AnimationConfig, timing: bool) -> bool {
+    let mut changed = false;
+    egui::Grid::new(("animation-grid", name))
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Motion");
+            egui::ComboBox::from_id_source(("motion", name))
+                .selected_text(animation.motion.label())
+                .show_ui(ui, |ui| {
+                    for motion in Motion::ALL {
+                        changed |= ui
+                            .selectable_value(&mut animation.motion, motion, motion.label())
+                            .changed();
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Speed");
+            changed |= ui
+                .add(egui::Slider::new(&mut animation.speed, 0.0..=2000.0).suffix(" px/s"))
+                .changed();
+            ui.end_row();
+
+            ui.label("Bounce angle");
+            changed |= ui
+                .add(egui::Slider::new(&mut animation.bounce_angle, 0.0..=90.0).suffix("°"))
+                .changed();
+            ui.end_row();
+
+            ui.label("Move for");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut animation.motion_duration)
+                        .clamp_range(0.0..=36_000.0)
+                        .suffix(" s (0 = whole track)"),
+                )
+                .changed();
+            ui.end_row();
+
+            if timing {
+                ui.label("Show at");
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut animation.show_at)
+                            .clamp_range(0.0..=36_000.0)
+                            .suffix(" s"),
+                    )
+                    .changed();
+                ui.end_row();
+
+                ui.label("Hide at");
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut animation.hide_at)
+                            .clamp_range(0.0..=36_000.0)
+                            .suffix(" s (0 = end)"),
+                    )
+                    .changed();
+                ui.end_row();
+
+                ui.label("Fade in");
+                changed |= ui
+                    .add(egui::Slider::new(&mut animation.fade_in, 0.0..=10.0).suffix(" s"))
+                    .changed();
+                ui.end_row();
+
+                ui.label("Fade out");
+                changed |= ui
+                    .add(egui::Slider::new(&mut animation.fade_out, 0.0..=10.0).suffix(" s"))
+                    .changed();
+                ui.end_row();
+            }
+
+            ui.label("Typewriter");
+            changed |= ui
+                .add(egui::Slider::new(&mut animation.typewriter_rate, 0.0..=60.0).suffix(" chars/s"))
+                .changed();
+            ui.end_row();
+
+            ui.label("Beat pulse");
+            changed |= ui
+                .add(egui::Slider::new(&mut animation.pulse_amount, 0.0..=1.0))
+                .changed();
+            ui.end_row();
+
+            ui.label("Pulse decay");
+            changed |= ui
+                .add(egui::Slider::new(&mut animation.pulse_decay, 0.05..=2.0).suffix(" s"))
+                .changed();
+            ui.end_row();
+
+            ui.label("Zoom");
+            changed |= ui
+                .add(egui::Slider::new(&mut animation.zoom_rate, -0.5..=0.5).suffix(" per minute"))
+                .on_hover_text("Slowly grows or shrinks the element while it moves")
+                .changed();
+            ui.end_row();
+        });
+    if timing {
+        ui.weak(format!(
+            "{} keyframes (edit them in config.toml)",
+            animation.keyframes.len()
+        ));
+    }
+    changed
+}
+
+fn hotkeys_tab(
+    ui: &mut egui::Ui,
+    hotkeys: &HotkeyConfig,
+    capturing: &mut Option<HotkeyAction>,
+) -> bool {
+    ui.label("Click a binding, then press the new key. Escape clears it.");
+    egui::Grid::new("hotkeys").num_columns(2).show(ui, |ui| {
+        for action in HotkeyAction::ALL {
+            ui.label(action.label());
+            let binding = hotkeys.binding(action);
+            let text = if *capturing == Some(action) {
+                "Press a key..."
+            } else if binding.is_empty() {
+                "Unbound"
+            } else {
+                binding
+            };
+            if ui.button(text).clicked() {
+                *capturing = Some(action);
+            }
+            ui.end_row();
+        }
+    });
+    // Bindings change when the captured key arrives, in `show`.
+    false
+}
+
+fn midi_tab(
+    ui: &mut egui::Ui,
+    midi: &mut MidiConfig,
+    settings: &mut SettingsWindow,
+    status: &str,
+) -> bool {
+    let mut changed = ui
+        .checkbox(&mut midi.enabled, "Listen for MIDI controllers")
+        .changed();
+    ui.add_enabled_ui(midi.enabled, |ui| {
+        let ports = settings.midi_ports.get_or_insert_with(midi::input_ports);
+        let mut rescan = false;
+        let virtual_label = format!("Virtual port ({})", VIRTUAL_PORT);
+        ui.horizontal(|ui| {
+            let selected = if midi.port.is_empty() {
+                virtual_label.clone()
+            } else {
+                midi.port.clone()
+            };
+            egui::ComboBox::from_label("Input")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    changed |= ui
+                        .selectable_value(&mut midi.port, String::new(), &virtual_label)
+                        .changed();
+                    for port in ports.iter().flatten() {
+                        changed |= ui
+                            .selectable_value(&mut midi.port, port.clone(), port)
+                            .changed();
+                    }
+                });
+            rescan = ui.button("Rescan").clicked();
+        });
+        if let Err(err) = ports {
+            ui.colored_label(ui.visuals().warn_fg_color, format!("No MIDI ports: {}", err));
+        }
+        if rescan {
+            settings.midi_ports = None;
+        }
+        if !status.is_empty() {
+            ui.colored_label(ui.visuals().warn_fg_color, status);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Profile")
+                .selected_text(midi.profile.as_str())
+                .show_ui(ui, |ui| {
+                    for name in midi.profiles.keys() {
+                        changed |= ui
+                            .selectable_value(&mut midi.profile, name.clone(), name)
+                            .changed();
+                    }
+                });
+            if ui
+                .add_enabled(midi.profiles.len() > 1, egui::Button::new("Delete"))
+                .clicked()
+            {
+                midi.profiles.remove(&midi.profile);
+                midi.profile = midi.profiles.keys().next().cloned().unwrap();
+                changed = true;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut settings.new_profile);
+            let name = settings.new_profile.trim().to_string();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("Save As New Profile"))
+                .clicked()
+            {
+                let bindings = midi.bindings().to_vec();
+                midi.profiles.insert(name.clone(), bindings);
+                midi.profile = name;
+                settings.new_profile.clear();
+                changed = true;
+            }
+        });
+
+        ui.label("Click a binding, then press a pad or move a knob on the controller.");
+        egui::Grid::new("midi_bindings").num_columns(3).show(ui, |ui| {
+            for target in MidiTarget::all() {
+                ui.label(target.label());
+                let control = midi.control_for(target);
+                let text = if settings.midi_learning == Some(target) {
+                    String::from("Touch a control...")
+                } else {
+                    control.map_or_else(|| String::from("Unbound"), |control| control.label())
+                };
+                if ui.button(text).clicked() {
+                    settings.midi_learning = if settings.midi_learning == Some(target) {
+                        None
+                    } else {
+                        Some(target)
+                    };
+                }
+                if ui
+                    .add_enabled(control.is_some(), egui::Button::new("Clear").small())
+                    .clicked()
+                {
+                    midi.bind(target, None);
+                    changed = true;
+                }
+                ui.end_row();
+            }
+        });
+    });
+    changed
+}
+
+fn remote_tab(ui: &mut egui::Ui, config: &mut Config, osc_status: &str) -> bool {
+    let remote = &mut config.remote;
+    ui.heading("Remote control");
+    ui.label("Scripts and aurora-ctl send JSON commands here. Changes take effect after a restart.");
+    let mut changed = ui
+        .checkbox(&mut remote.enabled, "Listen on a Unix socket")
+        .changed();
+    ui.add_enabled_ui(remote.enabled, |ui| {
+        changed |= path_row(ui, "Socket", &mut remote.socket_path, || {
+            rfd::FileDialog::new().set_file_name(paths::SOCKET_NAME).save_file()
+        });
+        ui.weak(format!("Empty uses {}", RemoteConfig::default().socket_path().display()));
+    });
+    changed |= ui
+        .checkbox(&mut remote.http, "Listen for HTTP and WebSocket on localhost")
+        .changed();
+    ui.add_enabled_ui(remote.http, |ui| {
+        changed |= ui
+            .add(egui::DragValue::new(&mut remote.http_port).prefix("Port "))
+            .changed();
+        ui.horizontal(|ui| {
+            ui.label("Token");
+            changed |= ui
+                .add(egui::TextEdit::singleline(&mut remote.token).password(true))
+                .changed();
+        });
+        ui.weak("Clients send it as \"Authorization: Bearer <token>\". Requests from web pages are always refused.");
+    });
+
+    ui.separator();
+    changed |= osc_section(ui, &mut config.osc);
+    if !osc_status.is_empty() {
+        ui.colored_label(ui.visuals().warn_fg_color, osc_status);
+    }
+    changed
+}
+
+fn osc_section(ui: &mut egui::Ui, osc: &mut OscConfig) -> bool {
+    ui.heading("OSC");
+    let mut changed = ui
+        .checkbox(&mut osc.enabled, "Listen for OSC from controllers")
+        .changed();
+    ui.add_enabled_ui(osc.enabled, |ui| {
+        changed |= ui
+            .add(egui::DragValue::new(&mut osc.listen_port).prefix("UDP port "))
+            .changed();
+        ui.label("Addresses");
+        let mut remove = None;
+        egui::Grid::new("osc_mappings").num_columns(3).show(ui, |ui| {
+            for (index, mapping) in osc.mappings.iter_mut().enumerate() {
+                changed |= ui.text_edit_singleline(&mut mapping.address).changed();
+                egui::ComboBox::from_id_source(("osc_action", index))
+                    .selected_text(mapping.action.label())
+                    .show_ui(ui, |ui| {
+                        for action in OscAction::ALL {
+                            changed |= ui
+                                .selectable_value(&mut mapping.action, action, action.label())
+                                .changed();
+                        }
+                    });
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(index);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(index) = remove {
+            osc.mappings.remove(index);
+            changed = true;
+        }
+        if ui.button("Add Address").clicked() {
+            osc.mappings.push(OscMapping {
+                address: String::from("/"),
+                action: OscAction::NextPreset,
+            });
+            changed = true;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Send beats and presets to");
+            changed |= ui
+                .add(egui::TextEdit::singleline(&mut osc.send_to).hint_text("host:port"))
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Beat address");
+            changed |= ui.text_edit_singleline(&mut osc.beat_address).changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Preset address");
+            changed |= ui.text_edit_singleline(&mut osc.preset_address).changed();
+        });
+    });
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draft_keeps_edits_apart_from_changes_elsewhere() {
+        let mut config = Config::default();
+        let mut draft = Draft::new(&config);
+        draft.edited.frame_rate = 30;
+
+        // Media keys change the volume while the window is open.
+        config.output.volume = 0.5;
+        draft.rebase(&config);
+        assert_eq!(draft.edited.output.volume, 0.5);
+        assert_eq!(config.frame_rate, Config::default().frame_rate);
+
+        let mut preview = config.clone();
+        draft.apply_to(&mut preview);
+        assert_eq!(preview.frame_rate, 30);
+        assert_eq!(preview.output.volume, 0.5);
+    }
+}
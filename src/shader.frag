@@ -4,8 +4,12 @@ out vec4 FragColor;
 in vec2 TexCoord;
 
 uniform sampler2D ourTexture;
+uniform float opacity;
 
 void main()
 {
-    FragColor = texture(ourTexture, TexCoord);
+    // Premultiplied, so every blend mode can share one set of alpha factors.
+    vec4 color = texture(ourTexture, TexCoord);
+    float alpha = color.a * opacity;
+    FragColor = vec4(color.rgb * alpha, alpha);
 }
@@ -0,0 +1,208 @@
+use egui_glow::glow::{self, HasContext};
+use serde::{Deserialize, Serialize};
+
+/// How a sprite combines with what is already in the framebuffer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    /// Paints over the picture.
+    Normal,
+    /// Brightens it; black disappears.
+    Add,
+    /// Brightens it more gently than `Add`; black disappears.
+    Screen,
+    /// Darkens it; white disappears.
+    Multiply,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 4] = [
+        BlendMode::Normal,
+        BlendMode::Add,
+        BlendMode::Screen,
+        BlendMode::Multiply,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BlendMode::Normal => "Normal",
+            BlendMode::Add => "Add",
+            BlendMode::Screen => "Screen",
+            BlendMode::Multiply => "Multiply",
+        }
+    }
+
+    /// Source and destination color factors for the premultiplied colors
+    /// `shader.frag` writes.
+    fn color_factors(self) -> (u32, u32) {
+        match self {
+            BlendMode::Normal => (glow::ONE, glow::ONE_MINUS_SRC_ALPHA),
+            BlendMode::Add => (glow::ONE, glow::ONE),
+            BlendMode::Screen => (glow::ONE, glow::ONE_MINUS_SRC_COLOR),
+            BlendMode::Multiply => (glow::DST_COLOR, glow::ONE_MINUS_SRC_ALPHA),
+        }
+    }
+}
+
+/// An RGBA texture uploaded from a CPU-side bitmap.
+pub struct Sprite {
+    pub texture: glow::Texture,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Sprite {
+    /// Uploads straight-alpha RGBA pixels, top row first.
+    pub fn upload(gl: &glow::Context, width: u32, height: u32, pixels: &[u8]) -> Self {
+        unsafe {
+            let texture = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA as i32,
+                width as i32,
+                height as i32,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                Some(pixels),
+            );
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::LINEAR as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            Self {
+                texture,
+                width,
+                height,
+            }
+        }
+    }
+
+    pub fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_texture(self.texture);
+        }
+    }
+}
+
+/// Draws textured quads into whatever framebuffer is bound, using
+/// `shader.vert` / `shader.frag`.
+pub struct SpriteRenderer {
+    program: glow::Program,
+    vao: glow::VertexArray,
+    vbo: glow::Buffer,
+}
+
+impl SpriteRenderer {
+    pub fn new(gl: &glow::Context) -> Self {
+        unsafe {
+            let program = gl.create_program().unwrap();
+            let sources = [
+                (glow::VERTEX_SHADER, include_str!("shader.vert")),
+                (glow::FRAGMENT_SHADER, include_str!("shader.frag")),
+            ];
+            let mut shaders = Vec::new();
+            for (kind, source) in sources {
+                let shader = gl.create_shader(kind).unwrap();
+                gl.shader_source(shader, source);
+                gl.compile_shader(shader);
+                if !gl.get_shader_compile_status(shader) {
+                    log::error!("Overlay shader failed to compile: {}", gl.get_shader_info_log(shader));
+                }
+                gl.attach_shader(program, shader);
+                shaders.push(shader);
+            }
+            gl.link_program(program);
+            if !gl.get_program_link_status(program) {
+                log::error!("Overlay shader failed to link: {}", gl.get_program_info_log(program));
+            }
+            for shader in shaders {
+                gl.detach_shader(program, shader);
+                gl.delete_shader(shader);
+            }
+
+            let vao = gl.create_vertex_array().unwrap();
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_vertex_array(Some(vao));
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            let stride = 5 * std::mem::size_of::<f32>() as i32;
+            gl.vertex_attrib_pointer_f32(0, 3, glow::FLOAT, false, stride, 0);
+            gl.enable_vertex_attrib_array(0);
+            gl.vertex_attrib_pointer_f32(1, 2, glow::FLOAT, false, stride, 12);
+            gl.enable_vertex_attrib_array(1);
+            gl.bind_vertex_array(None);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+
+            Self { program, vao, vbo }
+        }
+    }
+
+    /// Draws `sprite` with its top-left corner at `pos` and the given `size`,
+    /// both in pixels from the top-left of a `viewport`-sized framebuffer,
+    /// multiplying its alpha by `opacity` and combining it as `blend` says.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        gl: &glow::Context,
+        sprite: &Sprite,
+        pos: [f32; 2],
+        size: [f32; 2],
+        viewport: [u32; 2],
+        opacity: f32,
+        blend: BlendMode,
+    ) {
+        let to_ndc_x = |x: f32| x / viewport[0] as f32 * 2.0 - 1.0;
+        let to_ndc_y = |y: f32| 1.0 - y / viewport[1] as f32 * 2.0;
+        let (left, right) = (to_ndc_x(pos[0]), to_ndc_x(pos[0] + size[0]));
+        let (top, bottom) = (to_ndc_y(pos[1]), to_ndc_y(pos[1] + size[1]));
+
+        #[rustfmt::skip]
+        let vertices: [f32; 20] = [
+            left,  top,    0.0, 0.0, 0.0,
+            right, top,    0.0, 1.0, 0.0,
+            left,  bottom, 0.0, 0.0, 1.0,
+            right, bottom, 0.0, 1.0, 1.0,
+        ];
+        let bytes: Vec<u8> = vertices.iter().flat_map(|v| v.to_ne_bytes()).collect();
+
+        unsafe {
+            gl.viewport(0, 0, viewport[0] as i32, viewport[1] as i32);
+            gl.disable(glow::DEPTH_TEST);
+            gl.disable(glow::SCISSOR_TEST);
+            gl.disable(glow::CULL_FACE);
+            gl.enable(glow::BLEND);
+            let (src, dst) = blend.color_factors();
+            gl.blend_func_separate(src, dst, glow::ONE, glow::ONE_MINUS_SRC_ALPHA);
+
+            gl.use_program(Some(self.program));
+            gl.uniform_1_i32(gl.get_uniform_location(self.program, "ourTexture").as_ref(), 0);
+            gl.uniform_1_f32(gl.get_uniform_location(self.program, "opacity").as_ref(), opacity);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(sprite.texture));
+
+            gl.bind_vertex_array(Some(self.vao));
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, &bytes, glow::STREAM_DRAW);
+            gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+
+            gl.bind_vertex_array(None);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.use_program(None);
+            gl.disable(glow::BLEND);
+        }
+    }
+
+    pub fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_program(self.program);
+            gl.delete_vertex_array(self.vao);
+            gl.delete_buffer(self.vbo);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::overlay::Anchor;
+
+/// Subtitles of a recording; all off until the user asks for them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SubtitleConfig {
+    /// Write an `.srt` file next to each video.
+    pub srt: bool,
+    /// Write a WebVTT `.vtt` file next to each video.
+    pub vtt: bool,
+    /// Add the cues to MP4, MKV and WebM videos as a subtitle track players can turn on.
+    pub embed: bool,
+}
+
+/// Text shown between two track times.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    /// Where the overlay draws it; WebVTT cues are placed to match.
+    pub anchor: Anchor,
+}
+
+/// `cues` as they fit into a video `length` seconds long.
+pub fn clip(cues: &[Cue], length: f64) -> Vec<Cue> {
+    cues.iter()
+        .filter(|cue| cue.start < length && cue.end > cue.start)
+        .map(|cue| Cue {
+            end: cue.end.min(length),
+            ..cue.clone()
+        })
+        .collect()
+}
+
+/// `hh:mm:ss` and milliseconds, which SRT separates with a comma and WebVTT with a period.
+fn timestamp(seconds: f64, separator: char) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+/// Cue text without the blank lines that would end the cue early.
+fn cue_text(text: &str) -> String {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn to_srt(cues: &[Cue]) -> String {
+    let mut srt = String::new();
+    for (index, cue) in cues.iter().enumerate() {
+        srt.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            index + 1,
+            timestamp(cue.start, ','),
+            timestamp(cue.end, ','),
+            cue_text(&cue.text)
+        ));
+    }
+    srt
+}
+
+pub fn to_vtt(cues: &[Cue]) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for cue in cues {
+        // Cues go at the bottom unless told otherwise.
+        let (_, y) = cue.anchor.factors();
+        let placement = match y {
+            y if y < 0.25 => " line:0 align:center",
+            y if y < 0.75 => " line:50% align:center",
+            _ => "",
+        };
+        let text = cue_text(&cue.text)
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        vtt.push_str(&format!(
+            "{} --> {}{}\n{}\n\n",
+            timestamp(cue.start, '.'),
+            timestamp(cue.end, '.'),
+            placement,
+            text
+        ));
+    }
+    vtt
+}
+
+/// Writes the sidecar files `config` asks for next to `video`, named after it.
+pub fn write_sidecars(video: &Path, cues: &[Cue], config: &SubtitleConfig) -> io::Result<()> {
+    if config.srt {
+        fs::write(video.with_extension("srt"), to_srt(cues))?;
+    }
+    if config.vtt {
+        fs::write(video.with_extension("vtt"), to_vtt(cues))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(start: f64, end: f64, text: &str, anchor: Anchor) -> Cue {
+        Cue {
+            start,
+            end,
+            text: text.to_string(),
+            anchor,
+        }
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(timestamp(61.5, ','), "00:01:01,500");
+        assert_eq!(timestamp(61.5, '.'), "00:01:01.500");
+        // 100 minutes are written as hours and minutes.
+        assert_eq!(timestamp(100.0 * 60.0 + 0.0004, '.'), "01:40:00.000");
+        assert_eq!(timestamp(-1.0, ','), "00:00:00,000");
+    }
+
+    #[test]
+    fn writes_srt() {
+        let cues = [
+            cue(1.0, 2.25, "Title\n\n  Artist  ", Anchor::Top),
+            cue(3.0, 4.0, "Next", Anchor::Bottom),
+        ];
+        assert_eq!(
+            to_srt(&cues),
+            "1\n00:00:01,000 --> 00:00:02,250\nTitle\nArtist\n\n\
+             2\n00:00:03,000 --> 00:00:04,000\nNext\n\n"
+        );
+    }
+
+    #[test]
+    fn writes_vtt() {
+        let cues = [
+            cue(0.0, 1.0, "Rock & <Roll>", Anchor::TopLeft),
+            cue(1.0, 2.0, "Middle", Anchor::Center),
+            cue(2.0, 3.0, "Bottom\n\nline", Anchor::BottomRight),
+        ];
+        assert_eq!(
+            to_vtt(&cues),
+            "WEBVTT\n\n\
+             00:00:00.000 --> 00:00:01.000 line:0 align:center\nRock &amp; &lt;Roll&gt;\n\n\
+             00:00:01.000 --> 00:00:02.000 line:50% align:center\nMiddle\n\n\
+             00:00:02.000 --> 00:00:03.000\nBottom\nline\n\n"
+        );
+    }
+
+    #[test]
+    fn clips_to_the_video() {
+        let cues = [
+            cue(1.0, 5.0, "Runs over", Anchor::Top),
+            cue(10.0, 12.0, "After the end", Anchor::Top),
+            cue(2.0, 2.0, "Empty", Anchor::Top),
+        ];
+        assert_eq!(clip(&cues, 4.0), [cue(1.0, 4.0, "Runs over", Anchor::Top)]);
+    }
+}
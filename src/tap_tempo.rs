@@ -0,0 +1,164 @@
+use std::time::Instant;
+
+/// Taps further apart than this start a new count; closer together than this are ignored.
+const MAX_TAP_INTERVAL: f64 = 2.0;
+const MIN_TAP_INTERVAL: f64 = 0.2;
+/// Most recent taps averaged into the tempo.
+const MAX_TAPS: usize = 8;
+/// How far one nudge moves the grid, in seconds.
+const NUDGE: f64 = 0.01;
+const MIN_BPM: f64 = 30.0;
+const MAX_BPM: f64 = 300.0;
+/// A tap this soon after a grid beat, as a fraction of a period, is taken
+/// to be that beat played late rather than another one.
+const LATE_TAP: f64 = 0.25;
+/// The first tap of a count is a downbeat, and every fourth beat after it.
+const BEATS_PER_BAR: i64 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TappedBeat {
+    /// `None` for a lone tap, which has no tempo of its own.
+    pub bpm: Option<f32>,
+    pub downbeat: bool,
+}
+
+/// A beat grid set by hand, for music the beat detector can't follow.
+/// Tapping sets the tempo and the phase; once there is a tempo the grid keeps
+/// going on its own and replaces the detected beats until it is cleared.
+pub struct TapTempo {
+    started: Instant,
+    /// Times of the taps in the current count, oldest first.
+    taps: Vec<f64>,
+    /// Taps in the current count, including those dropped from `taps`.
+    tap_count: i64,
+    period: Option<f64>,
+    /// A beat on the grid and its number; the others are whole periods away.
+    anchor: (f64, i64),
+    /// Number of the last beat handed out by `update`.
+    emitted: i64,
+}
+
+impl TapTempo {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            taps: Vec::new(),
+            tap_count: 0,
+            period: None,
+            anchor: (0.0, 0),
+            emitted: 0,
+        }
+    }
+
+    fn now(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    /// True once taps have set a tempo.
+    pub fn is_active(&self) -> bool {
+        self.period.is_some()
+    }
+
+    pub fn bpm(&self) -> Option<f32> {
+        self.period.map(|period| (60.0 / period) as f32)
+    }
+
+    /// Puts a beat here. Taps in a row set the tempo from their average
+    /// spacing; a lone tap only moves the phase.
+    pub fn tap(&mut self) {
+        let now = self.now();
+        let interval = self.taps.last().map(|last| now - last);
+        if interval.is_some_and(|interval| interval < MIN_TAP_INTERVAL) {
+            return;
+        }
+        if interval.is_none_or(|interval| interval > MAX_TAP_INTERVAL) {
+            self.taps.clear();
+            self.tap_count = 0;
+        }
+        self.taps.push(now);
+        if self.taps.len() > MAX_TAPS {
+            self.taps.remove(0);
+        }
+        let late = self
+            .period
+            .is_some_and(|period| now - self.beat_time(self.emitted) < period * LATE_TAP);
+        if let [first, .., last] = self.taps[..] {
+            self.period = Some((last - first) / (self.taps.len() - 1) as f64);
+        }
+
+        self.anchor = (now, self.tap_count);
+        self.emitted = if late { self.tap_count } else { self.tap_count - 1 };
+        self.tap_count += 1;
+    }
+
+    /// Moves the grid by `steps` nudges; negative steps make beats earlier.
+    pub fn nudge(&mut self, steps: i32) {
+        self.anchor.0 += steps as f64 * NUDGE;
+    }
+
+    pub fn halve(&mut self) {
+        self.scale_period(2.0);
+    }
+
+    pub fn double(&mut self) {
+        self.scale_period(0.5);
+    }
+
+    /// Changes the tempo around the last beat, so the grid carries on from it.
+    fn scale_period(&mut self, factor: f64) {
+        let Some(period) = self.period else {
+            return;
+        };
+        let scaled = period * factor;
+        if !(60.0 / MAX_BPM..=60.0 / MIN_BPM).contains(&scaled) {
+            return;
+        }
+        self.anchor = (self.beat_time(self.emitted), self.emitted);
+        self.period = Some(scaled);
+        self.emitted = self.latest_beat(scaled);
+        self.taps.clear();
+    }
+
+    /// Hands beat tracking back to the detector.
+    pub fn clear(&mut self) {
+        self.taps.clear();
+        self.tap_count = 0;
+        self.period = None;
+    }
+
+    fn beat_time(&self, beat: i64) -> f64 {
+        let (time, number) = self.anchor;
+        time + (beat - number) as f64 * self.period.unwrap_or(0.0)
+    }
+
+    /// Number of the most recent beat on the grid.
+    fn latest_beat(&self, period: f64) -> i64 {
+        let (time, number) = self.anchor;
+        number + ((self.now() - time) / period).floor() as i64
+    }
+
+    /// The beat that fell due since the last call, if any. Beats missed
+    /// while the app was stalled are skipped rather than sent in a burst.
+    pub fn update(&mut self) -> Option<TappedBeat> {
+        let Some(period) = self.period else {
+            // A single tap still counts as a beat.
+            if self.emitted < self.anchor.1 && !self.taps.is_empty() {
+                self.emitted = self.anchor.1;
+                return Some(TappedBeat {
+                    bpm: None,
+                    downbeat: true,
+                });
+            }
+            return None;
+        };
+        let latest = self.latest_beat(period);
+        if latest <= self.emitted {
+            return None;
+        }
+        self.emitted = latest;
+        Some(TappedBeat {
+            bpm: Some((60.0 / period) as f32),
+            downbeat: latest.rem_euclid(BEATS_PER_BAR) == 0,
+        })
+    }
+}
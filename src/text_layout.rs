@@ -0,0 +1,411 @@
+use std::sync::LazyLock;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// A leading track number followed by `-`, `.`, `_` or `)`, or a zero-padded
+/// one followed by a space. Plain numbers are left alone because they are
+/// often part of the title, as in "99 Luftballons".
+static TRACK_NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*(?:\d{1,3}\s*[-._)]+\s*|0\d{1,2}\s+)").unwrap());
+static BRACKETS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[[^\]]*\]|\{[^}]*\}").unwrap());
+static PARENTHESES: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\([^)]*\)").unwrap());
+static VERSION_SUFFIX: LazyLock<Regex> = LazyLock::new(|| {
+    RegexBuilder::new(
+        r"[\s\-.]*\b(?:v\d+(?:\.\d+)*|final|master(?:ed)?|wip|demo|draft|rough|mixdown|bounce)\s*$",
+    )
+    .case_insensitive(true)
+    .build()
+    .unwrap()
+});
+
+/// A user-supplied find/replace applied after the built-in rules.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RegexRule {
+    pub pattern: String,
+    pub replacement: String,
+}
+
+/// How a file name is turned into a displayable title.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SanitizeConfig {
+    pub enabled: bool,
+    /// `_`, `*`, `~`, `+` and `=` become spaces.
+    pub replace_separators: bool,
+    /// Drops `[...]` and `{...}` groups, e.g. `[final]`.
+    pub strip_brackets: bool,
+    /// Also drops `(...)` groups, which often hold wanted text like `(feat. X)`.
+    pub strip_parentheses: bool,
+    /// Drops trailing `v2`, `final`, `master`, `wip` and similar.
+    pub strip_version_suffix: bool,
+    /// Drops leading `01 - `, `3.`, `01 ` and similar, but not `99 `.
+    pub strip_track_number: bool,
+    pub capitalize_words: bool,
+    pub regex_rules: Vec<RegexRule>,
+}
+
+impl Default for SanitizeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            replace_separators: true,
+            strip_brackets: true,
+            strip_parentheses: false,
+            strip_version_suffix: true,
+            strip_track_number: true,
+            capitalize_words: true,
+            regex_rules: Vec::new(),
+        }
+    }
+}
+
+/// Turns a file stem like `01_my_song_[final]*v2` into `My Song`.
+pub fn sanitize_title(raw: &str, config: &SanitizeConfig) -> String {
+    if !config.enabled {
+        return raw.to_string();
+    }
+
+    let mut title = raw.to_string();
+
+    if config.strip_track_number {
+        // Keep titles that are nothing but a number, like "1979".
+        let stripped = TRACK_NUMBER.replace(&title, "").to_string();
+        if !stripped.trim().is_empty() {
+            title = stripped;
+        }
+    }
+    if config.strip_brackets {
+        title = BRACKETS.replace_all(&title, " ").to_string();
+    }
+    if config.strip_parentheses {
+        title = PARENTHESES.replace_all(&title, " ").to_string();
+    }
+    if config.replace_separators {
+        title = title.replace(['_', '*', '~', '+', '='], " ");
+    }
+    if config.strip_version_suffix {
+        loop {
+            let stripped = VERSION_SUFFIX.replace(&title, "").to_string();
+            if stripped == title || stripped.trim().is_empty() {
+                break;
+            }
+            title = stripped;
+        }
+    }
+
+    for rule in &config.regex_rules {
+        match Regex::new(&rule.pattern) {
+            Ok(regex) => title = regex.replace_all(&title, rule.replacement.as_str()).to_string(),
+            Err(err) => log::warn!("Skipping invalid title rule '{}': {}", rule.pattern, err),
+        }
+    }
+
+    let mut title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    title = title
+        .trim_matches(|c: char| c == '-' || c == '.' || c.is_whitespace())
+        .to_string();
+
+    if config.capitalize_words {
+        title = title
+            .split(' ')
+            .map(capitalize)
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+
+    title
+}
+
+/// Upper-cases the first letter of a word that is entirely lower case, leaving
+/// deliberate casing like "DJ" or "iPhone" alone.
+fn capitalize(word: &str) -> String {
+    if word.chars().any(|c| c.is_uppercase()) {
+        return word.to_string();
+    }
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Breaks every line of `text` that is wider than `max_width` into the fewest
+/// lines that fit (at most `max_lines`), choosing the break points so the
+/// lines come out as close to the same width as possible.
+///
+/// Lines break at spaces and between Chinese and Japanese characters, which
+/// are written without spaces. `measure` returns the rendered width of a
+/// string, so this works with real font metrics. Words wider than
+/// `max_width` on their own are never split.
+pub fn balanced_wrap(
+    text: &str,
+    max_width: f32,
+    max_lines: usize,
+    measure: impl Fn(&str) -> f32,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        lines.extend(wrap_paragraph(paragraph, max_width, max_lines, &measure));
+    }
+    lines
+}
+
+fn wrap_paragraph(
+    paragraph: &str,
+    max_width: f32,
+    max_lines: usize,
+    measure: &impl Fn(&str) -> f32,
+) -> Vec<String> {
+    let words = break_pieces(paragraph);
+    if words.is_empty() {
+        return vec![String::new()];
+    }
+    let whole = join_pieces(&words);
+    if words.len() == 1 || measure(&whole) <= max_width {
+        return vec![whole];
+    }
+
+    // width[a][b] is the width of words a..b joined into one line.
+    let n = words.len();
+    let mut width = vec![vec![0.0f32; n + 1]; n + 1];
+    for a in 0..n {
+        for b in a + 1..=n {
+            width[a][b] = measure(&join_pieces(&words[a..b]));
+        }
+    }
+
+    let most_lines = max_lines.clamp(1, n);
+    let mut best = partition(&width, n, most_lines);
+    for lines in 2..most_lines {
+        let candidate = partition(&width, n, lines);
+        if candidate.0 <= max_width {
+            best = candidate;
+            break;
+        }
+    }
+
+    let breaks = best.2;
+    let mut start = 0;
+    breaks
+        .iter()
+        .chain(std::iter::once(&n))
+        .map(|&end| {
+            let line = join_pieces(&words[start..end]);
+            start = end;
+            line
+        })
+        .collect()
+}
+
+/// Splits a paragraph into the pieces a line may break between, each marked
+/// with whether a space separates it from the piece before.
+fn break_pieces(paragraph: &str) -> Vec<(&str, bool)> {
+    let mut pieces = Vec::new();
+    for word in paragraph.split_whitespace() {
+        let mut start = 0;
+        let mut previous = None;
+        for (index, c) in word.char_indices() {
+            if let Some(previous) = previous {
+                if can_break_between(previous, c) {
+                    pieces.push((&word[start..index], start == 0));
+                    start = index;
+                }
+            }
+            previous = Some(c);
+        }
+        pieces.push((&word[start..], start == 0));
+    }
+    pieces
+}
+
+fn join_pieces(pieces: &[(&str, bool)]) -> String {
+    let mut line = String::new();
+    for (index, (piece, spaced)) in pieces.iter().enumerate() {
+        if *spaced && index > 0 {
+            line.push(' ');
+        }
+        line.push_str(piece);
+    }
+    line
+}
+
+/// Whether a line may break between two characters of the same word: next to
+/// a Chinese or Japanese character, except before closing punctuation or
+/// after opening punctuation.
+fn can_break_between(before: char, after: char) -> bool {
+    (is_ideographic(before) || is_ideographic(after))
+        && !"、。，．・：；！？）」』】〉》ー…ぁぃぅぇぉっゃゅょゎァィゥェォッャュョヮ".contains(after)
+        && !"（「『【〈《".contains(before)
+}
+
+/// Kana, CJK ideographs and full-width punctuation.
+fn is_ideographic(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3000}'..='\u{30FF}' | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{FF00}'..='\u{FFEF}'
+    )
+}
+
+/// Splits `n` words into exactly `lines` lines, minimizing the widest line and
+/// then the spread of widths. Returns (widest line, sum of squared widths, break indices).
+fn partition(width: &[Vec<f32>], n: usize, lines: usize) -> (f32, f32, Vec<usize>) {
+    // cost[j][i]: best (max, sum of squares) for the first i words on j lines.
+    let unset = (f32::INFINITY, f32::INFINITY);
+    let mut cost = vec![vec![unset; n + 1]; lines + 1];
+    let mut from = vec![vec![0usize; n + 1]; lines + 1];
+    cost[0][0] = (0.0, 0.0);
+
+    for j in 1..=lines {
+        for i in j..=n {
+            for k in j - 1..i {
+                let (max, squares) = cost[j - 1][k];
+                if max.is_infinite() {
+                    continue;
+                }
+                let w = width[k][i];
+                let candidate = (max.max(w), squares + w * w);
+                if candidate.0 < cost[j][i].0
+                    || (candidate.0 == cost[j][i].0 && candidate.1 < cost[j][i].1)
+                {
+                    cost[j][i] = candidate;
+                    from[j][i] = k;
+                }
+            }
+        }
+    }
+
+    let mut breaks = Vec::with_capacity(lines - 1);
+    let mut i = n;
+    for j in (2..=lines).rev() {
+        i = from[j][i];
+        breaks.push(i);
+    }
+    breaks.reverse();
+
+    let (max, squares) = cost[lines][n];
+    (max, squares, breaks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ten pixels per character, like a monospaced font.
+    fn measure(text: &str) -> f32 {
+        text.chars().count() as f32 * 10.0
+    }
+
+    fn wrap(text: &str, max_width: f32, max_lines: usize) -> Vec<String> {
+        balanced_wrap(text, max_width, max_lines, measure)
+    }
+
+    #[test]
+    fn short_lines_are_left_alone() {
+        assert_eq!(wrap("Short title", 200.0, 3), ["Short title"]);
+        assert_eq!(wrap("First\nSecond", 200.0, 3), ["First", "Second"]);
+    }
+
+    #[test]
+    fn splits_into_balanced_lines() {
+        // Greedy filling would give "one two three four" and "five six".
+        assert_eq!(
+            wrap("one two three four five six", 180.0, 3),
+            ["one two three", "four five six"]
+        );
+        assert_eq!(wrap("aaa bbb ccc ddd", 80.0, 3), ["aaa bbb", "ccc ddd"]);
+    }
+
+    #[test]
+    fn uses_as_few_lines_as_fit() {
+        let lines = wrap("a b c d e f g h i j k l", 100.0, 4);
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| measure(line) <= 100.0));
+    }
+
+    #[test]
+    fn overflows_when_max_lines_is_too_few() {
+        let text = "one two three four five six seven eight nine";
+        let lines = wrap(text, 100.0, 2);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().any(|line| measure(line) > 100.0));
+        assert_eq!(lines.join(" "), text);
+
+        assert_eq!(wrap(text, 100.0, 1), [text]);
+    }
+
+    #[test]
+    fn never_splits_long_words() {
+        assert_eq!(wrap("Supercalifragilistic", 50.0, 3), ["Supercalifragilistic"]);
+
+        let lines = wrap("a Supercalifragilistic b", 100.0, 3);
+        assert!(lines.iter().any(|line| line.split(' ').any(|word| word == "Supercalifragilistic")));
+        assert_eq!(lines.join(" "), "a Supercalifragilistic b");
+    }
+
+    #[test]
+    fn breaks_text_without_spaces_between_characters() {
+        let text = "日本語のテキストを折り返します";
+        let lines = wrap(text, 80.0, 3);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| measure(line) <= 80.0));
+        assert_eq!(lines.concat(), text);
+    }
+
+    #[test]
+    fn keeps_closing_punctuation_on_the_line_before() {
+        let text = "こんにちは。元気ですか。";
+        for max_width in [30.0, 50.0, 60.0, 70.0] {
+            let lines = wrap(text, max_width, 4);
+            assert_eq!(lines.concat(), text);
+            assert!(lines.iter().all(|line| !line.starts_with('。')), "{:?}", lines);
+        }
+    }
+
+    #[test]
+    fn mixed_scripts_keep_their_spaces() {
+        let lines = wrap("Live at 武道館 tonight", 120.0, 3);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("Live at "));
+        assert!(lines[1].ends_with(" tonight"));
+        assert_eq!(lines.concat().replace(' ', ""), "Liveat武道館tonight");
+    }
+
+    #[test]
+    fn partition_minimizes_the_widest_line() {
+        let words = ["aaaa", "b", "c", "dddd"];
+        let n = words.len();
+        let mut width = vec![vec![0.0f32; n + 1]; n + 1];
+        for a in 0..n {
+            for b in a + 1..=n {
+                width[a][b] = measure(&words[a..b].join(" "));
+            }
+        }
+        let (widest, _, breaks) = partition(&width, n, 2);
+        assert_eq!(widest, 60.0);
+        assert_eq!(breaks, [2]);
+        let (widest, _, breaks) = partition(&width, n, 3);
+        assert_eq!(widest, 40.0);
+        assert_eq!(breaks, [1, 3]);
+    }
+
+    fn sanitize(raw: &str) -> String {
+        sanitize_title(raw, &SanitizeConfig::default())
+    }
+
+    #[test]
+    fn strips_track_numbers_with_a_separator_or_zero_padding() {
+        assert_eq!(sanitize("01 - Intro"), "Intro");
+        assert_eq!(sanitize("3. Song"), "Song");
+        assert_eq!(sanitize("12) Song"), "Song");
+        assert_eq!(sanitize("07 Song"), "Song");
+        assert_eq!(sanitize("01_my_song_[final]*v2"), "My Song");
+    }
+
+    #[test]
+    fn keeps_numbers_that_belong_to_the_title() {
+        assert_eq!(sanitize("99 Luftballons"), "99 Luftballons");
+        assert_eq!(sanitize("3 Doors Down"), "3 Doors Down");
+        assert_eq!(sanitize("1979"), "1979");
+    }
+}
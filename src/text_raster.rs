@@ -0,0 +1,273 @@
+use std::fs;
+use std::path::Path;
+
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+
+/// Horizontal alignment of the lines inside a multi-line block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HAlign {
+    Left,
+    Center,
+    Right,
+}
+
+pub struct TextStyle<'a> {
+    pub font: &'a FontArc,
+    /// Font size in pixels.
+    pub px: f32,
+    pub color: [u8; 4],
+    /// Outline thickness in pixels; 0 disables the outline.
+    pub outline_width: f32,
+    pub outline_color: [u8; 4],
+    /// Shadow offset in pixels; a transparent `shadow_color` disables the shadow.
+    pub shadow_offset: [f32; 2],
+    pub shadow_color: [u8; 4],
+    pub align: HAlign,
+    /// Draw only this many characters (line breaks count as one) while still
+    /// laying out the whole text, so the bitmap keeps its size as text appears.
+    pub visible_chars: Option<usize>,
+    /// Draw the first this many characters, counted like `visible_chars`, in
+    /// another color; used for the sung part of a lyric line.
+    pub highlight: Option<(usize, [u8; 4])>,
+}
+
+/// Rasterized text as straight-alpha RGBA, top row first.
+pub struct TextBitmap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// The font that ships with egui, used when an element has no font file set.
+pub fn default_font() -> FontArc {
+    let definitions = egui::FontDefinitions::default();
+    let data = &definitions.font_data["Ubuntu-Light"];
+    FontArc::try_from_vec(data.font.to_vec()).unwrap()
+}
+
+pub fn load_font(path: &Path) -> Option<FontArc> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::warn!("Could not read font {}: {}", path.display(), err);
+            return None;
+        }
+    };
+    match FontArc::try_from_vec(bytes) {
+        Ok(font) => Some(font),
+        Err(err) => {
+            log::warn!("Could not parse font {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Advance width of a single line of text in pixels.
+pub fn measure_line(font: &FontArc, px: f32, text: &str) -> f32 {
+    let font = font.as_scaled(PxScale::from(px));
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+pub fn rasterize(text: &str, style: &TextStyle) -> TextBitmap {
+    let font = style.font.as_scaled(PxScale::from(style.px));
+    let lines: Vec<&str> = text.lines().collect();
+    let line_height = font.height() + font.line_gap();
+
+    let widths: Vec<f32> = lines
+        .iter()
+        .map(|line| measure_line(style.font, style.px, line))
+        .collect();
+    let text_width = widths.iter().cloned().fold(0.0, f32::max);
+    let text_height = line_height * lines.len().max(1) as f32;
+
+    let shadow_visible = style.shadow_color[3] > 0;
+    let pad = style.outline_width.ceil() as i32
+        + 1
+        + if shadow_visible {
+            style.shadow_offset[0].abs().max(style.shadow_offset[1].abs()).ceil() as i32
+        } else {
+            0
+        };
+    let width = (text_width.ceil() as i32 + pad * 2).max(1) as usize;
+    let height = (text_height.ceil() as i32 + pad * 2).max(1) as usize;
+
+    let mut fill = vec![0.0f32; width * height];
+    let mut lit = vec![0.0f32; width * height];
+    let lit_chars = style.highlight.map_or(0, |(chars, _)| chars);
+    let mut drawn = 0;
+    for (index, line) in lines.iter().enumerate() {
+        let mut x = pad as f32
+            + match style.align {
+                HAlign::Left => 0.0,
+                HAlign::Center => (text_width - widths[index]) / 2.0,
+                HAlign::Right => text_width - widths[index],
+            };
+        let baseline = pad as f32 + font.ascent() + line_height * index as f32;
+        let mut previous = None;
+        for c in line.chars() {
+            let id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                x += font.kern(previous, id);
+            }
+            let glyph = id.with_scale_and_position(style.px, point(x, baseline));
+            x += font.h_advance(id);
+            previous = Some(id);
+
+            drawn += 1;
+            if style.visible_chars.is_some_and(|visible| drawn > visible) {
+                continue;
+            }
+            let Some(outlined) = style.font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            let highlighted = drawn <= lit_chars;
+            outlined.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i32 + gx as i32;
+                let py = bounds.min.y as i32 + gy as i32;
+                if px >= 0 && py >= 0 && (px as usize) < width && (py as usize) < height {
+                    let index = py as usize * width + px as usize;
+                    fill[index] = fill[index].max(coverage);
+                    if highlighted {
+                        lit[index] = lit[index].max(coverage);
+                    }
+                }
+            });
+        }
+        drawn += 1;
+    }
+
+    let outline = if style.outline_width > 0.0 {
+        dilate(&fill, width, height, style.outline_width)
+    } else {
+        fill.clone()
+    };
+
+    let mut pixels = vec![0u8; width * height * 4];
+    if shadow_visible {
+        let dx = style.shadow_offset[0].round() as i32;
+        let dy = style.shadow_offset[1].round() as i32;
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                let (sx, sy) = (x - dx, y - dy);
+                if sx < 0 || sy < 0 || sx >= width as i32 || sy >= height as i32 {
+                    continue;
+                }
+                let coverage = outline[sy as usize * width + sx as usize];
+                blend_over(&mut pixels, y as usize * width + x as usize, style.shadow_color, coverage);
+            }
+        }
+    }
+    if style.outline_width > 0.0 {
+        for (index, coverage) in outline.iter().enumerate() {
+            blend_over(&mut pixels, index, style.outline_color, *coverage);
+        }
+    }
+    for (index, coverage) in fill.iter().enumerate() {
+        blend_over(&mut pixels, index, style.color, *coverage);
+    }
+    if let Some((_, color)) = style.highlight {
+        for (index, coverage) in lit.iter().enumerate() {
+            blend_over(&mut pixels, index, color, *coverage);
+        }
+    }
+
+    TextBitmap {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    }
+}
+
+/// Puts `bitmap` on a `color` panel with rounded corners that extends
+/// `padding` pixels past it on every side.
+pub fn add_panel(bitmap: TextBitmap, color: [u8; 4], padding: f32, corner_radius: f32) -> TextBitmap {
+    let pad = padding.max(0.0).round() as usize;
+    let width = bitmap.width as usize + pad * 2;
+    let height = bitmap.height as usize + pad * 2;
+    let radius = corner_radius.clamp(0.0, width.min(height) as f32 / 2.0);
+
+    let mut pixels = vec![0u8; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            // Distance past the rounded corner's circle, measured from pixel centers.
+            let cx = (x as f32 + 0.5).clamp(radius, width as f32 - radius);
+            let cy = (y as f32 + 0.5).clamp(radius, height as f32 - radius);
+            let distance = ((x as f32 + 0.5 - cx).powi(2) + (y as f32 + 0.5 - cy).powi(2)).sqrt();
+            let coverage = if radius > 0.0 {
+                (radius - distance + 0.5).clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+            blend_over(&mut pixels, y * width + x, color, coverage);
+        }
+    }
+    for y in 0..bitmap.height as usize {
+        for x in 0..bitmap.width as usize {
+            let from = (y * bitmap.width as usize + x) * 4;
+            let pixel: [u8; 4] = bitmap.pixels[from..from + 4].try_into().unwrap();
+            blend_over(&mut pixels, (y + pad) * width + x + pad, pixel, 1.0);
+        }
+    }
+
+    TextBitmap {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    }
+}
+
+/// Grows the coverage mask by `radius` pixels in every direction.
+fn dilate(mask: &[f32], width: usize, height: usize, radius: f32) -> Vec<f32> {
+    let r = radius.ceil() as i32;
+    let mut out = vec![0.0f32; mask.len()];
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let mut value = 0.0f32;
+            for oy in -r..=r {
+                for ox in -r..=r {
+                    let distance = ((ox * ox + oy * oy) as f32).sqrt();
+                    if distance > radius + 0.5 {
+                        continue;
+                    }
+                    let (sx, sy) = (x + ox, y + oy);
+                    if sx < 0 || sy < 0 || sx >= width as i32 || sy >= height as i32 {
+                        continue;
+                    }
+                    // Soften the last half pixel so the outline edge stays anti-aliased.
+                    let falloff = (radius + 0.5 - distance).min(1.0);
+                    value = value.max(mask[sy as usize * width + sx as usize] * falloff);
+                }
+            }
+            out[y as usize * width + x as usize] = value;
+        }
+    }
+    out
+}
+
+/// Composites `color` at `coverage` over the straight-alpha pixel at `index`.
+fn blend_over(pixels: &mut [u8], index: usize, color: [u8; 4], coverage: f32) {
+    let src_a = color[3] as f32 / 255.0 * coverage;
+    if src_a <= 0.0 {
+        return;
+    }
+    let dst = &mut pixels[index * 4..index * 4 + 4];
+    let dst_a = dst[3] as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    for channel in 0..3 {
+        let src = color[channel] as f32;
+        let existing = dst[channel] as f32;
+        dst[channel] = ((src * src_a + existing * dst_a * (1.0 - src_a)) / out_a).round() as u8;
+    }
+    dst[3] = (out_a * 255.0).round() as u8;
+}
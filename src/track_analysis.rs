@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use music_visualizer::paths;
+use serde::{Deserialize, Serialize};
+
+use crate::beat_detector::{AudioEvent, BeatDetector};
+use crate::playback::{decode_file, DecodedAudio};
+
+/// Bumped whenever the analysis changes, so stale cache entries are redone.
+const CACHE_VERSION: u32 = 1;
+/// Min/max pairs in `TrackAnalysis::waveform`.
+pub const WAVEFORM_POINTS: usize = 1024;
+/// EBU R128 gating block and hop, in tenths of a second.
+const BLOCK_STEPS: usize = 4;
+/// Seconds of loudness compared on either side of a candidate section boundary.
+const SECTION_WINDOW: f64 = 8.0;
+/// Loudness difference between both sides that makes a section boundary.
+const SECTION_JUMP_DB: f32 = 4.0;
+/// Boundaries this close to a downbeat are moved onto it.
+const SECTION_SNAP: f64 = 1.0;
+/// Phases of the 4x oversampling used for true peak; phase 0 is the sample itself.
+const OVERSAMPLING: usize = 4;
+const INTERPOLATION_TAPS: usize = 12;
+
+/// Everything known about a track without playing it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrackAnalysis {
+    version: u32,
+    pub duration: f64,
+    pub bpm: Option<f32>,
+    /// Beat times in seconds.
+    pub beats: Vec<f64>,
+    pub downbeats: Vec<f64>,
+    /// Times in seconds where the track changes loudness noticeably, e.g. a drop or a breakdown.
+    pub sections: Vec<f64>,
+    /// EBU R128 integrated loudness in LUFS; `None` for silence.
+    pub integrated_lufs: Option<f32>,
+    /// Highest 4x oversampled sample level in dBTP; `None` for silence.
+    pub true_peak_db: Option<f32>,
+    /// Min and max of the mono mix over `WAVEFORM_POINTS` equal slices of the track.
+    pub waveform: Vec<[f32; 2]>,
+}
+
+/// Analyzes queued tracks on a background thread and keeps the results.
+pub struct TrackAnalyzer {
+    requests: Sender<PathBuf>,
+    results: Arc<Mutex<HashMap<PathBuf, Arc<TrackAnalysis>>>>,
+}
+
+impl TrackAnalyzer {
+    pub fn new() -> Self {
+        let (requests, receiver) = channel::<PathBuf>();
+        let results = Arc::new(Mutex::new(HashMap::new()));
+
+        let results_clone = results.clone();
+        thread::spawn(move || {
+            for path in receiver {
+                if results_clone.lock().unwrap().contains_key(&path) {
+                    continue;
+                }
+                match load_or_analyze(&path) {
+                    Ok(analysis) => {
+                        results_clone
+                            .lock()
+                            .unwrap()
+                            .insert(path, Arc::new(analysis));
+                    }
+                    Err(err) => log::warn!("Could not analyze {}: {}", path.display(), err),
+                }
+            }
+        });
+
+        Self { requests, results }
+    }
+
+    /// Queues `path` for analysis unless it is already done.
+    pub fn request(&self, path: PathBuf) {
+        let _ = self.requests.send(path);
+    }
+
+    pub fn get(&self, path: &Path) -> Option<Arc<TrackAnalysis>> {
+        self.results.lock().unwrap().get(path).cloned()
+    }
+}
+
+/// Returns the cached analysis of the file at `path`, analyzing and caching it first if needed.
+pub fn load_or_analyze(path: &Path) -> io::Result<TrackAnalysis> {
+    let hash = file_hash(path)?;
+    let cache_path = paths::cache_dir()
+        .map(|dir| dir.join("analysis").join(format!("{:016x}.json", hash)));
+
+    if let Some(cache_path) = &cache_path {
+        if let Ok(contents) = fs::read_to_string(cache_path) {
+            match serde_json::from_str::<TrackAnalysis>(&contents) {
+                Ok(analysis) if analysis.version == CACHE_VERSION => return Ok(analysis),
+                Ok(_) => {}
+                Err(err) => log::warn!("Ignoring invalid cache {}: {}", cache_path.display(), err),
+            }
+        }
+    }
+
+    let analysis = analyze(&decode_file(path).map_err(io::Error::other)?);
+
+    if let Some(cache_path) = &cache_path {
+        let written = serde_json::to_string(&analysis)
+            .map_err(io::Error::other)
+            .and_then(|json| {
+                fs::create_dir_all(cache_path.parent().unwrap())?;
+                fs::write(cache_path, json)
+            });
+        if let Err(err) = written {
+            log::warn!("Could not cache analysis to {}: {}", cache_path.display(), err);
+        }
+    }
+    Ok(analysis)
+}
+
+/// 64-bit FNV-1a of the file contents. Unlike `DefaultHasher` it stays the
+/// same across builds, so cache entries survive updates.
+fn file_hash(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0u8; 1 << 16];
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hash);
+        }
+        for byte in &buffer[..read] {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+pub fn analyze(audio: &DecodedAudio) -> TrackAnalysis {
+    let channels = audio.channels.max(1);
+    let frames = audio.samples.len() / channels;
+    let duration = frames as f64 / audio.sample_rate as f64;
+
+    let mut detector = BeatDetector::new(audio.sample_rate, 1.0);
+    let mut beats = Vec::new();
+    let mut downbeats = Vec::new();
+    let chunk_frames = 4096;
+    for (index, chunk) in audio.samples.chunks(chunk_frames * channels).enumerate() {
+        let time = (index * chunk_frames) as f64 / audio.sample_rate as f64;
+        for event in detector.process(chunk, channels, time) {
+            if let AudioEvent::Beat { time, downbeat, .. } = event {
+                beats.push(time);
+                if downbeat {
+                    downbeats.push(time);
+                }
+            }
+        }
+    }
+
+    let blocks = loudness_blocks(audio);
+    let sections = sections(&blocks, &downbeats);
+
+    TrackAnalysis {
+        version: CACHE_VERSION,
+        duration,
+        bpm: median_bpm(&beats).or(detector.bpm()),
+        beats,
+        downbeats,
+        sections,
+        integrated_lufs: integrated_loudness(&blocks),
+        true_peak_db: true_peak(audio),
+        waveform: waveform(audio),
+    }
+}
+
+/// Tempo from the median beat interval, which ignores the odd skipped or doubled beat.
+fn median_bpm(beats: &[f64]) -> Option<f32> {
+    let mut intervals: Vec<f64> = beats.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    if intervals.is_empty() {
+        return None;
+    }
+    intervals.sort_by(f64::total_cmp);
+    Some((60.0 / intervals[intervals.len() / 2]) as f32)
+}
+
+/// Transposed direct form II biquad.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The two ITU-R BS.1770 K-weighting stages, derived for any sample rate.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+/// BS.1770 channel weights: surround channels count more and LFE not at all.
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (5, 3) | (5, 4) | (6, 4) | (6, 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Weighted mean square of the K-weighted signal over 400 ms blocks every 100 ms.
+fn loudness_blocks(audio: &DecodedAudio) -> Vec<f64> {
+    let channels = audio.channels.max(1);
+    let step = (audio.sample_rate as usize / 10).max(1);
+    let mut filters: Vec<[Biquad; 2]> =
+        (0..channels).map(|_| k_weighting(audio.sample_rate)).collect();
+
+    // Weighted sum of squares per 100 ms step.
+    let mut steps = Vec::new();
+    for chunk in audio.samples.chunks(step * channels) {
+        if chunk.len() < step * channels {
+            break;
+        }
+        let mut sum = 0.0;
+        for frame in chunk.chunks(channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                let [shelf, high_pass] = &mut filters[channel];
+                let weighted = high_pass.process(shelf.process(*sample as f64));
+                sum += channel_weight(channel, channels) * weighted * weighted;
+            }
+        }
+        steps.push(sum);
+    }
+
+    steps
+        .windows(BLOCK_STEPS)
+        .map(|window| window.iter().sum::<f64>() / (step * BLOCK_STEPS) as f64)
+        .collect()
+}
+
+fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Gated integrated loudness per EBU R128.
+fn integrated_loudness(blocks: &[f64]) -> Option<f32> {
+    let gated_mean = |threshold: f64| {
+        let kept: Vec<f64> = blocks
+            .iter()
+            .copied()
+            .filter(|power| *power > 0.0 && lufs(*power) > threshold)
+            .collect();
+        (!kept.is_empty()).then(|| kept.iter().sum::<f64>() / kept.len() as f64)
+    };
+    let absolute = gated_mean(-70.0)?;
+    let relative = gated_mean(lufs(absolute) - 10.0)?;
+    Some(lufs(relative) as f32)
+}
+
+/// Points where the loudness of the following `SECTION_WINDOW` seconds differs
+/// most from the preceding ones, moved onto a nearby downbeat.
+fn sections(blocks: &[f64], downbeats: &[f64]) -> Vec<f64> {
+    let window = (SECTION_WINDOW * 10.0) as usize;
+    if blocks.len() < window * 2 {
+        return Vec::new();
+    }
+    let levels: Vec<f32> = blocks
+        .iter()
+        .map(|power| lufs(*power).max(-70.0) as f32)
+        .collect();
+    let mean = |range: &[f32]| range.iter().sum::<f32>() / range.len() as f32;
+    let novelty: Vec<f32> = (window..=levels.len() - window)
+        .map(|index| (mean(&levels[index..index + window]) - mean(&levels[index - window..index])).abs())
+        .collect();
+
+    let mut sections: Vec<f64> = Vec::new();
+    for (offset, value) in novelty.iter().enumerate() {
+        if *value < SECTION_JUMP_DB {
+            continue;
+        }
+        let neighbours = &novelty[offset.saturating_sub(window)..(offset + window + 1).min(novelty.len())];
+        if neighbours.iter().any(|other| other > value) {
+            continue;
+        }
+        // Blocks start every 100 ms; the window's split point is where the change happens.
+        let mut time = (offset + window) as f64 / 10.0;
+        if let Some(downbeat) = downbeats
+            .iter()
+            .copied()
+            .min_by(|a, b| (a - time).abs().total_cmp(&(b - time).abs()))
+            .filter(|downbeat| (downbeat - time).abs() <= SECTION_SNAP)
+        {
+            time = downbeat;
+        }
+        if sections.last().is_none_or(|last| time - last >= SECTION_WINDOW) {
+            sections.push(time);
+        }
+    }
+    sections
+}
+
+/// Peak level of the signal reconstructed at 4x the sample rate, which
+/// catches peaks that fall between samples.
+fn true_peak(audio: &DecodedAudio) -> Option<f32> {
+    let channels = audio.channels.max(1);
+    let half = INTERPOLATION_TAPS / 2;
+    // Hann-windowed sinc for each fractional phase.
+    let phases: Vec<Vec<f32>> = (1..OVERSAMPLING)
+        .map(|phase| {
+            let fraction = phase as f64 / OVERSAMPLING as f64;
+            (0..INTERPOLATION_TAPS)
+                .map(|tap| {
+                    let x = tap as f64 - (half as f64 - 1.0) - fraction;
+                    let sinc = if x == 0.0 {
+                        1.0
+                    } else {
+                        (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+                    };
+                    let position = (x + half as f64) / INTERPOLATION_TAPS as f64;
+                    let window = 0.5 - 0.5 * (2.0 * std::f64::consts::PI * position).cos();
+                    (sinc * window) as f32
+                })
+                .collect()
+        })
+        .collect();
+
+    let frames = audio.samples.len() / channels;
+    let mut peak = 0.0f32;
+    for channel in 0..channels {
+        let sample = |frame: isize| {
+            if frame < 0 || frame as usize >= frames {
+                0.0
+            } else {
+                audio.samples[frame as usize * channels + channel]
+            }
+        };
+        for frame in 0..frames {
+            peak = peak.max(sample(frame as isize).abs());
+            let first = frame as isize - (half as isize - 1);
+            for taps in &phases {
+                let value: f32 = taps
+                    .iter()
+                    .enumerate()
+                    .map(|(tap, weight)| weight * sample(first + tap as isize))
+                    .sum();
+                peak = peak.max(value.abs());
+            }
+        }
+    }
+    (peak > 0.0).then(|| 20.0 * peak.log10())
+}
+
+fn waveform(audio: &DecodedAudio) -> Vec<[f32; 2]> {
+    let channels = audio.channels.max(1);
+    let frames = audio.samples.len() / channels;
+    if frames == 0 {
+        return Vec::new();
+    }
+    (0..WAVEFORM_POINTS)
+        .map(|point| {
+            let start = point * frames / WAVEFORM_POINTS;
+            let end = ((point + 1) * frames / WAVEFORM_POINTS).max(start + 1).min(frames);
+            audio.samples[start * channels..end * channels]
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+                .fold([0.0f32, 0.0f32], |[min, max], value| [min.min(value), max.max(value)])
+        })
+        .collect()
+}
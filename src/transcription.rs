@@ -0,0 +1,479 @@
+use std::f64::consts::PI;
+use std::ffi::c_void;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use realfft::num_complex::Complex;
+use realfft::RealFftPlanner;
+use serde::{Deserialize, Serialize};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState};
+
+use crate::playback::{decode_file, DecodedAudio};
+
+/// Whisper models take 16 kHz mono.
+const SAMPLE_RATE: u32 = 16_000;
+/// Zero crossings of the resampling filter on either side of a sample.
+const SINC_ZEROS: f64 = 16.0;
+const STFT_SIZE: usize = 2048;
+const STFT_HOP: usize = STFT_SIZE / 4;
+/// Frequencies outside this range are dropped by the vocal isolation.
+const VOCAL_BAND: (f32, f32) = (100.0, 8000.0);
+/// Longest line whisper is asked to produce, in characters.
+const MAX_LINE_CHARS: i32 = 42;
+/// Gaps between lines longer than this, in seconds, get an empty line so the
+/// lyrics clear during instrumental parts.
+const INSTRUMENTAL_GAP: f64 = 5.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct TranscriptionConfig {
+    /// whisper.cpp model file, e.g. `ggml-base.bin`.
+    pub model_path: PathBuf,
+    /// Two-letter language code, or `auto` to detect it.
+    pub language: String,
+    /// Keep only what is panned to the center and in the vocal range before transcribing.
+    pub isolate_vocals: bool,
+    /// CPU threads; 0 uses one per core.
+    pub threads: usize,
+}
+
+impl Default for TranscriptionConfig {
+    fn default() -> Self {
+        Self {
+            model_path: PathBuf::new(),
+            language: String::from("auto"),
+            isolate_vocals: true,
+            threads: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Decoding,
+    IsolatingVocals,
+    Resampling,
+    Transcribing,
+}
+
+impl Stage {
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::Decoding => "Decoding",
+            Stage::IsolatingVocals => "Isolating vocals",
+            Stage::Resampling => "Resampling",
+            Stage::Transcribing => "Transcribing",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub stage: Stage,
+    /// How far through the stage, from 0 to 1.
+    pub fraction: f32,
+}
+
+struct Job {
+    track: PathBuf,
+    cancel: Arc<AtomicBool>,
+    progress: Arc<Mutex<Progress>>,
+    thread: JoinHandle<Result<PathBuf, String>>,
+}
+
+/// Writes an `.lrc` file with word timings next to a track by running
+/// whisper.cpp over it on a background thread, one track at a time.
+pub struct Transcriber {
+    job: Option<Job>,
+    /// Outcome of the last transcription.
+    pub status: String,
+}
+
+impl Transcriber {
+    pub fn new() -> Self {
+        Self {
+            job: None,
+            status: String::new(),
+        }
+    }
+
+    pub fn start(&mut self, track: &Path, config: &TranscriptionConfig) {
+        if self.job.is_some() {
+            return;
+        }
+        if config.model_path.as_os_str().is_empty() {
+            self.status = String::from("Choose a whisper model in the overlay settings first");
+            return;
+        }
+        self.status = String::new();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let progress = Arc::new(Mutex::new(Progress {
+            stage: Stage::Decoding,
+            fraction: 0.0,
+        }));
+
+        let (cancel_clone, progress_clone) = (cancel.clone(), progress.clone());
+        let (track_clone, config) = (track.to_path_buf(), config.clone());
+        let thread = thread::spawn(move || {
+            transcribe(&track_clone, &config, &cancel_clone, &progress_clone)
+        });
+        self.job = Some(Job {
+            track: track.to_path_buf(),
+            cancel,
+            progress,
+            thread,
+        });
+    }
+
+    /// Stops the running transcription at the next chance; nothing is written.
+    pub fn cancel(&self) {
+        if let Some(job) = &self.job {
+            job.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The track being transcribed.
+    pub fn track(&self) -> Option<&Path> {
+        self.job.as_ref().map(|job| job.track.as_path())
+    }
+
+    pub fn progress(&self) -> Option<Progress> {
+        self.job.as_ref().map(|job| *job.progress.lock().unwrap())
+    }
+
+    /// The track whose lyrics were just written, once its transcription is done.
+    pub fn update(&mut self) -> Option<PathBuf> {
+        if !self.job.as_ref()?.thread.is_finished() {
+            return None;
+        }
+        let job = self.job.take().unwrap();
+        let cancelled = job.cancel.load(Ordering::Relaxed);
+        match job.thread.join().unwrap() {
+            _ if cancelled => self.status = String::from("Transcription cancelled"),
+            Ok(lrc) => {
+                self.status = format!("Wrote {}", lrc.display());
+                return Some(job.track);
+            }
+            Err(err) => {
+                log::warn!("Could not transcribe {}: {}", job.track.display(), err);
+                self.status = err;
+            }
+        }
+        None
+    }
+}
+
+fn set_progress(progress: &Mutex<Progress>, stage: Stage, fraction: f32) {
+    *progress.lock().unwrap() = Progress { stage, fraction };
+}
+
+/// Transcribes `track` and writes the lyrics beside it, returning the `.lrc` path.
+fn transcribe(
+    track: &Path,
+    config: &TranscriptionConfig,
+    cancel: &Arc<AtomicBool>,
+    progress: &Arc<Mutex<Progress>>,
+) -> Result<PathBuf, String> {
+    let audio = decode_file(track)
+        .map_err(|err| format!("Could not decode {}: {}", track.display(), err))?;
+    if cancel.load(Ordering::Relaxed) {
+        return Err(String::from("Cancelled"));
+    }
+
+    let mono = if config.isolate_vocals {
+        set_progress(progress, Stage::IsolatingVocals, 0.0);
+        isolate_vocals(&audio, cancel, |fraction| {
+            set_progress(progress, Stage::IsolatingVocals, fraction)
+        })
+    } else {
+        downmix(&audio)
+    };
+    set_progress(progress, Stage::Resampling, 0.0);
+    let samples = resample(&mono, audio.sample_rate, cancel, |fraction| {
+        set_progress(progress, Stage::Resampling, fraction)
+    });
+    if cancel.load(Ordering::Relaxed) {
+        return Err(String::from("Cancelled"));
+    }
+
+    set_progress(progress, Stage::Transcribing, 0.0);
+    let mut context_params = WhisperContextParameters::default();
+    context_params.use_gpu(false);
+    let context =
+        WhisperContext::new_with_params(&config.model_path.to_string_lossy(), context_params)
+            .map_err(|err| format!("Could not load {}: {}", config.model_path.display(), err))?;
+    let mut state = context.create_state().map_err(|err| err.to_string())?;
+
+    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(4, |threads| threads.get()),
+        threads => threads,
+    };
+    params.set_n_threads(threads as i32);
+    let language = config.language.trim();
+    params.set_language(Some(if language.is_empty() { "auto" } else { language }));
+    params.set_token_timestamps(true);
+    params.set_max_len(MAX_LINE_CHARS);
+    params.set_split_on_word(true);
+    params.set_suppress_nst(true);
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_print_timestamps(false);
+    let progress_clone = progress.clone();
+    params.set_progress_callback_safe(move |percent: i32| {
+        set_progress(&progress_clone, Stage::Transcribing, percent as f32 / 100.0)
+    });
+    // The safe abort callback of whisper-rs casts its data to the wrong type,
+    // so the flag is handed over directly.
+    // SAFETY: `cancel` outlives `state.full`, the only place the callback runs.
+    unsafe {
+        params.set_abort_callback(Some(is_cancelled));
+        params.set_abort_callback_user_data(Arc::as_ptr(cancel) as *mut c_void);
+    }
+    state
+        .full(params, &samples)
+        .map_err(|err| format!("whisper failed: {}", err))?;
+
+    let lines = lyric_lines(&context, &state)?;
+    if lines.is_empty() {
+        return Err(String::from("No vocals were recognized"));
+    }
+    write_lrc(track, &lines)
+}
+
+/// whisper.cpp polls this during inference; `data` points at the job's cancel flag.
+unsafe extern "C" fn is_cancelled(data: *mut c_void) -> bool {
+    (*(data as *const AtomicBool)).load(Ordering::Relaxed)
+}
+
+/// The average of all channels.
+fn downmix(audio: &DecodedAudio) -> Vec<f32> {
+    audio
+        .samples
+        .chunks(audio.channels)
+        .map(|frame| frame.iter().sum::<f32>() / audio.channels as f32)
+        .collect()
+}
+
+/// Mono mix of what is panned to the center and in the vocal range, at the
+/// track's sample rate. Each frequency bin is weighted by how alike the left
+/// and right channels are there, which removes most of the hard-panned and
+/// wide stereo instruments while keeping the lead vocal.
+fn isolate_vocals(
+    audio: &DecodedAudio,
+    cancel: &AtomicBool,
+    mut report: impl FnMut(f32),
+) -> Vec<f32> {
+    let channels = audio.channels.max(1);
+    let frames = audio.samples.len() / channels;
+    let channel = |index: usize| -> Vec<f32> {
+        audio
+            .samples
+            .chunks(channels)
+            .map(|frame| frame[index.min(frame.len() - 1)])
+            .collect()
+    };
+    let (left, right) = (channel(0), channel(1));
+
+    let mut planner = RealFftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(STFT_SIZE);
+    let inverse = planner.plan_fft_inverse(STFT_SIZE);
+    let window: Vec<f32> = (0..STFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / STFT_SIZE as f32).cos())
+        .collect();
+    // Hann windows at a quarter overlap add up to 1.5, squared; the inverse FFT scales by the size.
+    let scale = 1.0 / (1.5 * STFT_SIZE as f32);
+    let bin_hz = audio.sample_rate as f32 / STFT_SIZE as f32;
+
+    let mut output = vec![0.0; frames + STFT_SIZE];
+    let mut left_in = forward.make_input_vec();
+    let mut right_in = forward.make_input_vec();
+    let mut left_spectrum = forward.make_output_vec();
+    let mut right_spectrum = forward.make_output_vec();
+    let mut mid_out = inverse.make_output_vec();
+
+    let mut start = 0;
+    while start < frames {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        for i in 0..STFT_SIZE {
+            let sample = |samples: &[f32]| samples.get(start + i).copied().unwrap_or(0.0);
+            left_in[i] = sample(&left) * window[i];
+            right_in[i] = sample(&right) * window[i];
+        }
+        forward.process(&mut left_in, &mut left_spectrum).unwrap();
+        forward.process(&mut right_in, &mut right_spectrum).unwrap();
+
+        let last = left_spectrum.len() - 1;
+        for (bin, (l, r)) in left_spectrum.iter_mut().zip(&right_spectrum).enumerate() {
+            let hz = bin as f32 * bin_hz;
+            if bin == 0 || bin == last || hz < VOCAL_BAND.0 || hz > VOCAL_BAND.1 {
+                *l = Complex::new(0.0, 0.0);
+                continue;
+            }
+            let energy = l.norm_sqr() + r.norm_sqr();
+            // 1 where both channels carry the same signal, 0 where they differ
+            // in level or phase.
+            let similarity = if energy > 0.0 {
+                (2.0 * (*l * r.conj()).re / energy).max(0.0)
+            } else {
+                0.0
+            };
+            *l = (*l + r) * 0.5 * similarity * similarity;
+        }
+        inverse.process(&mut left_spectrum, &mut mid_out).unwrap();
+        for i in 0..STFT_SIZE {
+            output[start + i] += mid_out[i] * window[i] * scale;
+        }
+
+        start += STFT_HOP;
+        report(start.min(frames) as f32 / frames as f32);
+    }
+    output.truncate(frames);
+    output
+}
+
+/// Resamples to `SAMPLE_RATE` with a windowed sinc, which also removes what
+/// is above the new Nyquist frequency. Stops early once `cancel` is set.
+fn resample(
+    samples: &[f32],
+    rate: u32,
+    cancel: &AtomicBool,
+    mut report: impl FnMut(f32),
+) -> Vec<f32> {
+    if rate == SAMPLE_RATE || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let step = rate as f64 / SAMPLE_RATE as f64;
+    // Cutoff as a fraction of the input's Nyquist frequency.
+    let cutoff = (1.0 / step).min(1.0);
+    let half_width = (SINC_ZEROS / cutoff).ceil();
+    let sinc = |x: f64| if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+
+    let length = (samples.len() as f64 / step) as usize;
+    let mut output = Vec::with_capacity(length);
+    for index in 0..length {
+        // Once per second of output.
+        if index % SAMPLE_RATE as usize == 0 {
+            if cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            report(index as f32 / length as f32);
+        }
+        let time = index as f64 * step;
+        let first = (time - half_width).ceil().max(0.0) as usize;
+        let last = ((time + half_width).floor() as usize).min(samples.len() - 1);
+        let sum: f64 = (first..=last)
+            .map(|source| {
+                let distance = time - source as f64;
+                let window = 0.5 + 0.5 * (PI * distance / half_width).cos();
+                samples[source] as f64 * cutoff * sinc(cutoff * distance) * window
+            })
+            .sum();
+        output.push(sum as f32);
+    }
+    report(1.0);
+    output
+}
+
+struct TimedWord {
+    start: f64,
+    text: String,
+}
+
+struct TimedLine {
+    start: f64,
+    end: f64,
+    words: Vec<TimedWord>,
+}
+
+/// One line per whisper segment, with the words put back together from their
+/// tokens. Sound descriptions like `[Music]` are left out.
+fn lyric_lines(context: &WhisperContext, state: &WhisperState) -> Result<Vec<TimedLine>, String> {
+    let error = |err: whisper_rs::WhisperError| err.to_string();
+    let mut lines = Vec::new();
+    for segment in 0..state.full_n_segments().map_err(error)? {
+        // Token bytes can split a character, so words are decoded once whole.
+        let mut words: Vec<(f64, Vec<u8>)> = Vec::new();
+        for token in 0..state.full_n_tokens(segment).map_err(error)? {
+            // Timestamps and other special tokens come after end-of-text.
+            if state.full_get_token_id(segment, token).map_err(error)? >= context.token_eot() {
+                continue;
+            }
+            let bytes = state.full_get_token_bytes(segment, token).map_err(error)?;
+            let data = state.full_get_token_data(segment, token).map_err(error)?;
+            match words.last_mut() {
+                Some((_, word)) if !bytes.starts_with(b" ") => word.extend(bytes),
+                _ => words.push((data.t0 as f64 / 100.0, bytes)),
+            }
+        }
+        let words: Vec<TimedWord> = words
+            .into_iter()
+            .map(|(start, bytes)| TimedWord {
+                start,
+                text: String::from_utf8_lossy(&bytes).trim().to_string(),
+            })
+            .filter(|word| !word.text.is_empty())
+            .collect();
+        let text: Vec<&str> = words.iter().map(|word| word.text.as_str()).collect();
+        if words.is_empty() || is_annotation(&text.join(" ")) {
+            continue;
+        }
+        lines.push(TimedLine {
+            start: state.full_get_segment_t0(segment).map_err(error)? as f64 / 100.0,
+            end: state.full_get_segment_t1(segment).map_err(error)? as f64 / 100.0,
+            words,
+        });
+    }
+    Ok(lines)
+}
+
+/// Text whisper writes for sounds rather than words, like `[Music]`, `(applause)` or `♪`.
+fn is_annotation(text: &str) -> bool {
+    let text = text.trim();
+    (text.starts_with('[') && text.ends_with(']'))
+        || (text.starts_with('(') && text.ends_with(')'))
+        || !text.chars().any(char::is_alphanumeric)
+}
+
+/// `mm:ss.xx`, as LRC tags have it.
+fn lrc_timestamp(seconds: f64) -> String {
+    let centiseconds = (seconds.max(0.0) * 100.0).round() as u64;
+    format!(
+        "{:02}:{:02}.{:02}",
+        centiseconds / 6000,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+/// Writes `lines` as enhanced LRC next to `track`. An `.lrc` already there is
+/// kept as `.lrc.bak`.
+fn write_lrc(track: &Path, lines: &[TimedLine]) -> Result<PathBuf, String> {
+    let mut lrc = String::from("[re:whisper.cpp]\n");
+    for (index, line) in lines.iter().enumerate() {
+        let words: Vec<String> = line
+            .words
+            .iter()
+            .map(|word| format!("<{}>{}", lrc_timestamp(word.start), word.text))
+            .collect();
+        lrc.push_str(&format!("[{}]{}\n", lrc_timestamp(line.start), words.join(" ")));
+
+        let next = lines.get(index + 1).map(|next| next.start);
+        if next.is_none_or(|next| next - line.end > INSTRUMENTAL_GAP) {
+            lrc.push_str(&format!("[{}]\n", lrc_timestamp(line.end)));
+        }
+    }
+
+    let path = track.with_extension("lrc");
+    if path.exists() {
+        fs::rename(&path, track.with_extension("lrc.bak"))
+            .map_err(|err| format!("Could not back up {}: {}", path.display(), err))?;
+    }
+    fs::write(&path, lrc).map_err(|err| format!("Could not write {}: {}", path.display(), err))?;
+    Ok(path)
+}
@@ -1,5 +1,19 @@
 use eframe::egui;
+use std::path::Path;
+
+use crate::config;
+use crate::latency_calibration;
+use crate::live_input::AudioSource;
 use crate::main_app::MusicVisualizerApp;
+use crate::preset_director::Intensity;
+use crate::preset_rotation::{PresetSource, RotationConfig, SwitchMode};
+use crate::recorder::RenderMode;
+use crate::settings_window;
+
+/// Seconds the beat indicator takes to fade after a beat.
+const BEAT_FLASH: f32 = 0.15;
+/// Milliseconds the lyric offset buttons move the lyrics by.
+const LYRICS_OFFSET_STEP: f32 = 100.0;
 
 pub fn draw_ui(ctx: &egui::Context, app: &mut MusicVisualizerApp) {
     egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
@@ -17,6 +31,22 @@ pub fn draw_ui(ctx: &egui::Context, app: &mut MusicVisualizerApp) {
                     ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                 }
             });
+            ui.menu_button("Options", |ui| {
+                if ui.button("Advanced Settings...").clicked() {
+                    app.settings.open(&app.config);
+                    ui.close_menu();
+                }
+                if ui.button("Latency Calibration...").clicked() {
+                    let offset = app
+                        .config
+                        .av_offsets
+                        .get(&app.playback.latency_key())
+                        .copied()
+                        .unwrap_or(0.0);
+                    app.calibration.open(offset);
+                    ui.close_menu();
+                }
+            });
         });
     });
 
@@ -25,17 +55,412 @@ pub fn draw_ui(ctx: &egui::Context, app: &mut MusicVisualizerApp) {
         .show(ctx, |ui| {
             ui.heading("Controls");
             ui.separator();
+            source_ui(ui, app);
             app.playback.ui(ui);
+            tempo_ui(ui, app);
+            volume_ui(ui, app);
             ui.separator();
             if ui.button("Previous Preset").clicked() {
-                app.playlist.play_prev();
+                app.previous_preset();
             }
             if ui.button("Next Preset").clicked() {
-                app.playlist.play_next();
+                app.next_preset();
             }
+            current_preset_ui(ui, app);
+            ui.separator();
+            overlay_ui(ui, app);
+            ui.separator();
+            recording_ui(ui, app);
         });
 
+    settings_window::show(ctx, app);
+    latency_calibration::show(ctx, app);
+
     egui::CentralPanel::default().show(ctx, |ui| {
-        app.visualizer.ui(ui, &mut app.painter);
+        let media_time = app.playback.position_secs();
+        app.visualizer.ui(
+            ui,
+            &mut app.painter,
+            &app.recorder,
+            &app.overlay,
+            media_time,
+        );
+    });
+}
+
+/// Files or live input, and which input device.
+fn source_ui(ui: &mut egui::Ui, app: &mut MusicVisualizerApp) {
+    let input = &mut app.config.input;
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        changed |= ui
+            .radio_value(&mut input.source, AudioSource::Files, "Files")
+            .changed();
+        changed |= ui
+            .radio_value(&mut input.source, AudioSource::Input, "Live input")
+            .changed();
+    });
+
+    let mut refresh = false;
+    if input.source == AudioSource::Input {
+        let devices = app.playback.input_devices();
+        let selected = devices
+            .iter()
+            .find(|device| device.name == input.device)
+            .map(|device| device.label.clone())
+            .unwrap_or_else(|| {
+                if input.device.is_empty() {
+                    String::from("Default input")
+                } else {
+                    input.device.clone()
+                }
+            });
+        let combo = egui::ComboBox::from_label("Device")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for device in devices {
+                    changed |= ui
+                        .selectable_value(&mut input.device, device.name.clone(), &device.label)
+                        .changed();
+                }
+            });
+        refresh = combo.response.clicked() || devices.is_empty();
+    }
+    if refresh {
+        app.playback.refresh_input_devices();
+    }
+
+    if changed {
+        app.apply_config();
+        config::save_config(&app.config);
+    }
+}
+
+/// Beat indicator and the tap-tempo controls.
+fn tempo_ui(ui: &mut egui::Ui, app: &mut MusicVisualizerApp) {
+    ui.horizontal(|ui| {
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(14.0, 14.0), egui::Sense::hover());
+        let visuals = ui.visuals();
+        let (color, fade) = match app.last_beat {
+            Some((at, downbeat)) => {
+                let color = if downbeat {
+                    visuals.selection.bg_fill
+                } else {
+                    visuals.strong_text_color()
+                };
+                (color, 1.0 - (at.elapsed().as_secs_f32() / BEAT_FLASH).min(1.0))
+            }
+            None => (visuals.weak_text_color(), 0.0),
+        };
+        ui.painter().circle(
+            rect.center(),
+            6.0,
+            color.gamma_multiply(0.15 + 0.85 * fade),
+            egui::Stroke::new(1.0, visuals.weak_text_color()),
+        );
+
+        if ui.button("Tap").clicked() {
+            app.tap_beat();
+        }
+        let tapped = app.tap_tempo.is_active();
+        ui.add_enabled_ui(tapped, |ui| {
+            if ui.small_button("◀").on_hover_text("Nudge beat earlier").clicked() {
+                app.tap_tempo.nudge(-1);
+            }
+            if ui.small_button("▶").on_hover_text("Nudge beat later").clicked() {
+                app.tap_tempo.nudge(1);
+            }
+            if ui.small_button("½×").clicked() {
+                app.tap_tempo.halve();
+            }
+            if ui.small_button("2×").clicked() {
+                app.tap_tempo.double();
+            }
+            if ui.small_button("Auto").on_hover_text("Detect the tempo again").clicked() {
+                app.tap_tempo.clear();
+            }
+        });
+        if let Some(bpm) = app.tap_tempo.bpm() {
+            ui.label(format!("{:.1} BPM (tapped)", bpm));
+        }
+    });
+}
+
+fn volume_ui(ui: &mut egui::Ui, app: &mut MusicVisualizerApp) {
+    ui.horizontal(|ui| {
+        let muted = app.playback.is_muted();
+        if ui
+            .selectable_label(muted, if muted { "Muted" } else { "Mute" })
+            .clicked()
+        {
+            app.playback.set_muted(!muted);
+        }
+        let volume = egui::Slider::new(&mut app.config.output.volume, 0.0..=1.0)
+            .custom_formatter(|value, _| format!("{:.0}%", value * 100.0))
+            .text("Volume");
+        if ui.add(volume).changed() {
+            app.playback.set_output(&app.config.output);
+            app.save_config_later();
+        }
+    });
+}
+
+/// Name, favorite star and intensity tag of the preset that is showing.
+fn current_preset_ui(ui: &mut egui::Ui, app: &mut MusicVisualizerApp) {
+    let Some(preset) = app.current_preset.get() else {
+        return;
+    };
+    let name = Path::new(&preset)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| preset.clone());
+    ui.label(format!("Preset: {}", name)).on_hover_text(&preset);
+
+    let mut favorite = app.favorites.contains(&preset);
+    if ui.checkbox(&mut favorite, "Favorite").changed() {
+        if favorite {
+            app.favorites.add(&preset);
+        } else {
+            app.favorites.remove(&preset);
+        }
+    }
+
+    let mut current = Intensity::ALL
+        .into_iter()
+        .find(|intensity| app.preset_tags.has_tag(&preset, intensity.tag()));
+    ui.horizontal(|ui| {
+        ui.label("Intensity");
+        let mut changed = ui.radio_value(&mut current, None, "None").changed();
+        for intensity in Intensity::ALL {
+            changed |= ui
+                .radio_value(&mut current, Some(intensity), intensity.label())
+                .changed();
+        }
+        if changed {
+            for intensity in Intensity::ALL {
+                if current == Some(intensity) {
+                    app.preset_tags.add(&preset, intensity.tag());
+                } else {
+                    app.preset_tags.remove(&preset, intensity.tag());
+                }
+            }
+        }
+    });
+}
+
+fn overlay_ui(ui: &mut egui::Ui, app: &mut MusicVisualizerApp) {
+    ui.heading("Text Overlay");
+
+    let overlay_config = &mut app.config.overlay;
+    let mut changed = ui.checkbox(&mut overlay_config.enabled, "Show overlay").changed();
+    ui.horizontal(|ui| {
+        changed |= ui.checkbox(&mut overlay_config.title.enabled, "Title").changed();
+        changed |= ui.checkbox(&mut overlay_config.artist.enabled, "Artist").changed();
+        changed |= ui.checkbox(&mut overlay_config.url.enabled, "URL").changed();
+    });
+    ui.horizontal(|ui| {
+        ui.label("Artist fallback");
+        changed |= ui
+            .text_edit_singleline(&mut overlay_config.artist.custom_text)
+            .changed();
+    });
+    ui.horizontal(|ui| {
+        ui.label("URL");
+        changed |= ui
+            .text_edit_singleline(&mut overlay_config.url.custom_text)
+            .changed();
+    });
+    changed |= ui
+        .checkbox(&mut overlay_config.title_cleanup.enabled, "Clean up file names")
+        .changed();
+    changed |= ui
+        .checkbox(&mut overlay_config.lyrics.enabled, "Show synced lyrics")
+        .changed();
+
+    if changed {
+        app.apply_config();
+        app.save_config_later();
+    }
+    lyrics_ui(ui, app);
+    #[cfg(feature = "transcription")]
+    transcription_ui(ui, app);
+}
+
+/// Where the current track's lyrics came from and how far they are shifted.
+fn lyrics_ui(ui: &mut egui::Ui, app: &mut MusicVisualizerApp) {
+    let source = app
+        .overlay
+        .lock()
+        .unwrap()
+        .lyrics()
+        .map(|lyrics| (lyrics.source.label(), lyrics.lines.len()));
+    let Some((source, lines)) = source else {
+        if app.playback.current_track().is_some() {
+            ui.weak("No synced lyrics for this track");
+        }
+        return;
+    };
+    ui.label(format!("Lyrics: {} ({} lines)", source, lines));
+    let mut offset = app.lyrics_offset();
+    let before = offset;
+    ui.horizontal(|ui| {
+        ui.label("Offset");
+        if ui.small_button("-100").clicked() {
+            offset -= LYRICS_OFFSET_STEP;
+        }
+        ui.add(egui::DragValue::new(&mut offset).speed(10.0).suffix(" ms"));
+        if ui.small_button("+100").clicked() {
+            offset += LYRICS_OFFSET_STEP;
+        }
+        if ui.small_button("Reset").clicked() {
+            offset = 0.0;
+        }
+    })
+    .response
+    .on_hover_text("Positive values show the lyrics later");
+    if offset != before {
+        app.set_lyrics_offset(offset);
+    }
+}
+
+/// Transcribes the current track's lyrics and shows how far along it is.
+#[cfg(feature = "transcription")]
+fn transcription_ui(ui: &mut egui::Ui, app: &mut MusicVisualizerApp) {
+    if let Some(progress) = app.transcriber.progress() {
+        let name = app
+            .transcriber
+            .track()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        ui.label(format!("Transcribing {}", name));
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::ProgressBar::new(progress.fraction)
+                    .desired_width(160.0)
+                    .text(format!("{} {:.0}%", progress.stage.label(), progress.fraction * 100.0)),
+            );
+            if ui.button("Cancel").clicked() {
+                app.transcriber.cancel();
+            }
+        });
+    } else if let Some(track) = app.playback.current_track().cloned() {
+        if ui
+            .button("Transcribe Lyrics")
+            .on_hover_text("Writes an .lrc file next to the track with whisper")
+            .clicked()
+        {
+            app.transcriber.start(&track, &app.config.transcription);
+        }
+    }
+    if !app.transcriber.status.is_empty() {
+        ui.label(&app.transcriber.status);
+    }
+}
+
+fn recording_ui(ui: &mut egui::Ui, app: &mut MusicVisualizerApp) {
+    ui.heading("Recording");
+
+    let recording = app.recorder.lock().unwrap().is_recording();
+
+    ui.add_enabled_ui(!recording, |ui| {
+        let mut changed = false;
+        let recording_config = &mut app.config.recording;
+        for profile in &recording_config.profiles {
+            let mut active = recording_config.active_profiles.contains(&profile.name);
+            let label = format!(
+                "{} ({}x{} @ {} fps)",
+                profile.name, profile.width, profile.height, profile.fps
+            );
+            if ui.checkbox(&mut active, label).changed() {
+                if active {
+                    recording_config.active_profiles.push(profile.name.clone());
+                } else {
+                    recording_config.active_profiles.retain(|name| name != &profile.name);
+                }
+                changed = true;
+            }
+        }
+        let tags: Vec<String> = app.preset_tags.tags().cloned().collect();
+        changed |= rotation_ui(ui, &mut app.config.recording.rotation, &tags);
+        if changed {
+            app.save_config_later();
+        }
+    });
+
+    if recording {
+        if ui.button("Stop Recording").clicked() {
+            app.stop_recording();
+        }
+    } else {
+        ui.horizontal(|ui| {
+            if ui.button("Record").clicked() {
+                app.start_recording(RenderMode::Live);
+            }
+            if ui.button("Render Offline").clicked() {
+                app.start_recording(RenderMode::Offline);
+            }
+        });
+        if ui.button("Replay Preset Schedule...").clicked() {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("Preset schedule", &["json"])
+                .pick_file()
+            {
+                app.replay_schedule(&path);
+            }
+        }
+    }
+
+    let status = app.recorder.lock().unwrap().status.clone();
+    if !status.is_empty() {
+        ui.label(status);
+    }
+}
+
+/// Preset source and switching settings for renders. Returns true if anything changed.
+pub fn rotation_ui(ui: &mut egui::Ui, rotation: &mut RotationConfig, tags: &[String]) -> bool {
+    let mut changed = false;
+
+    let source_label = match &rotation.source {
+        PresetSource::All => String::from("All presets"),
+        PresetSource::Favorites => String::from("Favorites"),
+        PresetSource::Tag(tag) => format!("Tag: {}", tag),
+    };
+    egui::ComboBox::from_label("Presets")
+        .selected_text(source_label)
+        .show_ui(ui, |ui| {
+            changed |= ui
+                .selectable_value(&mut rotation.source, PresetSource::Favorites, "Favorites")
+                .changed();
+            changed |= ui
+                .selectable_value(&mut rotation.source, PresetSource::All, "All presets")
+                .changed();
+            for tag in tags {
+                let label = format!("Tag: {}", tag);
+                changed |= ui
+                    .selectable_value(&mut rotation.source, PresetSource::Tag(tag.clone()), label)
+                    .changed();
+            }
+        });
+
+    ui.horizontal(|ui| {
+        changed |= ui
+            .radio_value(&mut rotation.switch_mode, SwitchMode::Interval, "Every")
+            .changed();
+        changed |= ui
+            .radio_value(&mut rotation.switch_mode, SwitchMode::Beat, "On beat after")
+            .changed();
+        changed |= ui
+            .add(
+                egui::DragValue::new(&mut rotation.interval)
+                    .clamp_range(1.0..=600.0)
+                    .suffix(" s"),
+            )
+            .changed();
     });
+    changed |= ui
+        .checkbox(&mut rotation.smooth_transitions, "Smooth transitions")
+        .changed();
+
+    changed
 }